@@ -50,6 +50,251 @@ documents = Documents
 title = Title
 edit-document = Edit Document
 
+## Approvals
+approvals = Approvals
+approval-status = Approval status:
+approval-complete = Complete
+approval-incomplete = Awaiting approval
+approver = Approver
+status = Status
+remove = Remove
+add-approver = Add Approver
+your-decision = Your decision
+decision-approved = Approve
+decision-returned = Return for changes
+decision-rejected = Reject
+comment-placeholder = Add a comment for the record...
+submit-decision = Submit Decision
+approval-history = Approval History
+no-decisions = No decisions recorded yet.
+
+## Document Lifecycle
+state-draft = Draft
+state-in_review = In Review
+state-approved = Approved
+state-published = Published
+state-withdrawn = Withdrawn
+state-archived = Archived
+transition-to-draft = Return to Draft
+transition-to-in_review = Submit for Review
+transition-to-approved = Mark Approved
+transition-to-published = Publish
+transition-to-withdrawn = Withdraw
+transition-to-archived = Archive
+state-history = State History
+filter-by-state = Filter by state:
+all-states = All
+
+## Word Import
+import-docx = Import Word Document
+import-docx-link = Or import an existing Word (.docx) document
+import-docx-help = Upload a Word (.docx) file. Headings in the file are matched to the sections of this template. Title and purpose are optional and are taken from the file if left blank.
+import-docx-sections = Headings in your file should match the following sections:
+docx-file = Word file (.docx)
+import-title-placeholder = Leave blank to use the title of the file
+import-purpose-placeholder = Leave blank to use the text before the first heading
+import-report = Import Report
+matched-headings = Matched headings
+unmatched-headings = Unmatched headings
+unmatched-headings-help = Content under these headings was added to the previous matched section.
+empty-sections = Empty sections
+empty-sections-help = No content was found for these sections. They contain the template help text.
+review-imported-document = Review and Edit Sections
+view-document = View Document
+none = None
+download-docx = Download Word (.docx)
+
+## Search
+search = Search
+search-placeholder = Search titles, purposes and content...
+search-result-count = { $count -> 
+    [0] No documents found.
+    [one] 1 document found.
+    *[other] { $count } documents found.
+}
+matched-in = Matched in:
+search-field-title = Title
+search-field-purpose = Purpose
+search-field-section = Content
+from-date = From
+to-date = To
+clear-search = Clear search
+save-search = Link to this search:
+facet-template_id = Template
+facet-security_classification = Security Classification
+facet-state = Status
+facet-subject_id = Subject
+facet-category_id = Category
+facet-keyword_id = Keywords
+facet-author_id = Author
+
+## Taxonomy
+taxonomy = Subjects, Categories and Keywords
+taxonomy-subjects = Subjects
+taxonomy-categories = Categories
+taxonomy-keywords = Keywords
+taxonomy-name = Name
+taxonomy-description = Description
+taxonomy-name-en = English name
+taxonomy-name-fr = French name
+taxonomy-description-en = English description
+taxonomy-description-fr = French description
+taxonomy-documents = Documents with this entry
+add-taxonomy-entry = Add entry
+delete-entry = Delete
+confirm-delete-entry = Delete this entry and remove it from all documents?
+subject = Subject
+category = Category
+keywords = Keywords
+not-assigned = None
+edit-metadata = Edit
+
+## Keyword Suggestions
+keyword-suggestions = Keyword Suggestions
+keyword-suggestions-instructions = These keywords were found in documents but are not in the keywords list yet. Check the English and French names, then accept them to add them to the list and the document, or reject them so they are not suggested again.
+suggested-term = Suggested term
+keyword-score = Score
+accept-keyword = Accept
+reject-keyword = Reject
+no-keyword-suggestions = No keywords are waiting for review.
+suggest-keywords = Suggest keywords
+
+## Text History
+text-history = Revision history
+text-history-instructions = Every saved version of this text, newest first. Compare two revisions to see the words that were added or removed, or restore an earlier revision. Restoring saves it as a new revision, so nothing is lost.
+revision = Revision
+revision-author = Author
+revision-date = Saved at
+revision-machine-translation = Machine translation
+revision-translated = Translated
+current-revision = Current
+revert-revision = Restore
+compare-revisions = Compare
+compare-from = From
+compare-to = To
+diff-no-changes = No changes between these revisions.
+back-to-document = Back to document
+history = History
+section-blame = Paragraph authors
+section-blame-instructions = Each paragraph of the current text with the revision that last changed it.
+paragraph = Paragraph
+last-changed-by = Last changed by
+last-changed-at = Last changed at
+
+## Edit Conflicts
+edit-conflict = Someone else saved this text while you were editing. Their change is shown below and your version is in the editor. Merge their change into your version, then save again.
+document-edit-conflict = Someone else saved this document while you were editing. The saved title and purpose are compared with yours below: struck-out words are only in the saved version and highlighted words are only in yours. Your version is in the form. Merge the changes, then save again.
+saved-version = Saved version
+
+## Section Locks
+section-locked = Being edited by { $user } since { $time }
+break-section-lock = Unlock
+
+## Live Updates
+live-viewing = Also here:
+live-editing = editing
+live-section-updated = A section you are editing was just saved by
+live-state-changed = The status of this document has changed.
+live-reload = Reload
+
+## Background Jobs
+jobs = Background Jobs
+jobs-instructions = Translations, summaries and keyword suggestions run in the background. Failed jobs are retried with a growing delay and marked dead after their last attempt.
+job-kind = Job
+job-target = For
+job-status = Status
+job-attempts = Attempts
+job-run-at = Next Run
+job-last-error = Last Error
+job-status-all = All
+job-status-pending = Pending
+job-status-running = Running
+job-status-done = Done
+job-status-dead = Dead
+job-kind-translate_text = Translation
+job-kind-update_metadata = Summary and Metadata
+job-kind-suggest_keywords = Keyword Suggestions
+retry-job = Retry
+no-jobs = No jobs.
+translation-pending = Translation pending
+translation-failed = Translation failed
+
+## Translation Staleness
+translation-out-of-date = Translation out of date
+document-translation-out-of-date = Some translations are out of date
+translation-queue = Translation Queue
+translation-queue-instructions = Sections whose English or French version has changed since the other was translated. Published documents come first, then the longest out of date.
+document-status = Status
+section = Section
+translate-into = Translate
+source-updated-at = Changed at
+translate = Translate
+no-stale-translations = All translations are up to date.
+mark-translated = This edit translates the current version in the other language
+translated-from-revision = from revision { $version }
+
+## XLIFF
+export-xliff = Export for translation (XLIFF)
+import-xliff = Import translation (XLIFF)
+import-xliff-help = Upload an XLIFF 2.0 file exported from this document and translated in a CAT tool. Each translated unit is saved as a new human translation of the text it came from.
+xliff-file = XLIFF file
+imported-units = Imported
+unchanged-units = Unchanged
+skipped-units = Skipped
+skipped-units-help = Units without a translation, or that don't belong to this document, were not imported.
+
+## Translation Memory
+translation-memory = Translation memory
+exact-match = Exact match
+insert-translation = Insert
+no-translation-suggestions = No translation memory matches.
+
+## Termbase
+termbase = Termbase
+termbase-help = Approved English and French renderings of terms. Sections that don't use them are flagged, and machine translation follows them.
+term-en = English term
+term-fr = French term
+term-deprecated = Avoid
+term-deprecated-en = English renderings to avoid (separated by semicolons)
+term-deprecated-fr = French renderings to avoid (separated by semicolons)
+term-note = Note
+add-term = Add a term
+import-terms = Import terms
+import-terms-help = Upload a TBX file, or a CSV file with en, fr and note columns. Optional en_deprecated and fr_deprecated columns list renderings to avoid, separated by semicolons. Terms already in the termbase are updated.
+termbase-file = CSV or TBX file
+terms-created = Terms added
+terms-updated = Terms updated
+terms-unchanged = Terms unchanged
+terms-rechecked = Sections are being checked against the updated termbase.
+term-warnings = Terminology
+term-warning-deprecated = "{ $found }" should be "{ $approved }" (for "{ $source }")
+term-warning-missing = "{ $source }" should be rendered as "{ $approved }"
+
+## Bilingual Editor
+bilingual-editor = English and French side by side
+bilingual-editor-help = Each section's English and French texts, edited separately. Saving with Machine Translate checked replaces the other language.
+retranslate-from-en = Translate again from English
+retranslate-from-fr = Translate again from French
+confirm-retranslate = Replace this text with a machine translation of the other language?
+
+## Translation QA
+translation-qa = Differences from the other language
+qa-numbers = Numbers
+qa-dates = Dates
+qa-links = Links
+qa-markdown = Formatting
+qa-redactions = Redactions
+qa-missing = missing
+qa-extra = not in the other language
+qa-blocks-publishing = The document can't be published until redactions match in both languages.
+qa-document-blocked = Redactions differ between English and French. Publishing is blocked.
+qa-save-blocked = This document is published, so redactions must stay the same in English and French. This change would leave a redaction in one language only and was not saved. Withdraw the document to change its redactions.
+transition-refused = Transition refused
+transition-refused-redactions = The document can't be moved to { $state }: redactions differ between English and French in { $count } text(s).
+blocked-units = Not imported
+conflicted-units = Edited since export
+conflicted-units-help = These texts were changed in the target language after the file was exported and were not overwritten. Export a new file to translate them.
+
 ## Templates
 create-template = Create Template
 create-template-instructions = A template is the base structure that you can use to create documents in a standard format. Every template has a name, a purpose and a number of template sections.
@@ -141,6 +386,8 @@ validated = Validated
 true = True
 false = False
 validated-helper = Choose to validate or invalidate the user.
+clearance = Security clearance
+clearance-helper = Choose the highest security classification this user can read.
 update-button = Update
 
 ## Delete User
@@ -0,0 +1,32 @@
+-- Approval chain for documents
+
+CREATE TABLE IF NOT EXISTS authorizations (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    document_id UUID NOT NULL,
+    FOREIGN KEY(document_id)
+        REFERENCES documents(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL,
+    FOREIGN KEY(user_id)
+        REFERENCES users(id) ON DELETE CASCADE,
+    order_number INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    -- removed approvers are kept so their decisions stay in the history
+    removed BOOLEAN NOT NULL DEFAULT false
+);
+
+-- an approver is in a chain once. Putting back a removed approver adds a new authorization
+CREATE UNIQUE INDEX authorizations__approver_idx ON authorizations(document_id, user_id) WHERE NOT removed;
+
+CREATE TABLE IF NOT EXISTS decisions (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    document_id UUID NOT NULL,
+    FOREIGN KEY(document_id)
+        REFERENCES documents(id) ON DELETE CASCADE,
+    authorization_id UUID NOT NULL,
+    FOREIGN KEY(authorization_id)
+        REFERENCES authorizations(id) ON DELETE RESTRICT,
+    user_id UUID NOT NULL,
+    decision VARCHAR(32) NOT NULL,
+    comment TEXT NOT NULL DEFAULT '',
+    date_time TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE IF EXISTS document_transitions;
+
+ALTER TABLE documents ADD COLUMN published bool NOT NULL DEFAULT false;
+
+UPDATE documents SET published = true WHERE state = 'published';
+
+DROP INDEX IF EXISTS documents__state_idx;
+
+ALTER TABLE documents DROP COLUMN state;
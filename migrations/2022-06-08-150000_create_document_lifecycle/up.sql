@@ -0,0 +1,20 @@
+-- Replace the published flag with an explicit lifecycle state
+
+ALTER TABLE documents ADD COLUMN state VARCHAR(32) NOT NULL DEFAULT 'draft';
+
+UPDATE documents SET state = 'published' WHERE published = true;
+
+ALTER TABLE documents DROP COLUMN published;
+
+CREATE INDEX documents__state_idx ON documents(state);
+
+CREATE TABLE IF NOT EXISTS document_transitions (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    document_id UUID NOT NULL,
+    FOREIGN KEY(document_id)
+        REFERENCES documents(id) ON DELETE CASCADE,
+    from_state VARCHAR(32) NOT NULL,
+    to_state VARCHAR(32) NOT NULL,
+    user_id UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS search_terms__term_idx;
+DROP TABLE IF EXISTS search_terms;
@@ -0,0 +1,16 @@
+-- Search index for encrypted texts. Terms are stored as keyed hashes of word stems
+-- so the plain text of a document can't be read back from the index.
+CREATE TABLE IF NOT EXISTS search_terms (
+    text_id UUID NOT NULL,
+    lang VARCHAR(2) NOT NULL,
+    term VARCHAR(64) NOT NULL,
+    -- occurrences in the internal view and in the redacted open view
+    internal_count INT NOT NULL DEFAULT 0,
+    open_count INT NOT NULL DEFAULT 0,
+    keyword BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (text_id, lang, term),
+    FOREIGN KEY(text_id, lang)
+        REFERENCES texts(id, lang) ON DELETE CASCADE
+);
+
+CREATE INDEX search_terms__term_idx ON search_terms(term, lang);
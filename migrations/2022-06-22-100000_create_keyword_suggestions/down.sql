@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS keyword_suggestions__status_idx;
+DROP TABLE IF EXISTS keyword_suggestions;
@@ -0,0 +1,22 @@
+-- Keywords found by RAKE in a document, waiting for a curator to accept or reject them
+CREATE TABLE IF NOT EXISTS keyword_suggestions (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    document_id UUID NOT NULL,
+    FOREIGN KEY(document_id)
+        REFERENCES documents(id) ON DELETE CASCADE,
+    keyword_id UUID,
+    FOREIGN KEY(keyword_id)
+        REFERENCES keywords(id) ON DELETE SET NULL,
+    -- lower case term as found in the document
+    term VARCHAR(256) NOT NULL,
+    lang VARCHAR(2) NOT NULL,
+    en_string VARCHAR(256) NOT NULL,
+    fr_string VARCHAR(256) NOT NULL,
+    score FLOAT8 NOT NULL,
+    -- pending, accepted or rejected
+    status VARCHAR(32) NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(document_id, term)
+);
+
+CREATE INDEX keyword_suggestions__status_idx ON keyword_suggestions(status);
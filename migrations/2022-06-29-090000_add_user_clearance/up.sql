@@ -0,0 +1,14 @@
+-- Security clearance for reading classified documents
+
+ALTER TABLE users ADD COLUMN clearance VARCHAR(32) NOT NULL DEFAULT 'unclassified';
+
+-- Admins could read every document before clearances were checked
+UPDATE users SET clearance = 'secret' WHERE role = 'admin';
+
+-- Classifications saved before they were validated, e.g. 'Protected B' or 'protected-b'
+UPDATE documents
+SET security_classification = regexp_replace(lower(trim(security_classification)), '[^a-z]+', '_', 'g');
+
+-- Anything still unknown was already treated as the highest classification
+UPDATE documents SET security_classification = 'secret'
+WHERE security_classification NOT IN ('unclassified', 'protected_a', 'protected_b', 'secret');
@@ -0,0 +1,35 @@
+ALTER TABLE texts
+    ADD COLUMN content TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN translated bool[] NOT NULL DEFAULT '{}',
+    ADD COLUMN machine_translation bool[] NOT NULL DEFAULT '{}',
+    ADD COLUMN created_at TIMESTAMP[] NOT NULL DEFAULT '{}',
+    ADD COLUMN created_by_id UUID[] NOT NULL DEFAULT '{}';
+
+UPDATE texts t SET
+    content = v.content,
+    translated = v.translated,
+    machine_translation = v.machine_translation,
+    created_at = v.created_at,
+    created_by_id = v.created_by_id
+FROM (
+    SELECT
+        text_id,
+        lang,
+        array_agg(content ORDER BY version_number) AS content,
+        array_agg(translated ORDER BY version_number) AS translated,
+        array_agg(machine_translation ORDER BY version_number) AS machine_translation,
+        array_agg(created_at ORDER BY version_number) AS created_at,
+        array_agg(created_by_id ORDER BY version_number) AS created_by_id
+    FROM text_versions
+    GROUP BY text_id, lang
+) v
+WHERE t.id = v.text_id AND t.lang = v.lang;
+
+ALTER TABLE texts
+    ALTER COLUMN content DROP DEFAULT,
+    ALTER COLUMN translated SET DEFAULT '{false}',
+    ALTER COLUMN machine_translation SET DEFAULT '{false}',
+    ALTER COLUMN created_at SET DEFAULT '{NOW()}',
+    ALTER COLUMN created_by_id DROP DEFAULT;
+
+DROP TABLE IF EXISTS text_versions;
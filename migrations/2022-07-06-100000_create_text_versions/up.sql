@@ -0,0 +1,43 @@
+-- One row per revision of a Text instead of parallel arrays on the texts row
+CREATE TABLE IF NOT EXISTS text_versions (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    text_id UUID NOT NULL,
+    lang VARCHAR(2) NOT NULL,
+    FOREIGN KEY(text_id, lang)
+        REFERENCES texts(id, lang) ON DELETE CASCADE,
+    -- 1 for the first revision, the head has the highest number
+    version_number INT NOT NULL,
+    -- ciphertext, see Keyring
+    content TEXT NOT NULL,
+    translated BOOL NOT NULL DEFAULT false,
+    machine_translation BOOL NOT NULL DEFAULT false,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    created_by_id UUID NOT NULL,
+    UNIQUE(text_id, lang, version_number)
+);
+
+-- Copy every array element into its own row. Ciphertexts are copied as they are.
+-- unnest pads arrays that drifted shorter than content with NULLs. Only machine translation
+-- pushed content without the other arrays, so missing flags are machine translations
+-- and missing authors fall back to the last recorded author.
+INSERT INTO text_versions (text_id, lang, version_number, content, translated, machine_translation, created_at, created_by_id)
+SELECT
+    t.id,
+    t.lang,
+    v.version_number::INT,
+    v.content,
+    COALESCE(v.translated, true),
+    COALESCE(v.machine_translation, true),
+    COALESCE(v.created_at, t.created_at[array_upper(t.created_at, 1)], NOW()),
+    COALESCE(v.created_by_id, t.created_by_id[array_upper(t.created_by_id, 1)])
+FROM texts t,
+    unnest(t.content, t.translated, t.machine_translation, t.created_at, t.created_by_id)
+        WITH ORDINALITY AS v(content, translated, machine_translation, created_at, created_by_id, version_number)
+WHERE v.content IS NOT NULL;
+
+ALTER TABLE texts
+    DROP COLUMN content,
+    DROP COLUMN translated,
+    DROP COLUMN machine_translation,
+    DROP COLUMN created_at,
+    DROP COLUMN created_by_id;
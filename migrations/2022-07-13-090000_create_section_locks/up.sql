@@ -0,0 +1,11 @@
+-- Soft locks on sections open for editing. A lock lapses at expires_at unless its holder renews it.
+CREATE TABLE IF NOT EXISTS section_locks (
+    section_id UUID PRIMARY KEY,
+    FOREIGN KEY(section_id)
+        REFERENCES sections(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL,
+    FOREIGN KEY(user_id)
+        REFERENCES users(id) ON DELETE CASCADE,
+    locked_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL
+);
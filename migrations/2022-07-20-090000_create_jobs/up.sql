@@ -0,0 +1,33 @@
+-- Background work that must survive restarts: translations, summaries, keyword suggestions,
+-- translation memory updates and terminology checks
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    -- translate_text, learn_translation, update_metadata, suggest_keywords or check_terminology
+    kind VARCHAR(32) NOT NULL,
+    -- text jobs name the text and the language it is translated from
+    text_id UUID,
+    document_id UUID,
+    FOREIGN KEY(document_id)
+        REFERENCES documents(id) ON DELETE CASCADE,
+    lang VARCHAR(2) NOT NULL,
+    -- translations aren't saved over a revision of the other language newer than this one
+    base_version INT,
+    -- pending, running, done or dead once out of attempts
+    status VARCHAR(32) NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL,
+    run_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX jobs__status_run_at_idx ON jobs(status, run_at);
+CREATE INDEX jobs__text_id_idx ON jobs(text_id);
+-- one waiting job of a kind per text or document and language. Missing ids count as equal.
+CREATE UNIQUE INDEX jobs__pending_idx ON jobs(
+    kind,
+    COALESCE(text_id, '00000000-0000-0000-0000-000000000000'),
+    COALESCE(document_id, '00000000-0000-0000-0000-000000000000'),
+    lang
+) WHERE status = 'pending';
@@ -0,0 +1 @@
+ALTER TABLE text_versions DROP COLUMN IF EXISTS source_version;
@@ -0,0 +1,13 @@
+-- Revision of the other language a translated revision was made from
+ALTER TABLE text_versions ADD COLUMN source_version INT;
+
+-- Existing translations are taken to be of the other language's head when they were saved
+UPDATE text_versions tv
+SET source_version = (
+    SELECT MAX(o.version_number)
+    FROM text_versions o
+    WHERE o.text_id = tv.text_id
+        AND o.lang <> tv.lang
+        AND o.created_at <= tv.created_at
+)
+WHERE tv.translated;
@@ -0,0 +1,25 @@
+-- Translation memory: aligned sentences from human translated texts.
+-- Segments are encrypted with the key for their document's classification,
+-- and matched through keyed hashes of the whole segment and of its word stems.
+CREATE TABLE IF NOT EXISTS translation_pairs (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    -- text and revisions the pair was aligned from
+    text_id UUID NOT NULL,
+    en_version INT NOT NULL,
+    fr_version INT NOT NULL,
+    en_segment TEXT NOT NULL,
+    fr_segment TEXT NOT NULL,
+    en_hash VARCHAR NOT NULL,
+    fr_hash VARCHAR NOT NULL,
+    en_terms VARCHAR[] NOT NULL,
+    fr_terms VARCHAR[] NOT NULL,
+    security_classification VARCHAR(32) NOT NULL DEFAULT 'unclassified',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX translation_pairs__pair_idx ON translation_pairs(en_hash, fr_hash, security_classification);
+CREATE INDEX translation_pairs__en_hash_idx ON translation_pairs(en_hash);
+CREATE INDEX translation_pairs__fr_hash_idx ON translation_pairs(fr_hash);
+CREATE INDEX translation_pairs__en_terms_idx ON translation_pairs USING GIN (en_terms);
+CREATE INDEX translation_pairs__fr_terms_idx ON translation_pairs USING GIN (fr_terms);
+CREATE INDEX translation_pairs__text_id_idx ON translation_pairs(text_id);
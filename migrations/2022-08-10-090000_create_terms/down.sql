@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS term_warnings;
+DROP TABLE IF EXISTS terms;
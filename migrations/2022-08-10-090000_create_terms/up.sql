@@ -0,0 +1,32 @@
+-- Termbase: approved English and French renderings of a term, with renderings to avoid
+CREATE TABLE IF NOT EXISTS terms (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    en_term VARCHAR NOT NULL,
+    fr_term VARCHAR NOT NULL,
+    en_deprecated VARCHAR[] NOT NULL DEFAULT '{}',
+    fr_deprecated VARCHAR[] NOT NULL DEFAULT '{}',
+    note TEXT,
+    created_by_id UUID NOT NULL,
+    FOREIGN KEY(created_by_id)
+        REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX terms__en_fr_idx ON terms(en_term, fr_term);
+
+-- A term in one language of a text that the other language doesn't render as approved
+CREATE TABLE IF NOT EXISTS term_warnings (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    text_id UUID NOT NULL,
+    -- language of the version that needs fixing
+    lang VARCHAR(2) NOT NULL,
+    term_id UUID NOT NULL,
+    FOREIGN KEY(term_id)
+        REFERENCES terms(id) ON DELETE CASCADE,
+    -- deprecated rendering used instead of the approved one, if any
+    found VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX term_warnings__text_id_lang_idx ON term_warnings(text_id, lang);
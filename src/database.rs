@@ -4,7 +4,7 @@ use diesel::r2d2::ConnectionManager;
 use lazy_static::lazy_static;
 use r2d2;
 use std::env;
-use crate::models::{User, UserData};
+use crate::models::{User, UserData, SearchTerm, SYSTEM_CLEARANCE};
 use crate::{construct_demo_template, construct_demo_document};
 
 
@@ -26,6 +26,12 @@ pub fn init() {
     let conn = connection().expect("Failed to get DB connection");
     embedded_migrations::run(&conn).unwrap();
 
+    // Index texts created before the search index existed
+    let indexed = SearchTerm::rebuild_if_empty().expect("Unable to build search index");
+    if indexed > 0 {
+        println!("Search index built for {} texts", indexed);
+    };
+
     // Auto-add admin if does not exist
     let admin_name = env::var("ADMIN_NAME").expect("Unable to load admin name");
     let admin_email = env::var("ADMIN_EMAIL").expect("Unable to load admin email");
@@ -46,8 +52,14 @@ pub fn init() {
                 role: "admin".to_owned(),
             };
         
-            let admin = User::create(admin_data)
+            let mut admin = User::create(admin_data)
                 .expect("Unable to create admin");
+
+            // the admin manages every document, whatever its classification
+            admin.clearance = SYSTEM_CLEARANCE.to_string();
+
+            let admin = admin.update()
+                .expect("Unable to set admin clearance");
         
             println!("Admin created: {:?}", &admin);
 
@@ -0,0 +1,660 @@
+// Conversion between Word .docx files and Data Docs documents
+// docx-rs serializes a parsed file to JSON, which we walk to build markdown for each section.
+// Export walks the markdown of each section with pulldown-cmark to build the .docx.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use docx_rs::{read_docx, Docx, Paragraph, Run, BreakType, Style, StyleType, Table, TableRow, TableCell};
+use pulldown_cmark::{Parser, Options, Event, Tag};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::process_text_redactions;
+use crate::errors::CustomError;
+use crate::models::{Document, InsertableDocument, Section, InsertableSection, InsertableText, Text,
+    Template, ReadableTemplateSection, ReadableDocument, ReadableSection};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A heading from a .docx file and the markdown content that follows it
+pub struct DocxSection {
+    pub heading: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A parsed .docx file. Content before the first heading is kept as the preamble.
+pub struct ParsedDocx {
+    pub title: Option<String>,
+    pub preamble: String,
+    pub sections: Vec<DocxSection>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Outcome of importing a .docx into a template-backed Document
+pub struct DocxImportReport {
+    pub document_id: Uuid,
+    pub matched_headings: Vec<String>,
+    pub unmatched_headings: Vec<String>,
+    pub empty_sections: Vec<String>,
+}
+
+/// Parse the bytes of a .docx file into headings and markdown content
+pub fn parse_docx(buf: &[u8]) -> Result<ParsedDocx, CustomError> {
+
+    let docx = read_docx(buf)
+        .map_err(|e| CustomError::new(400, format!("Unable to read docx file: {:?}", e)))?;
+
+    let json: Value = serde_json::from_str(&docx.json())
+        .map_err(|e| CustomError::new(500, format!("Unable to parse docx structure: {}", e)))?;
+
+    Ok(parse_docx_json(&json))
+}
+
+/// Walk the JSON produced by docx-rs and split the body on heading paragraphs
+pub fn parse_docx_json(json: &Value) -> ParsedDocx {
+
+    let empty = Vec::new();
+    let children = json["document"]["children"].as_array().unwrap_or(&empty);
+
+    let mut title = None;
+    let mut preamble = String::new();
+    let mut sections: Vec<DocxSection> = Vec::new();
+    let mut last_was_list = false;
+
+    for child in children {
+        let data = &child["data"];
+
+        let (block, is_list) = match child["type"].as_str() {
+            Some("paragraph") => {
+                let style = paragraph_style(data).to_lowercase();
+
+                if style == "title" {
+                    let text = paragraph_text(data, false);
+                    if title.is_none() && !text.trim().is_empty() {
+                        title = Some(text.trim().to_string());
+                    };
+                    continue
+                };
+
+                if style.starts_with("heading") {
+                    let text = paragraph_text(data, false);
+                    if !text.trim().is_empty() {
+                        sections.push(DocxSection {
+                            heading: text.trim().to_string(),
+                            content: String::new(),
+                        });
+                        last_was_list = false;
+                        continue
+                    };
+                };
+
+                let text = paragraph_text(data, true);
+
+                if text.trim().is_empty() {
+                    continue
+                };
+
+                // nested items are indented past the widest marker so they stay in their parent item
+                match list_level(data) {
+                    Some(level) => {
+                        let marker = match is_numbered(json, data, level) {
+                            true => "1.",
+                            false => "*",
+                        };
+                        (format!("{}{} {}", "   ".repeat(level), marker, text.trim()), true)
+                    },
+                    None => (text.trim().to_string(), false),
+                }
+            },
+            Some("table") => (table_markdown(data), false),
+            _ => continue,
+        };
+
+        let target = match sections.last_mut() {
+            Some(s) => &mut s.content,
+            None => &mut preamble,
+        };
+
+        if !target.is_empty() {
+            // keep list items together so they render as one list
+            if is_list && last_was_list {
+                target.push('\n');
+            } else {
+                target.push_str("\n\n");
+            }
+        };
+
+        target.push_str(&block);
+        last_was_list = is_list;
+    };
+
+    ParsedDocx {
+        title,
+        preamble,
+        sections,
+    }
+}
+
+/// Match parsed headings to template section headers.
+/// Content under a heading that doesn't match is kept with the previous matched section.
+/// Returns content by template_section_id and the headings that didn't match.
+pub fn match_docx_sections(
+    parsed: &ParsedDocx,
+    template_sections: &BTreeMap<Uuid, ReadableTemplateSection>,
+) -> (BTreeMap<Uuid, String>, Vec<String>, Vec<String>) {
+
+    let mut headers: BTreeMap<String, Uuid> = BTreeMap::new();
+
+    for (id, ts) in template_sections {
+        headers.insert(normalize_heading(&ts.header_text), *id);
+    };
+
+    let mut contents: BTreeMap<Uuid, String> = BTreeMap::new();
+    let mut matched = Vec::new();
+    let mut unmatched = Vec::new();
+    let mut current: Option<Uuid> = None;
+
+    for section in parsed.sections.iter() {
+        let block = match headers.get(&normalize_heading(&section.heading)) {
+            Some(id) => {
+                current = Some(*id);
+                matched.push(section.heading.clone());
+                section.content.clone()
+            },
+            None => {
+                unmatched.push(section.heading.clone());
+                format!("### {}\n\n{}", section.heading, section.content)
+            },
+        };
+
+        if let Some(id) = current {
+            let content = contents.entry(id).or_insert_with(String::new);
+            if !content.is_empty() {
+                content.push_str("\n\n");
+            };
+            content.push_str(block.trim());
+        };
+    };
+
+    (contents, matched, unmatched)
+}
+
+/// Create a Document with Sections and Texts for template_id from a parsed .docx
+pub fn import_docx_document(
+    parsed: &ParsedDocx,
+    template_id: Uuid,
+    raw_title: String,
+    raw_purpose: String,
+    lang: &str,
+    created_by_id: Uuid,
+    security_classification: String,
+    machine_translate: bool,
+) -> Result<DocxImportReport, CustomError> {
+
+    let (_template, template_sections) = Template::get_readable_by_id(template_id, lang)?;
+
+    let (contents, matched_headings, unmatched_headings) = match_docx_sections(parsed, &template_sections);
+
+    let title = match (raw_title.trim(), &parsed.title) {
+        ("", Some(t)) => t.to_owned(),
+        ("", None) => "Imported document".to_string(),
+        (t, _) => t.to_string(),
+    };
+
+    // Fall back on the preamble for the purpose of the document
+    let purpose = match (raw_purpose.trim(), parsed.preamble.trim()) {
+        ("", "") => title.clone(),
+        ("", p) => p.to_string(),
+        (p, _) => p.to_string(),
+    };
+
+    let insertable_document = InsertableDocument::new(
+        template_id,
+        title,
+        purpose,
+        lang,
+        created_by_id,
+        security_classification,
+        machine_translate,
+    )?;
+
+    let document = match Document::create(&insertable_document) {
+        Ok(d) => d,
+        Err(err) => {
+            Text::delete(insertable_document.title_text_id)?;
+            Text::delete(insertable_document.purpose_text_id)?;
+            return Err(err)
+        },
+    };
+
+    // A failed import removes the partly created document so it can be retried
+    let empty_sections = match create_docx_sections(&document, &template_sections, &contents, lang, created_by_id, machine_translate) {
+        Ok(e) => e,
+        Err(err) => {
+            Document::delete(document.id)?;
+            return Err(err)
+        },
+    };
+
+    Ok(DocxImportReport {
+        document_id: document.id,
+        matched_headings,
+        unmatched_headings,
+        empty_sections,
+    })
+}
+
+// Create the sections of an imported document and return the headers of those without content
+fn create_docx_sections(
+    document: &Document,
+    template_sections: &BTreeMap<Uuid, ReadableTemplateSection>,
+    contents: &BTreeMap<Uuid, String>,
+    lang: &str,
+    created_by_id: Uuid,
+    machine_translate: bool,
+) -> Result<Vec<String>, CustomError> {
+
+    let mut empty_sections = Vec::new();
+
+    for (template_section_id, template_section) in template_sections.iter() {
+
+        let insertable_section = InsertableSection::new(
+            document.id,
+            *template_section_id,
+            created_by_id,
+        )?;
+
+        let section = Section::create(&insertable_section)?;
+
+        let content = match contents.get(template_section_id) {
+            Some(c) if !c.trim().is_empty() => c.to_owned(),
+            _ => {
+                empty_sections.push(template_section.header_text.clone());
+                template_section.help_text.clone()
+            },
+        };
+
+        let insertable_text = InsertableText::new(
+            Some(section.id),
+            lang,
+            content,
+            created_by_id,
+        );
+
+        let _text = Text::create(&insertable_text, machine_translate)?;
+    };
+
+    Ok(empty_sections)
+}
+
+/// Build a .docx file from a document and its sections ordered by order_number.
+/// Section content is expected as raw markdown. If redact is true redacted text is blacked out
+/// and the act reference kept, as in the open view.
+pub fn export_docx(
+    document: &ReadableDocument,
+    sections: &BTreeMap<i32, ReadableSection>,
+    redact: bool,
+) -> Result<Vec<u8>, CustomError> {
+
+    let mut docx = Docx::new()
+        .add_style(Style::new("Title", StyleType::Paragraph).name("Title").size(48).bold())
+        .add_style(Style::new("Heading1", StyleType::Paragraph).name("Heading 1").size(36).bold())
+        .add_style(Style::new("Heading2", StyleType::Paragraph).name("Heading 2").size(30).bold())
+        .add_style(Style::new("Heading3", StyleType::Paragraph).name("Heading 3").size(26).bold())
+        .add_style(Style::new("Heading4", StyleType::Paragraph).name("Heading 4").size(24).bold())
+        .add_style(Style::new("Heading5", StyleType::Paragraph).name("Heading 5").size(22).bold())
+        .add_style(Style::new("Heading6", StyleType::Paragraph).name("Heading 6").size(22).bold());
+
+    // Classification marking goes at the top as on the printed briefing notes
+    docx = docx.add_paragraph(Paragraph::new().add_run(
+        Run::new()
+            .add_text(&document.security_classification.replace('_', " ").to_uppercase())
+            .bold()
+    ));
+
+    docx = docx.add_paragraph(Paragraph::new()
+        .add_run(Run::new().add_text(&document.title_text))
+        .style("Title"));
+
+    docx = add_markdown(docx, &document.purpose_text, 1);
+
+    for (_order, section) in sections.iter() {
+        docx = docx.add_paragraph(Paragraph::new()
+            .add_run(Run::new().add_text(&section.header_text))
+            .style("Heading1"));
+
+        let content = process_text_redactions(section.content.to_owned(), redact);
+
+        // markdown headings sit under the section heading
+        docx = add_markdown(docx, &content, 1);
+    };
+
+    let mut buf = Cursor::new(Vec::new());
+
+    docx.build()
+        .pack(&mut buf)
+        .map_err(|e| CustomError::new(500, format!("Unable to write docx file: {}", e)))?;
+
+    Ok(buf.into_inner())
+}
+
+/// File name for a downloaded document, keeping only characters safe in a header
+pub fn docx_file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    match name.trim_matches('_') {
+        "" => "document".to_string(),
+        n => n.to_string(),
+    }
+}
+
+/// Add markdown content to docx as paragraphs and tables. Heading levels are shifted down by heading_offset.
+fn add_markdown(mut docx: Docx, markdown: &str, heading_offset: usize) -> Docx {
+
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+
+    let mut runs: Vec<Run> = Vec::new();
+    let mut style: Option<String> = None;
+    let mut bold = 0;
+    let mut italic = 0;
+
+    // next number for each open list, None for bullets
+    let mut lists: Vec<Option<u64>> = Vec::new();
+
+    let mut rows: Vec<TableRow> = Vec::new();
+    let mut cells: Vec<TableCell> = Vec::new();
+    let mut in_table_head = false;
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                style = Some(format!("Heading{}", (level as usize + heading_offset).min(6)));
+            },
+            Event::Start(Tag::List(start)) => {
+                // a nested list starts before the parent item ends
+                docx = flush_paragraph(docx, &mut runs, &mut style);
+                lists.push(start);
+            },
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+            },
+            Event::Start(Tag::Item) => {
+                let indent = "    ".repeat(lists.len().saturating_sub(1));
+
+                let marker = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}{}. ", indent, *n - 1)
+                    },
+                    _ => format!("{}\u{2022} ", indent),
+                };
+
+                runs.push(Run::new().add_text(&marker));
+            },
+            Event::Start(Tag::Emphasis) => italic += 1,
+            Event::End(Tag::Emphasis) => italic -= 1,
+            Event::Start(Tag::Strong) => bold += 1,
+            Event::End(Tag::Strong) => bold -= 1,
+            Event::Start(Tag::TableHead) => {
+                in_table_head = true;
+                cells = Vec::new();
+            },
+            Event::Start(Tag::TableRow) => {
+                cells = Vec::new();
+            },
+            Event::End(Tag::TableCell) => {
+                let paragraph = runs.drain(..).fold(Paragraph::new(), |p, r| p.add_run(r));
+                cells.push(TableCell::new().add_paragraph(paragraph));
+            },
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => {
+                in_table_head = false;
+                rows.push(TableRow::new(cells.drain(..).collect()));
+            },
+            Event::End(Tag::Table(_)) => {
+                docx = docx.add_table(Table::new(rows.drain(..).collect()));
+            },
+            Event::End(Tag::Heading(..)) | Event::End(Tag::Paragraph) | Event::End(Tag::Item)
+            | Event::End(Tag::CodeBlock(_)) => {
+                docx = flush_paragraph(docx, &mut runs, &mut style);
+            },
+            Event::Text(text) | Event::Code(text) => {
+                let mut run = Run::new().add_text(&text);
+
+                if bold > 0 || in_table_head {
+                    run = run.bold();
+                };
+
+                if italic > 0 {
+                    run = run.italic();
+                };
+
+                runs.push(run);
+            },
+            Event::SoftBreak => runs.push(Run::new().add_text(" ")),
+            Event::HardBreak => runs.push(Run::new().add_break(BreakType::TextWrapping)),
+            Event::Rule => docx = docx.add_paragraph(Paragraph::new()),
+            _ => (),
+        }
+    };
+
+    flush_paragraph(docx, &mut runs, &mut style)
+}
+
+fn flush_paragraph(docx: Docx, runs: &mut Vec<Run>, style: &mut Option<String>) -> Docx {
+
+    if runs.is_empty() {
+        *style = None;
+        return docx
+    };
+
+    let mut paragraph = runs.drain(..).fold(Paragraph::new(), |p, r| p.add_run(r));
+
+    if let Some(s) = style.take() {
+        paragraph = paragraph.style(&s);
+    };
+
+    docx.add_paragraph(paragraph)
+}
+
+fn normalize_heading(heading: &str) -> String {
+    heading
+        .trim()
+        .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ')')
+        .trim()
+        .trim_end_matches(':')
+        .trim()
+        .to_lowercase()
+}
+
+// docx-rs serializes some properties as plain values and others as {"val": ...}
+fn property_value(v: &Value) -> &Value {
+    match v.get("val") {
+        Some(inner) => inner,
+        None => v,
+    }
+}
+
+fn is_on(v: &Value) -> bool {
+    match property_value(v) {
+        Value::Bool(b) => *b,
+        Value::Null => false,
+        Value::Object(_) => true,
+        _ => false,
+    }
+}
+
+fn paragraph_style(paragraph: &Value) -> String {
+    property_value(&paragraph["property"]["style"])
+        .as_str()
+        .unwrap_or("")
+        .to_string()
+}
+
+fn list_level(paragraph: &Value) -> Option<usize> {
+    let numbering = &paragraph["property"]["numberingProperty"];
+
+    if numbering.is_null() {
+        return None
+    };
+
+    let level = property_value(&numbering["level"]).as_u64().unwrap_or(0);
+
+    Some(level as usize)
+}
+
+// Whether the list a paragraph belongs to is numbered rather than bulleted at level,
+// from the numbering definitions of the file
+fn is_numbered(json: &Value, paragraph: &Value, level: usize) -> bool {
+    let numbering_id = property_value(&paragraph["property"]["numberingProperty"]["id"]).as_u64();
+
+    let numbering = json["numberings"]["numberings"]
+        .as_array()
+        .and_then(|n| n.iter().find(|n| n["id"].as_u64() == numbering_id));
+
+    let numbering = match numbering {
+        Some(n) => n,
+        None => return false,
+    };
+
+    let level_format = |levels: &Value| -> Option<String> {
+        levels
+            .as_array()?
+            .iter()
+            .find(|l| l["level"].as_u64() == Some(level as u64))
+            .and_then(|l| l["format"].as_str())
+            .map(|f| f.to_string())
+    };
+
+    // a level override takes the place of the abstract numbering's level
+    let overridden = numbering["levelOverrides"]
+        .as_array()
+        .and_then(|o| o.iter().find(|o| o["level"].as_u64() == Some(level as u64)))
+        .and_then(|o| o["overrideLevel"]["format"].as_str())
+        .map(|f| f.to_string());
+
+    let format = overridden.or_else(|| {
+        let abstract_num = json["numberings"]["abstractNums"]
+            .as_array()?
+            .iter()
+            .find(|a| a["id"] == numbering["abstractNumId"])?;
+
+        level_format(&abstract_num["levels"])
+    });
+
+    !matches!(format.as_deref(), None | Some("bullet") | Some("none"))
+}
+
+/// Return the text of a paragraph, with bold and italic runs as markdown if markdown is true
+fn paragraph_text(paragraph: &Value, markdown: bool) -> String {
+    let mut text = String::new();
+
+    if let Some(children) = paragraph["children"].as_array() {
+        for child in children {
+            match child["type"].as_str() {
+                Some("run") => text.push_str(&run_text(&child["data"], markdown)),
+                // hyperlinks and tracked insertions wrap runs
+                Some("hyperlink") | Some("insert") => text.push_str(&paragraph_text(&child["data"], markdown)),
+                _ => (),
+            }
+        };
+    };
+
+    text
+}
+
+fn run_text(run: &Value, markdown: bool) -> String {
+    let mut text = String::new();
+
+    if let Some(children) = run["children"].as_array() {
+        for child in children {
+            match child["type"].as_str() {
+                Some("text") => text.push_str(child["data"]["text"].as_str().unwrap_or("")),
+                Some("tab") => text.push(' '),
+                Some("break") => text.push(' '),
+                _ => (),
+            }
+        };
+    };
+
+    if !markdown {
+        return text
+    };
+
+    let bold = is_on(&run["runProperty"]["bold"]);
+    let italic = is_on(&run["runProperty"]["italic"]);
+
+    let marker = match (bold, italic) {
+        (true, true) => "***",
+        (true, false) => "**",
+        (false, true) => "*",
+        (false, false) => return text,
+    };
+
+    let trimmed = text.trim();
+
+    if trimmed.is_empty() {
+        return text
+    };
+
+    // markdown emphasis can't start or end with whitespace
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+
+    format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+}
+
+fn table_markdown(table: &Value) -> String {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+
+    if let Some(table_rows) = table["rows"].as_array() {
+        for row in table_rows {
+            let mut cells = Vec::new();
+
+            if let Some(row_cells) = row["data"]["cells"].as_array() {
+                for cell in row_cells {
+                    let mut cell_text = Vec::new();
+
+                    if let Some(children) = cell["data"]["children"].as_array() {
+                        for child in children {
+                            if child["type"].as_str() == Some("paragraph") {
+                                cell_text.push(paragraph_text(&child["data"], true).trim().to_string());
+                            };
+                        };
+                    };
+
+                    cells.push(cell_text.join(" ").replace('|', "\\|"));
+                };
+            };
+
+            rows.push(cells);
+        };
+    };
+
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+    if columns == 0 {
+        return String::new()
+    };
+
+    let mut markdown = String::new();
+
+    for (i, row) in rows.iter().enumerate() {
+        let mut cells = row.clone();
+        cells.resize(columns, String::new());
+
+        markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+
+        // first row of the table is the header
+        if i == 0 {
+            markdown.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+        };
+    };
+
+    markdown.trim_end().to_string()
+}
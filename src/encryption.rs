@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use magic_crypt::{MagicCrypt256, MagicCryptTrait};
+
+use crate::errors::CustomError;
+use crate::models::{SECURITY_CLASSIFICATIONS, UNCLASSIFIED};
+
+/// Version of ciphertexts written before keys were versioned. They have no prefix.
+pub const LEGACY_KEY_VERSION: u32 = 1;
+
+const KEY_VAR_PREFIX: &str = "SECRET_KEY_";
+
+/// Versioned keys for encrypting Text content, one set per security classification.
+/// New ciphertexts are written as `v{version}:{base64}` for unclassified content and
+/// `{classification}.v{version}:{base64}` otherwise, using the active key for the classification.
+/// Every configured key can still decrypt, so a key can be rotated in
+/// before old texts are re-encrypted and retired afterwards.
+pub struct Keyring {
+    keys: BTreeMap<(String, u32), MagicCrypt256>,
+    active: BTreeMap<String, u32>,
+}
+
+impl Keyring {
+    /// Keys are (classification, version) -> key. Every classification needs keys of its own:
+    /// a classification without any can't be written, so content is never stored under a lower level's key.
+    pub fn new(keys: BTreeMap<(String, u32), String>, active: BTreeMap<String, u32>) -> Result<Self, CustomError> {
+
+        if !keys.keys().any(|(c, _)| c == UNCLASSIFIED) {
+            return Err(CustomError::new(500, "No unclassified encryption key configured".to_string()))
+        };
+
+        let mut crypts = BTreeMap::new();
+        let mut active_versions = BTreeMap::new();
+
+        for classification in SECURITY_CLASSIFICATIONS {
+
+            let class_keys: BTreeMap<u32, String> = keys
+                .iter()
+                .filter(|((c, _), _)| c == classification)
+                .map(|((_, v), k)| (*v, k.to_owned()))
+                .collect();
+
+            let active_version = match (active.get(classification), class_keys.keys().max()) {
+                (Some(v), _) => *v,
+                (None, Some(newest)) => *newest,
+                (None, None) => {
+                    println!("No encryption key configured for {}. Content can't be saved at that classification.", classification);
+                    continue
+                },
+            };
+
+            if !class_keys.contains_key(&active_version) {
+                return Err(CustomError::new(500, format!(
+                    "Active encryption key version {} is not configured for {}", active_version, classification)))
+            };
+
+            for (version, key) in class_keys {
+                crypts.insert((classification.to_string(), version), magic_crypt::new_magic_crypt!(key, 256));
+            };
+
+            active_versions.insert(classification.to_string(), active_version);
+        };
+
+        Ok(Keyring { keys: crypts, active: active_versions })
+    }
+
+    /// Load keys from the environment:
+    /// SECRET_KEY (unclassified version 1), SECRET_KEY_V2, SECRET_KEY_V3...
+    /// SECRET_KEY_PROTECTED_B (version 1), SECRET_KEY_PROTECTED_B_V2...
+    /// SECRET_KEY_VERSION and SECRET_KEY_PROTECTED_B_VERSION pick the key for new writes, otherwise the newest is used.
+    pub fn from_env() -> Result<Self, CustomError> {
+        let mut keys = BTreeMap::new();
+        let mut active = BTreeMap::new();
+
+        if let Ok(key) = env::var("SECRET_KEY") {
+            keys.insert((UNCLASSIFIED.to_string(), LEGACY_KEY_VERSION), key);
+        };
+
+        for (name, value) in env::vars() {
+            let rest = match name.strip_prefix(KEY_VAR_PREFIX) {
+                Some(r) => r,
+                None => continue,
+            };
+
+            if rest == "VERSION" || rest.ends_with("_VERSION") {
+                let classification = match rest.strip_suffix("VERSION").unwrap().trim_end_matches('_') {
+                    "" => UNCLASSIFIED.to_string(),
+                    c => c.to_lowercase(),
+                };
+
+                let version = value.trim().parse::<u32>()
+                    .map_err(|_| CustomError::new(500, format!("{} must be a number", name)))?;
+
+                active.insert(classification, version);
+                continue
+            };
+
+            let (classification, version) = parse_key_var(rest)
+                .ok_or_else(|| CustomError::new(500, format!("Unable to read encryption key variable {}", name)))?;
+
+            keys.insert((classification, version), value);
+        };
+
+        for ((classification, _), _) in keys.iter() {
+            if !SECURITY_CLASSIFICATIONS.contains(&classification.as_str()) {
+                return Err(CustomError::new(500, format!("Encryption key for unknown classification {}", classification)))
+            };
+        };
+
+        Keyring::new(keys, active)
+    }
+
+    /// Version of the key new content at classification is written with.
+    /// Errors if no key is configured for the classification.
+    pub fn active_version(&self, classification: &str) -> Result<u32, CustomError> {
+        self.active.get(classification)
+            .copied()
+            .ok_or_else(|| CustomError::new(500, format!("No encryption key for classification {}", classification)))
+    }
+
+    /// Classification and key version recorded in a ciphertext
+    pub fn key_of(ciphertext: &str) -> (String, u32) {
+        let (classification, version, _) = split_header(ciphertext);
+        (classification, version)
+    }
+
+    /// Encrypt unclassified content
+    pub fn encrypt<S: AsRef<str>>(&self, plain_text: S) -> String {
+        self.encrypt_for(UNCLASSIFIED, plain_text).expect("Unclassified key is always configured")
+    }
+
+    /// Encrypt content with the active key for classification
+    pub fn encrypt_for<S: AsRef<str>>(&self, classification: &str, plain_text: S) -> Result<String, CustomError> {
+        let version = self.active_version(classification)?;
+        let crypt = &self.keys[&(classification.to_string(), version)];
+
+        let header = match classification {
+            UNCLASSIFIED => format!("v{}", version),
+            c => format!("{}.v{}", c, version),
+        };
+
+        Ok(format!("{}:{}", header, crypt.encrypt_str_to_base64(plain_text)))
+    }
+
+    pub fn decrypt<S: AsRef<str>>(&self, ciphertext: S) -> Result<String, CustomError> {
+        let (classification, version, base64) = split_header(ciphertext.as_ref());
+
+        let crypt = match self.keys.get(&(classification.to_owned(), version)) {
+            Some(c) => c,
+            None => return Err(CustomError::new(500, format!(
+                "Encryption key version {} for {} is not configured", version, classification))),
+        };
+
+        crypt.decrypt_base64_to_string(base64)
+            .map_err(|e| CustomError::new(500, format!("Unable to decrypt with {} key version {}: {}", classification, version, e)))
+    }
+
+    /// Whether a ciphertext was written with a key other than the active key for classification
+    pub fn is_stale(&self, ciphertext: &str, classification: &str) -> bool {
+        let (c, v) = Keyring::key_of(ciphertext);
+
+        c != classification || Some(&v) != self.active.get(classification)
+    }
+
+    /// Decrypt with the recorded key and encrypt again with the active key for classification
+    pub fn reencrypt(&self, ciphertext: &str, classification: &str) -> Result<String, CustomError> {
+        if !self.is_stale(ciphertext, classification) {
+            return Ok(ciphertext.to_string())
+        };
+
+        let plain_text = self.decrypt(ciphertext)?;
+
+        self.encrypt_for(classification, plain_text)
+    }
+}
+
+// V2 -> unclassified version 2, PROTECTED_B -> protected_b version 1, PROTECTED_B_V2 -> version 2
+fn parse_key_var(rest: &str) -> Option<(String, u32)> {
+    let parse_version = |v: &str| v.strip_prefix('V').and_then(|n| n.parse::<u32>().ok());
+
+    if let Some(v) = parse_version(rest) {
+        return Some((UNCLASSIFIED.to_string(), v))
+    };
+
+    if let Some((classification, v)) = rest.rsplit_once('_') {
+        if let Some(v) = parse_version(v) {
+            return Some((classification.to_lowercase(), v))
+        };
+    };
+
+    match rest {
+        "" => None,
+        c => Some((c.to_lowercase(), LEGACY_KEY_VERSION)),
+    }
+}
+
+// Base64 never contains ':' or '.', so a header can't be confused with legacy content
+fn split_header(ciphertext: &str) -> (String, u32, &str) {
+    if let Some((header, base64)) = ciphertext.split_once(':') {
+        let (classification, version) = match header.split_once('.') {
+            Some((c, v)) => (c, v),
+            None => (UNCLASSIFIED, header),
+        };
+
+        if let Some(v) = version.strip_prefix('v').and_then(|n| n.parse::<u32>().ok()) {
+            return (classification.to_string(), v, base64)
+        };
+    };
+
+    (UNCLASSIFIED.to_string(), LEGACY_KEY_VERSION, ciphertext)
+}
@@ -11,6 +11,14 @@ pub struct CustomError {
     pub error_message: String,
 }
 
+/// Status for an edit based on a revision that is no longer the latest.
+/// Kept apart from 409, which database errors use.
+pub const EDIT_CONFLICT: u16 = 412;
+
+/// Status for a save or transition refused because a published document would show
+/// in one language what is redacted in the other
+pub const QA_BLOCKED: u16 = 422;
+
 impl CustomError {
     pub fn new(error_status_code: u16, error_message: String) -> CustomError {
         CustomError {
@@ -18,6 +26,24 @@ impl CustomError {
             error_message,
         }
     }
+
+    /// Someone else saved since the edit started
+    pub fn edit_conflict(error_message: String) -> CustomError {
+        CustomError::new(EDIT_CONFLICT, error_message)
+    }
+
+    pub fn is_edit_conflict(&self) -> bool {
+        self.error_status_code == EDIT_CONFLICT
+    }
+
+    /// Redactions would differ between the languages of a published document
+    pub fn qa_blocked(error_message: String) -> CustomError {
+        CustomError::new(QA_BLOCKED, error_message)
+    }
+
+    pub fn is_qa_blocked(&self) -> bool {
+        self.error_status_code == QA_BLOCKED
+    }
 }
 
 impl fmt::Display for CustomError {
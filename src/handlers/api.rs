@@ -1,8 +1,9 @@
-use actix_web::{web, get, HttpResponse, HttpRequest, Responder};
+use actix_web::{web, get, HttpResponse, HttpRequest, Responder, ResponseError};
 use actix_identity::Identity;
-use crate::{AppData, generate_basic_context};
+use crate::{AppData, generate_basic_context, get_clearance};
 
-use crate::models::{Template};
+use crate::models::{Template, SearchResult, SearchFilters};
+use super::{SEARCH_LIMIT};
 
 #[get("/{lang}/api/templates")]
 pub async fn template_api(
@@ -20,4 +21,28 @@ pub async fn template_api(
     let data = Template::get_all_with_data(&lang).unwrap();
 
     HttpResponse::Ok().json(data)
-}
\ No newline at end of file
+}
+#[get("/{lang}/api/search/{document_view}")]
+pub async fn search_api(
+    web::Path((lang, document_view)): web::Path<(String, String)>,
+    filters: web::Query<SearchFilters>,
+    id: Identity,
+    req: HttpRequest,
+) -> impl Responder {
+
+    let (_ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    // only signed in users can search the internal view
+    let internal = document_view == "internal" &&
+        (role == "user".to_string() || role == "admin".to_string());
+
+    let search = match SearchResult::search(&filters, &lang, internal, &get_clearance(&session_user), SEARCH_LIMIT) {
+        Ok(s) => s,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    HttpResponse::Ok().json(search)
+}
@@ -0,0 +1,233 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context};
+use crate::models::{ApprovalChain, Authorization, InsertableAuthorization, User, Document, DocumentTransition};
+use super::{ApproverForm, DecisionForm};
+use crate::errors::CustomError;
+
+#[get("/{lang}/approvals/{document_id}/{document_view}")]
+pub async fn get_approvals(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id, document_view)): web::Path<(String, Uuid, String)>,
+
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    } else {
+
+        let approval_chain = ApprovalChain::get_by_document_id(document_id)
+            .expect("Unable to load approval chain");
+
+        let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+        ctx.insert("approval_chain", &approval_chain);
+        ctx.insert("session_user_id", &user.id);
+        ctx.insert("document_view", &document_view);
+
+        let rendered = data.tmpl.render("approvals/approval_chain.html", &ctx).unwrap();
+        HttpResponse::Ok().body(rendered)
+    }
+}
+
+#[post("/{lang}/add_approver/{document_id}/{document_view}")]
+/// Add an approver to the end of the approval chain for a document
+pub async fn add_approver(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id, document_view)): web::Path<(String, Uuid, String)>,
+    form: web::Form<ApproverForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    } else {
+
+        let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+        // Only the document owner or an admin can change who approves it
+        let document = match Document::get_by_id(document_id) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if document.created_by_id != user.id && role != "admin".to_string() {
+            let err = CustomError::new(
+                406,
+                "Only the document owner or an admin can change approvers".to_string(),
+            );
+            println!("{}", &err);
+            return err.error_response()
+        };
+
+        let email = form.email.trim().to_lowercase();
+
+        let approver = match User::find_from_email(&email) {
+            Ok(u) => u,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        let insertable_authorization = InsertableAuthorization::new(document_id, approver.id)
+            .expect("Unable to generate insertable_authorization");
+
+        let _authorization = Authorization::create(&insertable_authorization)
+            .expect("Unable to add approver");
+
+        let approval_chain = ApprovalChain::get_by_document_id(document_id)
+            .expect("Unable to load approval chain");
+
+        ctx.insert("approval_chain", &approval_chain);
+        ctx.insert("session_user_id", &user.id);
+        ctx.insert("document_view", &document_view);
+
+        let rendered = data.tmpl.render("approvals/approval_chain.html", &ctx).unwrap();
+        HttpResponse::Ok().body(rendered)
+    }
+}
+
+#[post("/{lang}/remove_approver/{document_id}/{authorization_id}/{document_view}")]
+pub async fn remove_approver(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id, authorization_id, document_view)): web::Path<(String, Uuid, Uuid, String)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    } else {
+
+        let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+        // Only the document owner or an admin can change who approves it
+        let document = match Document::get_by_id(document_id) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if document.created_by_id != user.id && role != "admin".to_string() {
+            let err = CustomError::new(
+                406,
+                "Only the document owner or an admin can change approvers".to_string(),
+            );
+            println!("{}", &err);
+            return err.error_response()
+        };
+
+        let authorization = match Authorization::get_by_id(authorization_id) {
+            Ok(a) => a,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if authorization.document_id != document_id {
+            let err = CustomError::new(
+                404,
+                "Approver not found for this document".to_string(),
+            );
+            println!("{}", &err);
+            return err.error_response()
+        };
+
+        let _removed = Authorization::remove(authorization.id)
+            .expect("Unable to remove approver");
+
+        let approval_chain = ApprovalChain::get_by_document_id(document_id)
+            .expect("Unable to load approval chain");
+
+        ctx.insert("approval_chain", &approval_chain);
+        ctx.insert("session_user_id", &user.id);
+        ctx.insert("document_view", &document_view);
+
+        let rendered = data.tmpl.render("approvals/approval_chain.html", &ctx).unwrap();
+        HttpResponse::Ok().body(rendered)
+    }
+}
+
+#[post("/{lang}/approval_decision/{document_id}/{document_view}")]
+/// Record the decision of the current approver: approved, rejected or returned
+pub async fn approval_decision(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id, document_view)): web::Path<(String, Uuid, String)>,
+    form: web::Form<DecisionForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    } else {
+
+        let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+        let decision = ApprovalChain::record_decision(
+            document_id,
+            user.id,
+            form.decision.trim(),
+            form.comment.trim().to_string(),
+        );
+
+        if let Err(err) = decision {
+            println!("{}", &err);
+            return err.error_response()
+        };
+
+        // Approve the document or send it back to draft based on the decision
+        let _document = DocumentTransition::sync_with_approvals(document_id, user.id)
+            .expect("Unable to update document state");
+
+        let approval_chain = ApprovalChain::get_by_document_id(document_id)
+            .expect("Unable to load approval chain");
+
+        ctx.insert("approval_chain", &approval_chain);
+        ctx.insert("session_user_id", &user.id);
+        ctx.insert("document_view", &document_view);
+
+        let rendered = data.tmpl.render("approvals/approval_chain.html", &ctx).unwrap();
+        HttpResponse::Ok().body(rendered)
+    }
+}
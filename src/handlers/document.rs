@@ -1,23 +1,32 @@
 use std::collections::BTreeMap;
 
-use actix_web::{HttpRequest, HttpResponse, Responder, get, web, ResponseError};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
 use actix_identity::{Identity};
 use uuid::Uuid;
+use serde::{Deserialize};
 
-use crate::{AppData, generate_basic_context};
-use crate::models::{Document, MetaData, ReadableMetaData};
+use crate::{AppData, generate_basic_context, get_clearance};
+use crate::docx::{export_docx, docx_file_name};
+use crate::xliff::{document_units, write_xliff, xliff_file_name};
+use crate::models::{Document, MetaData, ReadableMetaData, ApprovalChain, User,
+    DocumentTransition, ReadableDocumentTransition, SectionLock, Job, TextVersion, TermWarning, QaIssue, allowed_transitions, DOCUMENT_STATES};
 
 use crate::errors::CustomError;
 
+#[derive(Deserialize, Debug)]
+pub struct DocumentIndexParams {
+    pub state: Option<String>,
+}
+
 #[get("/{lang}/document_index/{document_view}")]
 pub async fn document_index(
     data: web::Data<AppData>,
     web::Path((lang, document_view)): web::Path<(String, String)>,
-    
+    params: web::Query<DocumentIndexParams>,
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() &&
@@ -28,11 +37,19 @@ pub async fn document_index(
                 format!("/{}/document_index/open", lang)).finish()
     };
 
-    let documents_data = Document::get_all_readable(&lang, &document_view)
+    // Only filter on known lifecycle states
+    let state = match &params.state {
+        Some(s) if DOCUMENT_STATES.contains(&s.as_str()) => Some(s.as_str()),
+        _ => None,
+    };
+
+    let documents_data = Document::get_all_readable(&lang, &document_view, state, &get_clearance(&session_user))
         .expect("Unable to load templates");
 
     ctx.insert("documents", &documents_data);
     ctx.insert("document_view", &document_view);
+    ctx.insert("states", &DOCUMENT_STATES);
+    ctx.insert("selected_state", &state);
 
     let rendered = data.tmpl.render("documents/document_index.html", &ctx).unwrap();
     HttpResponse::Ok().body(rendered)
@@ -46,7 +63,7 @@ pub async fn get_document(
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() &&
@@ -55,13 +72,99 @@ pub async fn get_document(
             return HttpResponse::Found().header("Location", format!("/{}/document/{}/open", lang, document_id)).finish()
     };
 
+    // used to show the decision form to the current approver
+    let session_user_id = User::find_from_slug(&session_user).ok().map(|u| u.id);
+
+    if let Some(user_id) = session_user_id {
+        ctx.insert("session_user_id", &user_id);
+    };
+
     let redact = match document_view.as_str() {
         "internal" => false,
         _ => true,
     };
 
-    let (document, sections) = Document::get_all_readable_by_id(
-        document_id, &lang, true, redact).expect("Unable to retrieve text");
+    // Only published documents are part of the open view, checked before any content is decrypted
+    if document_view != "internal" {
+        let published = match Document::get_by_id(document_id) {
+            Ok(d) => d.state == "published",
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if !published {
+            let err = CustomError::new(
+                408,
+                "Record not found".to_string(),
+            );
+            println!("{}", &err);
+            return err.error_response()
+        };
+    };
+
+    let (document, sections) = match Document::get_all_readable_by_id(
+        document_id, &lang, true, redact, &get_clearance(&session_user)) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+    // who is editing which section
+    let locks = match document_view.as_str() {
+        "internal" => SectionLock::get_active_map(sections.keys().cloned().collect())
+            .expect("Unable to load section locks"),
+        _ => BTreeMap::new(),
+    };
+
+    // translations still queued or given up on
+    let translation_jobs = match document_view.as_str() {
+        "internal" => Job::get_translation_statuses(sections.values().map(|s| s.text_id).collect())
+            .expect("Unable to load translation jobs"),
+        _ => BTreeMap::new(),
+    };
+
+    // section texts in this language, and in either language, that need translating again
+    let text_ids: Vec<Uuid> = sections.values().map(|s| s.text_id).collect();
+
+    // terms the sections don't render as the termbase approves
+    let term_warnings = match document_view.as_str() {
+        "internal" => TermWarning::get_map(text_ids.clone(), &lang)
+            .expect("Unable to load term warnings"),
+        _ => BTreeMap::new(),
+    };
+
+    // numbers, dates, links, markdown and redactions that differ from the other language
+    let qa_issues = match document_view.as_str() {
+        "internal" => QaIssue::get_map(text_ids.clone(), &lang)
+            .expect("Unable to compare translations"),
+        _ => BTreeMap::new(),
+    };
+
+    let qa_blocked = qa_issues.values().flatten().any(|i| i.blocking);
+
+    let (out_of_date, document_out_of_date) = match document_view.as_str() {
+        "internal" => {
+            let out_of_date = TextVersion::get_out_of_date(text_ids.clone(), &lang)
+                .expect("Unable to compare translations");
+
+            let other_lang = match lang.as_str() {
+                "fr" => "en",
+                _ => "fr",
+            };
+
+            let other_out_of_date = TextVersion::get_out_of_date(text_ids, other_lang)
+                .expect("Unable to compare translations");
+
+            let any = !out_of_date.is_empty() || !other_out_of_date.is_empty();
+
+            (out_of_date, any)
+        },
+        _ => (Vec::new(), false),
+    };
 
     let mut ordered_sections = BTreeMap::new();
 
@@ -71,17 +174,145 @@ pub async fn get_document(
 
     let metadata = MetaData::get_by_document_id(document_id).expect("Unable to retrieve metadata for document");
 
-    let readable_metadata = ReadableMetaData::from_metadata(metadata, &lang);
+    let readable_metadata = ReadableMetaData::from_metadata(metadata, &lang)
+        .expect("Unable to read metadata for document");
+
+    let approval_chain = ApprovalChain::get_by_document_id(document_id)
+        .expect("Unable to retrieve approval chain for document");
 
     ctx.insert("document", &document);
     ctx.insert("sections", &ordered_sections);
+    ctx.insert("locks", &locks);
+    ctx.insert("translation_jobs", &translation_jobs);
+    ctx.insert("out_of_date", &out_of_date);
+    ctx.insert("document_out_of_date", &document_out_of_date);
+    ctx.insert("term_warnings", &term_warnings);
+    ctx.insert("qa_issues", &qa_issues);
+    ctx.insert("qa_blocked", &qa_blocked);
     ctx.insert("document_view", &document_view);
     ctx.insert("metadata", &readable_metadata);
+    let transition_history = ReadableDocumentTransition::get_by_document_id(document_id)
+        .expect("Unable to retrieve transitions for document");
+
+    ctx.insert("approval_chain", &approval_chain);
+    ctx.insert("transitions", &allowed_transitions(&document.state, &role, session_user_id == Some(document.created_by_id)));
+    ctx.insert("transition_history", &transition_history);
 
     let rendered = data.tmpl.render("documents/document.html", &ctx).unwrap();
     HttpResponse::Ok().body(rendered)
 }
 
+#[get("/{lang}/document/{document_id}/{document_view}/docx")]
+/// Download the document as a Word file. The open view is redacted.
+pub async fn get_document_docx(
+    web::Path((lang, document_id, document_view)): web::Path<(String, Uuid, String)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() &&
+        document_view == "internal" {
+            // send to external view
+            return HttpResponse::Found().header("Location", format!("/{}/document/{}/open/docx", lang, document_id)).finish()
+    };
+
+    let redact = match document_view.as_str() {
+        "internal" => false,
+        _ => true,
+    };
+
+    // Only published documents are part of the open view, checked before any content is decrypted
+    if document_view != "internal" {
+        let published = match Document::get_by_id(document_id) {
+            Ok(d) => d.state == "published",
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if !published {
+            let err = CustomError::new(
+                408,
+                "Record not found".to_string(),
+            );
+            println!("{}", &err);
+            return err.error_response()
+        };
+    };
+
+    // Sections are returned as raw markdown and redacted when the file is built
+    let (document, sections) = match Document::get_all_readable_by_id(
+        document_id, &lang, false, redact, &get_clearance(&session_user)) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+    let mut ordered_sections = BTreeMap::new();
+
+    for (_k, v) in sections {
+        ordered_sections.insert(v.order_number, v);
+    };
+
+    let file = match export_docx(&document, &ordered_sections, redact) {
+        Ok(f) => f,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        .header("Content-Disposition", format!("attachment; filename=\"{}.docx\"", docx_file_name(&document.title_text)))
+        .body(file)
+}
+
+#[get("/{lang}/export_xliff/{document_id}")]
+/// Download the document's texts in lang as an XLIFF 2.0 file for translation into the other language
+pub async fn export_xliff(
+    web::Path((lang, document_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let trg_lang = match lang.as_str() {
+        "en" => "fr",
+        _ => "en",
+    };
+
+    let clearance = get_clearance(&session_user);
+
+    let (document, units) = match document_units(document_id, &lang, trg_lang, &clearance) {
+        Ok(d) => d,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/xliff+xml")
+        .header("Content-Disposition", format!("attachment; filename=\"{}\"", xliff_file_name(&document.title_text, &lang, trg_lang)))
+        .body(write_xliff(document_id, &lang, trg_lang, &units))
+}
+
 #[get("/{lang}/edit_document_sections/{document_id}")]
 pub async fn edit_document_sections(
     data: web::Data<AppData>,
@@ -89,7 +320,7 @@ pub async fn edit_document_sections(
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() {
@@ -101,8 +332,17 @@ pub async fn edit_document_sections(
         return err.error_response()
     } else {
 
-        let (document, sections) = Document::get_all_readable_by_id(document_id, &lang, false, false)
-            .expect("Unable to load document");
+        let (document, sections) = match Document::get_all_readable_by_id(
+            document_id, &lang, false, false, &get_clearance(&session_user)) {
+                Ok(d) => d,
+                Err(err) => {
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
+
+        let locks = SectionLock::get_active_map(sections.keys().cloned().collect())
+            .expect("Unable to load section locks");
 
         let mut ordered_sections = BTreeMap::new();
 
@@ -112,21 +352,115 @@ pub async fn edit_document_sections(
 
         ctx.insert("document", &document);
         ctx.insert("sections", &ordered_sections);
+        ctx.insert("locks", &locks);
 
         let rendered = data.tmpl.render("documents/edit_document_sections.html", &ctx).unwrap();
         HttpResponse::Ok().body(rendered)
     }
 }
 
-#[get("/{lang}/toggle_document_published/{document_id}/{document_view}")]
-pub async fn toggle_document_published(
-    _data: web::Data<AppData>,
-    web::Path((lang, document_id, document_view)): web::Path<(String, Uuid, String)>,
+#[get("/{lang}/bilingual_sections/{document_id}")]
+/// Each section's English and French texts side by side, each with its own editor,
+/// translation status and action to translate it again from the other language
+pub async fn bilingual_document_sections(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let clearance = get_clearance(&session_user);
+
+    // the document in the page language, then each section in both languages
+    let (document, sections) = match Document::get_all_readable_by_id(
+        document_id, &lang, true, false, &clearance) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+    let text_ids: Vec<Uuid> = sections.values().map(|s| s.text_id).collect();
+
+    let mut side_sections = BTreeMap::new();
+    let mut translation_jobs = BTreeMap::new();
+    let mut out_of_date = BTreeMap::new();
+    let mut term_warnings = BTreeMap::new();
+    let mut qa_issues = BTreeMap::new();
+
+    for side in ["en", "fr"] {
+        let side_document_sections = match side == lang {
+            true => sections.clone(),
+            false => match Document::get_all_readable_by_id(document_id, side, true, false, &clearance) {
+                Ok((_d, s)) => s,
+                Err(err) => {
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            },
+        };
+
+        side_sections.insert(side, side_document_sections);
+
+        translation_jobs.insert(side, Job::get_translation_statuses_into(text_ids.clone(), side)
+            .expect("Unable to load translation jobs"));
+
+        out_of_date.insert(side, TextVersion::get_out_of_date(text_ids.clone(), side)
+            .expect("Unable to compare translations"));
+
+        term_warnings.insert(side, TermWarning::get_map(text_ids.clone(), side)
+            .expect("Unable to load term warnings"));
+
+        qa_issues.insert(side, QaIssue::get_map(text_ids.clone(), side)
+            .expect("Unable to compare translations"));
+    };
+
+    let locks = SectionLock::get_active_map(sections.keys().cloned().collect())
+        .expect("Unable to load section locks");
+
+    let mut ordered_sections = BTreeMap::new();
+
+    for (_k, v) in sections {
+        ordered_sections.insert(v.order_number, v);
+    };
+
+    ctx.insert("document", &document);
+    ctx.insert("sections", &ordered_sections);
+    ctx.insert("side_sections", &side_sections);
+    ctx.insert("sides", &["en", "fr"]);
+    ctx.insert("locks", &locks);
+    ctx.insert("translation_jobs", &translation_jobs);
+    ctx.insert("out_of_date", &out_of_date);
+    ctx.insert("term_warnings", &term_warnings);
+    ctx.insert("qa_issues", &qa_issues);
+    ctx.insert("document_view", "internal");
+
+    let rendered = data.tmpl.render("documents/bilingual_sections.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/transition_document/{document_id}/{to_state}/{document_view}")]
+/// Move a document to a new lifecycle state if the user's role allows it
+pub async fn transition_document(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id, to_state, document_view)): web::Path<(String, Uuid, String, String)>,
     
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() {
@@ -134,17 +468,38 @@ pub async fn toggle_document_published(
             return HttpResponse::Found().header("Location", format!("/{}/document/{}/open", lang, document_id)).finish()
     };
 
-    let mut document = Document::get_by_id(document_id).expect("Unable to retrieve text");
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
 
-    document.published = match document.published {
-        false => true,
-        true => false,
-    };
+    let d = match DocumentTransition::apply(document_id, &to_state, user.id, &role) {
+        Ok(d) => d,
+        // redactions don't match: show which texts to fix instead of a not authorized page
+        Err(err) if err.is_qa_blocked() => {
+            println!("{}", &err);
 
-    let d = document.update()
-        .expect("Unable to update document");
+            let blocking = QaIssue::get_blocking(document_id).expect("Unable to compare translations");
+
+            ctx.insert("document_id", &document_id);
+            ctx.insert("document_view", &document_view);
+            ctx.insert("to_state", &to_state);
+            ctx.insert("blocking_count", &blocking.len());
+
+            let rendered = data.tmpl.render("documents/transition_refused.html", &ctx).unwrap();
+            return HttpResponse::UnprocessableEntity().body(rendered)
+        },
+        // someone else moved it first: show the document in its current state
+        Err(err) if err.is_edit_conflict() => {
+            println!("{}", &err);
+            return HttpResponse::Found().header(
+                "Location",
+                format!("/{}/document/{}/{}", lang, document_id, &document_view)).finish()
+        },
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
 
     HttpResponse::Found().header(
         "Location",
         format!("/{}/document/{}/{}", lang, d.id, &document_view)).finish()
-}
\ No newline at end of file
+}
@@ -4,9 +4,9 @@ use actix_web::{HttpRequest, HttpResponse, Responder, get, post, put, web, Respo
 use actix_identity::{Identity};
 use uuid::Uuid;
 
-use crate::{AppData, generate_basic_context};
+use crate::{AppData, generate_basic_context, get_clearance, KEYRING};
 use crate::models::{Section, User, Document, Template, InsertableDocument, InsertableSection,
-    InsertableText, Text};
+    InsertableText, Text, validate_classification, check_clearance, diff_words};
 use super::DocumentForm;
 use crate::errors::CustomError;
 
@@ -18,7 +18,7 @@ pub async fn get_document_core(
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() &&
@@ -32,8 +32,14 @@ pub async fn get_document_core(
         _ => true,
     };
 
-    let document = Document::get_readable_core_by_id(
-        document_id, &lang, true, redact).expect("Unable to retrieve text");
+    let document = match Document::get_readable_core_by_id(
+        document_id, &lang, true, redact, &get_clearance(&session_user)) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
 
     ctx.insert("document_core", &document);
     ctx.insert("document_view", &document_view);
@@ -110,6 +116,15 @@ pub async fn create_document_core_post(
 
         let user = User::find_from_slug(&session_user).expect("Unable to find user");
 
+        // users can't create documents they wouldn't be cleared to read,
+        // or at a classification without its own encryption key
+        if let Err(err) = validate_classification(&security_classification)
+            .and_then(|_| check_clearance(&user.clearance, &security_classification))
+            .and_then(|_| KEYRING.active_version(&security_classification)) {
+            println!("{}", &err);
+            return err.error_response()
+        };
+
         // create document
         let document = InsertableDocument::new(
             template_id,
@@ -164,7 +179,7 @@ pub async fn edit_document_core_form(
     req:HttpRequest,
 ) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() {
@@ -176,9 +191,14 @@ pub async fn edit_document_core_form(
         return err.error_response()
     } else {
 
-        let document_core = Document::get_readable_core_by_id(
-            document_id, &lang, false, false)
-            .expect("Unable to load document core");
+        let document_core = match Document::get_readable_core_by_id(
+            document_id, &lang, false, false, &get_clearance(&session_user)) {
+                Ok(d) => d,
+                Err(err) => {
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
 
         ctx.insert("document_core", &document_core);
         ctx.insert("document_view", &document_view);
@@ -225,11 +245,65 @@ pub async fn edit_document_core_put(
 
         let user = User::find_from_slug(&session_user).expect("Unable to find user");
 
-        // update document_core
+        let mut document = Document::get_by_id(document_id)
+            .expect("Unable to load document");
+
+        // users need clearance for both the current and the new classification,
+        // and the new classification needs its own encryption key
+        if let Err(err) = validate_classification(&security_classification)
+            .and_then(|_| check_clearance(&user.clearance, &document.security_classification))
+            .and_then(|_| check_clearance(&user.clearance, &security_classification))
+            .and_then(|_| KEYRING.active_version(&security_classification)) {
+            println!("{}", &err);
+            return err.error_response()
+        };
+
         let document_core = Document::get_readable_core_by_id(
-            document_id, &lang, false, false)
+            document_id, &lang, false, false, &user.clearance)
             .expect("Unable to load document");
 
+        let reclassified = document.security_classification != security_classification;
+
+        if reclassified {
+            document.security_classification = security_classification.clone();
+        };
+
+        document.updated_at = chrono::Utc::now().naive_utc();
+
+        // saving the document first claims the edit, so a stale form changes nothing
+        let updated = match form.base_updated_at {
+            Some(base) => document.update_if_unchanged(base),
+            None => document.update(),
+        };
+
+        let document = match updated {
+            Ok(d) => d,
+            Err(err) if err.is_edit_conflict() => {
+                println!("{}", &err);
+
+                // the form again with the user's values, the saved version and how they differ
+                let mut yours = document_core.clone();
+                yours.title_text = raw_title_text.clone();
+                yours.purpose_text = raw_purpose_text.clone();
+                yours.security_classification = security_classification;
+
+                ctx.insert("document_core", &yours);
+                ctx.insert("saved", &document_core);
+                ctx.insert("conflict", &true);
+                ctx.insert("title_diff", &diff_words(&document_core.title_text, &raw_title_text));
+                ctx.insert("purpose_diff", &diff_words(&document_core.purpose_text, &raw_purpose_text));
+                ctx.insert("document_view", &document_view);
+
+                let rendered = data.tmpl.render("document_core/edit_document_core_form.html", &ctx).unwrap();
+                return HttpResponse::Conflict().body(rendered)
+            },
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        // update document_core texts
         if document_core.title_text != raw_title_text {
             let _title_text = Text::update(
                 document_core.title_text_id,
@@ -250,20 +324,14 @@ pub async fn edit_document_core_put(
             ).expect("Unable to update text");
         };
 
-        let mut document = Document::get_by_id(document_id)
-            .expect("Unable to load document");
-
-        if document.security_classification != security_classification {
-            document.security_classification = security_classification;
+        // move section content to the key for the new classification
+        if reclassified {
+            Text::reencrypt_document(document.id, &document.security_classification)
+                .expect("Unable to re-encrypt document");
         };
 
-        document.updated_at = chrono::Utc::now().naive_utc();
-        
-        document.update()
-            .expect("Unable to update document");
-
         let readable_document_core = Document::get_readable_core_by_id(
-            document_id, &lang, true, redact)
+            document_id, &lang, true, redact, &user.clearance)
             .expect("Unable to get readable document");
 
         ctx.insert("document_core", &readable_document_core);
@@ -0,0 +1,48 @@
+use actix_web::{HttpRequest, HttpResponse, get, web, ResponseError};
+use actix_identity::{Identity};
+use actix_web_actors::ws;
+use uuid::Uuid;
+
+use crate::generate_basic_context;
+use crate::models::{Document, User, check_clearance};
+use crate::realtime::DocumentSocket;
+use crate::errors::CustomError;
+
+#[get("/{lang}/document_socket/{document_id}")]
+/// WebSocket for a document page. Sends section updates, state changes and
+/// who else has the document open. The page tells it which section is being edited.
+pub async fn document_socket(
+    web::Path((lang, document_id)): web::Path<(String, Uuid)>,
+    stream: web::Payload,
+    id: Identity,
+    req:HttpRequest) -> Result<HttpResponse, actix_web::Error> {
+
+    let (_ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return Ok(err.error_response())
+    };
+
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+    let document = match Document::get_by_id(document_id) {
+        Ok(d) => d,
+        Err(err) => {
+            println!("{}", &err);
+            return Ok(err.error_response())
+        },
+    };
+
+    if let Err(err) = check_clearance(&user.clearance, &document.security_classification) {
+        println!("{}", &err);
+        return Ok(err.error_response())
+    };
+
+    ws::start(DocumentSocket::new(document.id, user.user_name, user.slug), &req, stream)
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize};
+use chrono::NaiveDateTime;
 
 // Business Forms
 #[derive(Deserialize, Debug)]
@@ -7,19 +8,44 @@ pub struct DocumentForm {
     pub purpose: String,
     pub security_classification: String,
     pub machine_translate: String,
+    // updated_at of the document when the edit started
+    pub base_updated_at: Option<NaiveDateTime>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct MetaDataForm {
-    pub subject: String,
-    pub category: String,
-    pub keywords: String,
+pub struct TaxonomyForm {
+    pub en_string: String,
+    pub fr_string: String,
+    pub en_description: String,
+    pub fr_description: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TermForm {
+    pub en_term: String,
+    pub fr_term: String,
+    // renderings to avoid, separated by semicolons or new lines
+    pub en_deprecated: String,
+    pub fr_deprecated: String,
+    pub note: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KeywordSuggestionForm {
+    pub en_string: String,
+    pub fr_string: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TextForm {
     pub content: String,
+    // left out when the box is unticked, so the other language isn't overwritten by default
+    #[serde(default)]
     pub machine_translate: String,
+    // revision the edit started from
+    pub base_version: Option<i32>,
+    // set when the edit translates this revision of the other language
+    pub source_version: Option<i32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -46,6 +72,17 @@ pub struct TemplateSectionForm {
     pub machine_translate: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ApproverForm {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecisionForm {
+    pub decision: String,
+    pub comment: String,
+}
+
 // Administrative Forms
 
 #[derive(Deserialize, Debug)]
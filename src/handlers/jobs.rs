@@ -0,0 +1,79 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context};
+use crate::models::{Job};
+use crate::errors::CustomError;
+
+// Jobs shown on the admin page
+const JOBS_SHOWN: i64 = 200;
+
+#[derive(Deserialize, Debug)]
+pub struct JobIndexParams {
+    pub status: Option<String>,
+}
+
+#[get("/{lang}/jobs")]
+/// Recent background jobs, with dead jobs ready to be retried
+pub async fn job_index(
+    data: web::Data<AppData>,
+    web::Path(lang): web::Path<String>,
+    params: web::Query<JobIndexParams>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let status = params.status.as_deref().filter(|s| !s.is_empty());
+
+    let jobs = match Job::get_recent(status, JOBS_SHOWN) {
+        Ok(j) => j,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("jobs", &jobs);
+    ctx.insert("status", &status.unwrap_or(""));
+    ctx.insert("job_statuses", &["pending", "running", "done", "dead"]);
+
+    let rendered = data.tmpl.render("jobs/jobs.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/retry_job/{job_id}")]
+pub async fn retry_job(
+    web::Path((lang, job_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = Job::retry(job_id) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/jobs", lang)).finish()
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context};
+use crate::models::{KeywordSuggestion};
+use super::KeywordSuggestionForm;
+use crate::errors::CustomError;
+
+#[get("/{lang}/keyword_suggestions")]
+/// Queue of RAKE keywords waiting to be accepted into or rejected from the keywords vocabulary
+pub async fn keyword_suggestions_index(
+    data: web::Data<AppData>,
+    web::Path(lang): web::Path<String>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let suggestions = match KeywordSuggestion::get_pending_readable(&lang) {
+        Ok(s) => s,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("suggestions", &suggestions);
+
+    let rendered = data.tmpl.render("keywords/keyword_suggestions.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/accept_keyword_suggestion/{suggestion_id}")]
+/// Accept a suggestion with the names entered by the curator
+pub async fn accept_keyword_suggestion(
+    web::Path((lang, suggestion_id)): web::Path<(String, Uuid)>,
+    form: web::Form<KeywordSuggestionForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = KeywordSuggestion::accept(suggestion_id, &form.en_string, &form.fr_string) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/keyword_suggestions", lang)).finish()
+}
+
+#[post("/{lang}/reject_keyword_suggestion/{suggestion_id}")]
+pub async fn reject_keyword_suggestion(
+    web::Path((lang, suggestion_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = KeywordSuggestion::reject(suggestion_id) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/keyword_suggestions", lang)).finish()
+}
+
+#[post("/{lang}/suggest_keywords/{document_id}")]
+/// Run the keyword pipeline for a document now instead of waiting for its next edit
+pub async fn suggest_keywords(
+    web::Path((lang, document_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = KeywordSuggestion::suggest_for_document(document_id, Arc::new(lang.clone())).await {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/keyword_suggestions", lang)).finish()
+}
@@ -1,17 +1,16 @@
-use actix_web::{HttpRequest, HttpResponse, Responder, get, post, put, web, ResponseError};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, put, web, ResponseError};
 use actix_identity::{Identity};
 use uuid::Uuid;
 
 use crate::{AppData, generate_basic_context};
-use crate::models::{MetaData, InsertableMetaData, ReadableMetaData, User};
-use super::MetaDataForm;
+use crate::models::{MetaData, ReadableMetaData, TaxonomyEntry};
 use crate::errors::CustomError;
 
 #[get("/{lang}/metadata/{metadata_id}/{document_view}")]
 pub async fn get_metadata(
     data: web::Data<AppData>,
     web::Path((lang, metadata_id, document_view)): web::Path<(String, Uuid, String)>,
-    
+
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
@@ -29,7 +28,7 @@ pub async fn get_metadata(
 
         let metadata = MetaData::get_by_id(metadata_id).expect("Unable to retrieve metadata");
 
-        let metadata = ReadableMetaData::from_metadata(metadata, &lang);
+        let metadata = ReadableMetaData::from_metadata(metadata, &lang).expect("Unable to read metadata");
 
         ctx.insert("metadata", &metadata);
         ctx.insert("document_view", &document_view);
@@ -39,60 +38,12 @@ pub async fn get_metadata(
     }
 }
 
-/*
-
-#[post("/{lang}/create_metadata/{section_id}")]
-pub async fn create_new_metadata(
-    data: web::Data<AppData>,
-    web::Path((lang, section_id)): web::Path<(String, Uuid)>,
-    form: web::Form<MetaDataForm>,
-    id: Identity,
-    req:HttpRequest) -> impl Responder {
-
-    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
-
-    if role != "user".to_string() &&
-        role != "admin".to_string() {
-        let err = CustomError::new(
-            406,
-            "Not authorized".to_string(),
-        );
-        println!("{}", &err);
-        return err.error_response()
-    } else {
-
-        // validate authorized to edit document
-        let subject = form.subject.trim();
-
-        let machine_translate = match form.machine_translate.as_str() {
-            "true" => true,
-            _ => false,
-        };
-
-        println!("Saving metadata: {}", content);
-
-        let user = User::find_from_slug(&session_user).expect("Unable to find user");
-
-        let insertable_metadata = InsertableMetaData::new(Some(section_id), &lang, content.to_string(), user.id);
-
-        println!("Saving metadata: {:?}", &insertable_metadata.content);
-
-        let metadata = MetaData::create(&insertable_metadata, machine_translate).expect("Unable to create metadata");
-
-        println!("Saved!");
-
-        ctx.insert("metadata", &metadata);
-
-        let rendered = data.tmpl.render("metadata/metadata.html", &ctx).unwrap();
-        HttpResponse::Ok().body(rendered)
-    }
-}
-
 #[get("/{lang}/edit_metadata/{metadata_id}/{document_view}")]
+/// Form to assign a subject, category and keywords to a document
 pub async fn edit_metadata_form(
     data: web::Data<AppData>,
     web::Path((lang, metadata_id, document_view)): web::Path<(String, Uuid, String)>,
-    
+
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
@@ -108,11 +59,22 @@ pub async fn edit_metadata_form(
         return err.error_response()
     } else {
 
-        let metadata = MetaData::get_metadata_by_id(metadata_id, &lang).expect("Unable to retrieve metadata");
+        let metadata = MetaData::get_by_id(metadata_id).expect("Unable to retrieve metadata");
+
+        let subjects: Vec<_> = TaxonomyEntry::get_all("subjects").expect("Unable to load subjects")
+            .iter().map(|e| e.to_readable("subjects", &lang)).collect();
 
-        let metadata = LatestMetaData::get_from(metadata, false, false);
+        let categories: Vec<_> = TaxonomyEntry::get_all("categories").expect("Unable to load categories")
+            .iter().map(|e| e.to_readable("categories", &lang)).collect();
+
+        let keywords: Vec<_> = TaxonomyEntry::get_all("keywords").expect("Unable to load keywords")
+            .iter().map(|e| e.to_readable("keywords", &lang)).collect();
 
         ctx.insert("metadata", &metadata);
+        ctx.insert("subjects", &subjects);
+        ctx.insert("categories", &categories);
+        ctx.insert("keywords", &keywords);
+        ctx.insert("keyword_ids", &metadata.keyword_ids.clone().unwrap_or_default());
         ctx.insert("document_view", &document_view);
 
         let rendered = data.tmpl.render("metadata/edit_metadata.html", &ctx).unwrap();
@@ -121,14 +83,15 @@ pub async fn edit_metadata_form(
 }
 
 #[put("/{lang}/edit_metadata/{metadata_id}/{document_view}")]
+/// Takes the form as pairs because keywords are sent as one keyword_id per checkbox
 pub async fn edit_metadata_put(
     data: web::Data<AppData>,
     web::Path((lang, metadata_id, document_view)): web::Path<(String, Uuid, String)>,
-    form: web::Form<MetaDataForm>,
+    form: web::Form<Vec<(String, String)>>,
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     // validate authorized to edit document
     if role != "user".to_string() &&
@@ -141,34 +104,26 @@ pub async fn edit_metadata_put(
         return err.error_response()
     } else {
 
-        
-        let content = form.content.trim();
+        let mut subject_id = None;
+        let mut category_id = None;
+        let mut keyword_ids = Vec::new();
 
-        let machine_translate = match form.machine_translate.as_str() {
-            "true" => true,
-            _ => false,
-        };
-
-        let user = User::find_from_slug(&session_user).expect("Unable to find user");
-
-        println!("Updating metadata: {:?}", &content);
+        // empty selections and invalid ids clear the value
+        for (key, value) in form.iter() {
+            let id = Uuid::parse_str(value.trim()).ok();
 
-        let metadata = MetaData::update(
-            metadata_id, 
-            content.to_string(), 
-            &lang, user.id, 
-            machine_translate)
-            .expect("Unable to update MetaData");
-
-        // Determine view of metadata to render
-        let redact = match document_view.as_str() {
-            "internal" => false,
-            _ => true,
+            match key.as_str() {
+                "subject_id" => subject_id = id,
+                "category_id" => category_id = id,
+                "keyword_id" => keyword_ids.extend(id),
+                _ => (),
+            };
         };
 
-        let metadata = LatestMetaData::get_from(metadata, true, redact);
+        let metadata = MetaData::update_taxonomy(metadata_id, subject_id, category_id, keyword_ids)
+            .expect("Unable to update metadata");
 
-        println!("Updated!");
+        let metadata = ReadableMetaData::from_metadata(metadata, &lang).expect("Unable to read metadata");
 
         ctx.insert("metadata", &metadata);
         ctx.insert("document_view", &document_view);
@@ -177,5 +132,3 @@ pub async fn edit_metadata_put(
         HttpResponse::Ok().body(rendered)
     }
 }
-
-*/
\ No newline at end of file
@@ -14,7 +14,17 @@ pub mod document_core;
 pub mod template_section;
 pub mod template_core;
 pub mod metadata;
-//pub mod upload_files;
+pub mod approvals;
+pub mod upload_files;
+pub mod search;
+pub mod taxonomy;
+pub mod keyword_suggestions;
+pub mod section_lock;
+pub mod document_socket;
+pub mod jobs;
+pub mod translation_queue;
+pub mod translation_memory;
+pub mod terms;
 
 pub use base::{index, raw_index};
 pub use routes::configure_services;
@@ -32,4 +42,14 @@ pub use document_core::*;
 pub use template_section::*;
 pub use template_core::*;
 pub use metadata::*;
-//pub use upload_files::*;
\ No newline at end of file
+pub use approvals::*;
+pub use upload_files::*;
+pub use search::*;
+pub use taxonomy::*;
+pub use keyword_suggestions::*;
+pub use section_lock::*;
+pub use document_socket::*;
+pub use jobs::*;
+pub use translation_queue::*;
+pub use translation_memory::*;
+pub use terms::*;
\ No newline at end of file
@@ -7,6 +7,7 @@ use crate::handlers::{
 
     // API
     template_api,
+    search_api,
 
     //about,
     toggle_language,
@@ -73,8 +74,11 @@ use crate::handlers::{
     // documents
     document_index,
     get_document,
+    get_document_docx,
+    export_xliff,
     edit_document_sections,
-    toggle_document_published,
+    bilingual_document_sections,
+    transition_document,
     
     // document_core
     get_document_core,
@@ -88,9 +92,70 @@ use crate::handlers::{
     create_new_text,
     edit_text_form,
     edit_text_put,
+    cancel_edit_text,
+    text_history,
+    text_diff,
+    revert_text,
+    retranslate_text,
+    section_blame,
 
     // metadata
     get_metadata,
+    edit_metadata_form,
+    edit_metadata_put,
+
+    // taxonomy
+    taxonomy_index,
+    get_taxonomy_entry,
+    create_taxonomy_entry,
+    edit_taxonomy_entry_form,
+    edit_taxonomy_entry_post,
+    delete_taxonomy_entry,
+
+    // keyword suggestions
+    keyword_suggestions_index,
+    accept_keyword_suggestion,
+    reject_keyword_suggestion,
+    suggest_keywords,
+
+    // section locks
+    get_section_lock,
+    lock_section,
+    break_section_lock,
+
+    // live updates
+    document_socket,
+
+    // background jobs
+    job_index,
+    retry_job,
+
+    // translations
+    translation_queue,
+    translation_suggestions,
+
+    // termbase
+    term_index,
+    create_term,
+    edit_term_form,
+    edit_term_post,
+    delete_term,
+
+    // approvals
+    get_approvals,
+    add_approver,
+    remove_approver,
+    approval_decision,
+
+    // upload_files
+    import_docx_form,
+    import_docx,
+    import_xliff_form,
+    import_xliff,
+    import_terms,
+
+    // search
+    search_page,
 
 };
 
@@ -98,6 +163,7 @@ pub fn configure_services(config: &mut web::ServiceConfig) {
     config.service(index);
     config.service(raw_index);
     config.service(template_api);
+    config.service(search_api);
     //config.service(about);
     config.service(toggle_language);
     config.service(toggle_language_index);
@@ -161,8 +227,11 @@ pub fn configure_services(config: &mut web::ServiceConfig) {
      // documents
      config.service(document_index);
      config.service(get_document);
+     config.service(get_document_docx);
+     config.service(export_xliff);
      config.service(edit_document_sections);
-     config.service(toggle_document_published);
+     config.service(bilingual_document_sections);
+     config.service(transition_document);
      
      // document_core
      config.service(create_document_core_form);
@@ -176,7 +245,68 @@ pub fn configure_services(config: &mut web::ServiceConfig) {
      config.service(create_new_text);
      config.service(edit_text_form);
      config.service(edit_text_put);
+     config.service(cancel_edit_text);
+     config.service(text_history);
+     config.service(text_diff);
+     config.service(revert_text);
+     config.service(retranslate_text);
+     config.service(section_blame);
 
     // metadata
     config.service(get_metadata);
+    config.service(edit_metadata_form);
+    config.service(edit_metadata_put);
+
+    // taxonomy
+    config.service(taxonomy_index);
+    config.service(get_taxonomy_entry);
+    config.service(create_taxonomy_entry);
+    config.service(edit_taxonomy_entry_form);
+    config.service(edit_taxonomy_entry_post);
+    config.service(delete_taxonomy_entry);
+
+    // keyword suggestions
+    config.service(keyword_suggestions_index);
+    config.service(accept_keyword_suggestion);
+    config.service(reject_keyword_suggestion);
+    config.service(suggest_keywords);
+
+    // section locks
+    config.service(get_section_lock);
+    config.service(lock_section);
+    config.service(break_section_lock);
+
+    // live updates
+    config.service(document_socket);
+
+    // background jobs
+    config.service(job_index);
+    config.service(retry_job);
+
+    // translations
+    config.service(translation_queue);
+    config.service(translation_suggestions);
+
+    // termbase
+    config.service(term_index);
+    config.service(create_term);
+    config.service(edit_term_form);
+    config.service(edit_term_post);
+    config.service(delete_term);
+
+    // approvals
+    config.service(get_approvals);
+    config.service(add_approver);
+    config.service(remove_approver);
+    config.service(approval_decision);
+
+    // upload_files
+    config.service(import_docx_form);
+    config.service(import_docx);
+    config.service(import_xliff_form);
+    config.service(import_xliff);
+    config.service(import_terms);
+
+    // search
+    config.service(search_page);
 }
@@ -0,0 +1,45 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web, ResponseError};
+use actix_identity::{Identity};
+
+use crate::{AppData, generate_basic_context, get_clearance};
+use crate::models::{SearchResult, SearchFilters};
+
+// Results shown on the search page and returned by the api
+pub const SEARCH_LIMIT: usize = 25;
+
+#[get("/{lang}/search/{document_view}")]
+/// Search and filter documents. The query string holds the whole search so it can be bookmarked.
+pub async fn search_page(
+    data: web::Data<AppData>,
+    web::Path((lang, document_view)): web::Path<(String, String)>,
+    filters: web::Query<SearchFilters>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() &&
+        document_view == "internal" {
+            // send to external view
+            return HttpResponse::Found().header(
+                "Location",
+                format!("/{}/search/open?{}", lang, req.query_string())).finish()
+    };
+
+    let search = match SearchResult::search(&filters, &lang, document_view == "internal", &get_clearance(&session_user), SEARCH_LIMIT) {
+        Ok(s) => s,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("query", filters.query());
+    ctx.insert("filters", &filters.into_inner());
+    ctx.insert("search", &search);
+    ctx.insert("document_view", &document_view);
+
+    let rendered = data.tmpl.render("search/search.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
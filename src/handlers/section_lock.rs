@@ -0,0 +1,103 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context};
+use crate::models::{SectionLock, User};
+use crate::errors::CustomError;
+
+#[get("/{lang}/section_lock/{section_id}")]
+/// Who is editing a section, polled by the section partials
+pub async fn get_section_lock(
+    data: web::Data<AppData>,
+    web::Path((lang, section_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let lock = SectionLock::get_active(section_id).expect("Unable to load section lock");
+
+    ctx.insert("section_id", &section_id);
+    ctx.insert("lock", &lock);
+
+    let rendered = data.tmpl.render("sections/section_lock.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/lock_section/{section_id}")]
+/// Take or renew the lock on a section. Sent when an editor opens or types in a section.
+pub async fn lock_section(
+    data: web::Data<AppData>,
+    web::Path((lang, section_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+    if let Err(err) = SectionLock::acquire(section_id, user.id) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let lock = SectionLock::get_active(section_id).expect("Unable to load section lock");
+
+    ctx.insert("section_id", &section_id);
+    ctx.insert("lock", &lock);
+
+    let rendered = data.tmpl.render("sections/section_lock.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/break_section_lock/{section_id}")]
+/// Remove someone else's lock, e.g. when they left a section open
+pub async fn break_section_lock(
+    data: web::Data<AppData>,
+    web::Path((lang, section_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = SectionLock::break_lock(section_id) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    ctx.insert("section_id", &section_id);
+    ctx.insert("lock", &None::<()>);
+
+    let rendered = data.tmpl.render("sections/section_lock.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
@@ -0,0 +1,193 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context};
+use crate::models::{TaxonomyEntry, InsertableTaxonomyEntry, TAXONOMIES};
+use super::TaxonomyForm;
+use crate::errors::CustomError;
+
+#[get("/{lang}/taxonomy/{taxonomy}")]
+/// List the subjects, categories or keywords. Admins can add, edit and delete entries.
+pub async fn taxonomy_index(
+    data: web::Data<AppData>,
+    web::Path((lang, taxonomy)): web::Path<(String, String)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, _role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    let entries = match TaxonomyEntry::get_all(&taxonomy) {
+        Ok(e) => e,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let readable_entries: Vec<_> = entries.iter().map(|e| e.to_readable(&taxonomy, &lang)).collect();
+
+    ctx.insert("taxonomy", &taxonomy);
+    ctx.insert("taxonomies", &TAXONOMIES);
+    ctx.insert("entries", &readable_entries);
+
+    let rendered = data.tmpl.render("taxonomy/taxonomy_index.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[get("/{lang}/taxonomy/{taxonomy}/{entry_id}")]
+pub async fn get_taxonomy_entry(
+    data: web::Data<AppData>,
+    web::Path((lang, taxonomy, entry_id)): web::Path<(String, String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, _role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    let entry = match TaxonomyEntry::get_by_id(&taxonomy, entry_id) {
+        Ok(e) => e,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    // Documents with this entry are found through the matching search facet
+    let facet = match taxonomy.as_str() {
+        "subjects" => "subject_id",
+        "categories" => "category_id",
+        _ => "keyword_id",
+    };
+
+    ctx.insert("taxonomy", &taxonomy);
+    ctx.insert("entry", &entry.to_readable(&taxonomy, &lang));
+    ctx.insert("facet", &facet);
+
+    let rendered = data.tmpl.render("taxonomy/taxonomy_entry.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/create_taxonomy_entry/{taxonomy}")]
+pub async fn create_taxonomy_entry(
+    web::Path((lang, taxonomy)): web::Path<(String, String)>,
+    form: web::Form<TaxonomyForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let insertable_entry = InsertableTaxonomyEntry::new(
+        &form.en_string,
+        &form.fr_string,
+        &form.en_description,
+        &form.fr_description,
+    );
+
+    if let Err(err) = TaxonomyEntry::create(&taxonomy, &insertable_entry) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/taxonomy/{}", lang, taxonomy)).finish()
+}
+
+#[get("/{lang}/edit_taxonomy_entry/{taxonomy}/{entry_id}")]
+pub async fn edit_taxonomy_entry_form(
+    data: web::Data<AppData>,
+    web::Path((lang, taxonomy, entry_id)): web::Path<(String, String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let entry = match TaxonomyEntry::get_by_id(&taxonomy, entry_id) {
+        Ok(e) => e,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("taxonomy", &taxonomy);
+    ctx.insert("entry", &entry);
+
+    let rendered = data.tmpl.render("taxonomy/edit_taxonomy_entry.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/edit_taxonomy_entry/{taxonomy}/{entry_id}")]
+pub async fn edit_taxonomy_entry_post(
+    web::Path((lang, taxonomy, entry_id)): web::Path<(String, String, Uuid)>,
+    form: web::Form<TaxonomyForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let insertable_entry = InsertableTaxonomyEntry::new(
+        &form.en_string,
+        &form.fr_string,
+        &form.en_description,
+        &form.fr_description,
+    );
+
+    if let Err(err) = TaxonomyEntry::update(&taxonomy, entry_id, &insertable_entry) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/taxonomy/{}", lang, taxonomy)).finish()
+}
+
+#[post("/{lang}/delete_taxonomy_entry/{taxonomy}/{entry_id}")]
+/// Delete an entry and remove it from the metadata of any documents using it
+pub async fn delete_taxonomy_entry(
+    web::Path((lang, taxonomy, entry_id)): web::Path<(String, String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = TaxonomyEntry::delete(&taxonomy, entry_id) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/taxonomy/{}", lang, taxonomy)).finish()
+}
@@ -0,0 +1,174 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context};
+use crate::models::{Term, InsertableTerm, User, Job, parse_variants};
+use super::TermForm;
+use crate::errors::CustomError;
+
+#[get("/{lang}/terms")]
+/// List the approved English and French terms. Admins can add, edit, delete and import terms.
+pub async fn term_index(
+    data: web::Data<AppData>,
+    web::Path(lang): web::Path<String>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, _role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    let terms = match Term::get_all() {
+        Ok(t) => t,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("terms", &terms);
+
+    let rendered = data.tmpl.render("terms/term_index.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/create_term")]
+pub async fn create_term(
+    web::Path(lang): web::Path<String>,
+    form: web::Form<TermForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+    let insertable_term = InsertableTerm::new(
+        &form.en_term,
+        &form.fr_term,
+        parse_variants(&form.en_deprecated),
+        parse_variants(&form.fr_deprecated),
+        &form.note,
+        user.id,
+    );
+
+    if let Err(err) = Term::create(&insertable_term) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    Job::enqueue_terminology_check().expect("Unable to queue terminology check");
+
+    HttpResponse::Found().header("Location", format!("/{}/terms", lang)).finish()
+}
+
+#[get("/{lang}/edit_term/{term_id}")]
+pub async fn edit_term_form(
+    data: web::Data<AppData>,
+    web::Path((lang, term_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let term = match Term::get_by_id(term_id) {
+        Ok(t) => t,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("term", &term);
+
+    let rendered = data.tmpl.render("terms/edit_term.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/edit_term/{term_id}")]
+pub async fn edit_term_post(
+    web::Path((lang, term_id)): web::Path<(String, Uuid)>,
+    form: web::Form<TermForm>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let term = match Term::get_by_id(term_id) {
+        Ok(t) => t,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let insertable_term = InsertableTerm::new(
+        &form.en_term,
+        &form.fr_term,
+        parse_variants(&form.en_deprecated),
+        parse_variants(&form.fr_deprecated),
+        &form.note,
+        term.created_by_id,
+    );
+
+    if let Err(err) = Term::update(term_id, &insertable_term) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    Job::enqueue_terminology_check().expect("Unable to queue terminology check");
+
+    HttpResponse::Found().header("Location", format!("/{}/terms", lang)).finish()
+}
+
+#[post("/{lang}/delete_term/{term_id}")]
+/// Delete a term along with any warnings about it
+pub async fn delete_term(
+    web::Path((lang, term_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    if let Err(err) = Term::delete(term_id) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/terms", lang)).finish()
+}
@@ -1,10 +1,12 @@
 use actix_web::{HttpRequest, HttpResponse, Responder, get, post, put, web, ResponseError};
 use actix_identity::{Identity};
+use serde::Deserialize;
+use tera::Context;
 use uuid::Uuid;
 
-use crate::{AppData, generate_basic_context};
+use crate::{AppData, generate_basic_context, get_clearance};
 use crate::handlers::TextForm;
-use crate::models::{Text, LatestText, InsertableText, User};
+use crate::models::{Text, LatestText, TextVersion, SectionLock, InsertableText, User, Section, ReadableTextVersion, BlameParagraph, Job, TermWarning, ReadableTermWarning, QaIssue, check_clearance};
 use crate::errors::CustomError;
 
 #[get("/{lang}/text/{text_id}/{document_view}")]
@@ -15,7 +17,7 @@ pub async fn get_text(
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() {
@@ -27,21 +29,73 @@ pub async fn get_text(
         return err.error_response()
     } else {
 
-        // Determine view of text to render
-        let redact = match document_view.as_str() {
-            "internal" => false,
-            _ => true,
+        let text = match Text::get_text_by_id(text_id, &lang) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
         };
 
-        let text = Text::get_text_by_id(text_id, &lang).expect("Unable to retrieve text");
+        if let Err(err) = text.check_clearance(&get_clearance(&session_user)) {
+            println!("{}", &err);
+            return err.error_response()
+        };
 
-        let text = LatestText::get_from(text, true, redact);
+        render_text(&data, &mut ctx, text, &lang, &document_view)
+    }
+}
 
-        ctx.insert("text", &text);
-        ctx.insert("document_view", &document_view);
+#[post("/{lang}/cancel_edit_text/{text_id}/{document_view}")]
+/// Close the editor without saving and give up the user's lock on the section
+pub async fn cancel_edit_text(
+    data: web::Data<AppData>,
+    web::Path((lang, text_id, document_view)): web::Path<(String, Uuid, String)>,
+    
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
 
-        let rendered = data.tmpl.render("texts/text.html", &ctx).unwrap();
-        HttpResponse::Ok().body(rendered)
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    } else {
+
+        let text = match Text::get_text_by_id(text_id, &lang) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if let Err(err) = text.check_clearance(&get_clearance(&session_user)) {
+            println!("{}", &err);
+            return err.error_response()
+        };
+
+        if let Some(section_id) = text.section_id {
+            let user = match User::find_from_slug(&session_user) {
+                Ok(u) => u,
+                Err(err) => {
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
+
+            if let Err(err) = SectionLock::release(section_id, user.id) {
+                println!("{}", &err);
+                return err.error_response()
+            };
+        };
+
+        render_text(&data, &mut ctx, text, &lang, &document_view)
     }
 }
 
@@ -75,13 +129,40 @@ pub async fn create_new_text(
 
         println!("Saving text: {}", content);
 
-        let user = User::find_from_slug(&session_user).expect("Unable to find user");
+        let user = match User::find_from_slug(&session_user) {
+            Ok(u) => u,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        let classification = match Section::get_classification(section_id) {
+            Ok(c) => c,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if let Err(err) = check_clearance(&user.clearance, &classification) {
+            println!("{}", &err);
+            return err.error_response()
+        };
 
         let insertable_text = InsertableText::new(Some(section_id), &lang, content.to_string(), user.id);
 
         println!("Saving text: {:?}", &insertable_text.content);
 
-        let text = Text::create(&insertable_text, machine_translate).expect("Unable to create text");
+        let text = match Text::create(&insertable_text, machine_translate) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        let text = LatestText::get_from(text, true, false);
 
         println!("Saved!");
 
@@ -100,7 +181,7 @@ pub async fn edit_text_form(
     id: Identity,
     req:HttpRequest) -> impl Responder {
 
-    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
 
     if role != "user".to_string() &&
         role != "admin".to_string() {
@@ -112,12 +193,46 @@ pub async fn edit_text_form(
         return err.error_response()
     } else {
 
-        let text = Text::get_text_by_id(text_id, &lang).expect("Unable to retrieve text");
+        let text = match Text::get_text_by_id(text_id, &lang) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if let Err(err) = text.check_clearance(&get_clearance(&session_user)) {
+            println!("{}", &err);
+            return err.error_response()
+        };
+
+        // opening a section for editing takes its lock unless someone else is editing it
+        if let Some(section_id) = text.section_id {
+            let user = match User::find_from_slug(&session_user) {
+                Ok(u) => u,
+                Err(err) => {
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
+
+            let lock = match SectionLock::acquire(section_id, user.id)
+                .and_then(|_| SectionLock::get_active(section_id)) {
+                    Ok(l) => l,
+                    Err(err) => {
+                        println!("{}", &err);
+                        return err.error_response()
+                    },
+                };
+
+            ctx.insert("lock", &lock);
+        };
 
         let text = LatestText::get_from(text, false, false);
 
         ctx.insert("text", &text);
         ctx.insert("document_view", &document_view);
+        ctx.insert("source_version", &other_lang_head(text_id, &lang));
 
         let rendered = data.tmpl.render("texts/edit_text.html", &ctx).unwrap();
         HttpResponse::Ok().body(rendered)
@@ -153,16 +268,99 @@ pub async fn edit_text_put(
             _ => false,
         };
 
-        let user = User::find_from_slug(&session_user).expect("Unable to find user");
+        let user = match User::find_from_slug(&session_user) {
+            Ok(u) => u,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        let current_text = match Text::get_text_by_id(text_id, &lang) {
+            Ok(t) => t,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+        if let Err(err) = current_text.check_clearance(&user.clearance) {
+            println!("{}", &err);
+            return err.error_response()
+        };
 
         println!("Updating text: {:?}", &content);
 
-        let text = Text::update(
+        let text = match Text::update_from_version(
             text_id, 
             content.to_string(), 
             &lang, user.id, 
-            machine_translate)
-            .expect("Unable to update Text");
+            machine_translate,
+            form.base_version,
+            form.source_version) {
+                Ok(t) => t,
+                // someone saved since this edit started: send the editor back with their change
+                Err(err) if err.is_edit_conflict() => {
+                    println!("{}", &err);
+
+                    let head = LatestText::get_from(current_text, false, false);
+
+                    let base_version = form.base_version.unwrap_or(head.version_number);
+
+                    let diff = match TextVersion::get_all(text_id, &lang).and_then(|versions| {
+                        match versions.iter().find(|v| v.version_number == base_version) {
+                            Some(base) => base.diff(versions.last().unwrap()),
+                            None => Ok(Vec::new()),
+                        }
+                    }) {
+                        Ok(d) => d,
+                        Err(err) => {
+                            println!("{}", &err);
+                            return err.error_response()
+                        },
+                    };
+
+                    let mut text = head;
+                    text.content = content.to_string();
+
+                    ctx.insert("text", &text);
+                    ctx.insert("document_view", &document_view);
+                    ctx.insert("conflict", &true);
+                    ctx.insert("from", &base_version);
+                    ctx.insert("to", &text.version_number);
+                    ctx.insert("diff", &diff);
+                    ctx.insert("source_version", &other_lang_head(text_id, &lang));
+
+                    let rendered = data.tmpl.render("texts/edit_text.html", &ctx).unwrap();
+                    return HttpResponse::Conflict().body(rendered)
+                },
+                // the document is published: send the editor back with the reason
+                Err(err) if err.is_qa_blocked() => {
+                    println!("{}", &err);
+
+                    let mut text = LatestText::get_from(current_text, false, false);
+                    text.content = content.to_string();
+
+                    ctx.insert("text", &text);
+                    ctx.insert("document_view", &document_view);
+                    ctx.insert("qa_blocked", &true);
+                    ctx.insert("source_version", &other_lang_head(text_id, &lang));
+
+                    let rendered = data.tmpl.render("texts/edit_text.html", &ctx).unwrap();
+                    return HttpResponse::UnprocessableEntity().body(rendered)
+                },
+                Err(err) => {
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+        };
+
+        if let Some(section_id) = text.section_id {
+            if let Err(err) = SectionLock::release(section_id, user.id) {
+                println!("{}", &err);
+                return err.error_response()
+            };
+        };
 
         // Determine view of text to render
         let redact = match document_view.as_str() {
@@ -176,10 +374,342 @@ pub async fn edit_text_put(
 
         ctx.insert("text", &text);
         ctx.insert("document_view", &document_view);
+        ctx.insert("translation_status", &translation_status(text.id));
+        ctx.insert("translation_out_of_date", &translation_out_of_date(text.id, &lang));
+        ctx.insert("text_term_warnings", &term_warnings(text.id, &lang));
+        ctx.insert("text_qa_issues", &qa_issues(text.id, &lang));
 
         let rendered = data.tmpl.render("texts/text.html", &ctx).unwrap();
         HttpResponse::Ok()
             .header("HX-Trigger", "textUpdate")
             .body(rendered)
     }
-}
\ No newline at end of file
+}
+#[derive(Deserialize, Debug)]
+pub struct TextDiffParams {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[get("/{lang}/text_history/{text_id}")]
+/// Revisions of a text with a diff of the latest change
+pub async fn text_history(
+    data: web::Data<AppData>,
+    web::Path((lang, text_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let text = match Text::get_text_by_id(text_id, &lang) {
+        Ok(t) => t,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let versions = match text.get_versions(&get_clearance(&session_user)) {
+        Ok(v) => v,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let readable_versions = ReadableTextVersion::from_versions(&versions).expect("Unable to load authors");
+
+    // Compare the head with the revision before it
+    let to = versions.last().expect("Text has no revisions");
+    let from = versions.iter().rev().nth(1).unwrap_or(to);
+
+    let diff = from.diff(to).expect("Unable to decrypt revisions");
+
+    let document_id = text.section_id
+        .and_then(|id| Section::get_by_id(id).ok())
+        .map(|s| s.document_id);
+
+    ctx.insert("text_id", &text_id);
+    ctx.insert("section_id", &text.section_id);
+    ctx.insert("document_id", &document_id);
+    ctx.insert("versions", &readable_versions);
+    ctx.insert("head", &to.version_number);
+    ctx.insert("from", &from.version_number);
+    ctx.insert("to", &to.version_number);
+    ctx.insert("diff", &diff);
+
+    let rendered = data.tmpl.render("texts/text_history.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[get("/{lang}/text_diff/{text_id}")]
+/// Word-level diff between two revisions of a text
+pub async fn text_diff(
+    data: web::Data<AppData>,
+    web::Path((lang, text_id)): web::Path<(String, Uuid)>,
+    params: web::Query<TextDiffParams>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let versions = match Text::get_text_by_id(text_id, &lang)
+        .and_then(|t| t.get_versions(&get_clearance(&session_user))) {
+        Ok(v) => v,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let from = versions.iter().find(|v| v.version_number == params.from);
+    let to = versions.iter().find(|v| v.version_number == params.to);
+
+    let diff = match (from, to) {
+        (Some(from), Some(to)) => from.diff(to),
+        _ => Err(CustomError::new(408, "Revision not found".to_string())),
+    };
+
+    let diff = match diff {
+        Ok(d) => d,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("from", &params.from);
+    ctx.insert("to", &params.to);
+    ctx.insert("diff", &diff);
+
+    let rendered = data.tmpl.render("texts/text_diff.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/revert_text/{text_id}/{version_number}")]
+/// Restore an earlier revision as a new revision
+pub async fn revert_text(
+    web::Path((lang, text_id, version_number)): web::Path<(String, Uuid, i32)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (_ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let user = match User::find_from_slug(&session_user) {
+        Ok(u) => u,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    if let Err(err) = Text::revert(text_id, &lang, version_number, user.id, &user.clearance) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    HttpResponse::Found().header("Location", format!("/{}/text_history/{}", lang, text_id)).finish()
+}
+
+#[post("/{lang}/retranslate_text/{text_id}")]
+/// Replace the text in lang with a machine translation of the other language
+pub async fn retranslate_text(
+    data: web::Data<AppData>,
+    web::Path((lang, text_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let user = match User::find_from_slug(&session_user) {
+        Ok(u) => u,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    if let Err(err) = Text::retranslate(text_id, &lang, &user.clearance) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let text = match Text::get_text_by_id(text_id, &lang) {
+        Ok(t) => t,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let text = LatestText::get_from(text, true, false);
+
+    let translation_status = Job::get_translation_statuses_into(vec![text_id], &lang)
+        .expect("Unable to load translation jobs")
+        .remove(&text_id);
+
+    ctx.insert("text", &text);
+    ctx.insert("document_view", "internal");
+    ctx.insert("translation_status", &translation_status);
+    ctx.insert("translation_out_of_date", &translation_out_of_date(text_id, &lang));
+    ctx.insert("text_term_warnings", &term_warnings(text_id, &lang));
+    ctx.insert("text_qa_issues", &qa_issues(text_id, &lang));
+
+    let rendered = data.tmpl.render("texts/text.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[get("/{lang}/section_blame/{section_id}")]
+/// Who last changed each paragraph of a section and when
+pub async fn section_blame(
+    data: web::Data<AppData>,
+    web::Path((lang, section_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let text = match Text::get_text_by_section_id(section_id, &lang) {
+        Ok(t) => t,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let paragraphs = match text.get_versions(&get_clearance(&session_user))
+        .and_then(|versions| BlameParagraph::from_versions(&versions)) {
+        Ok(p) => p,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let section = match Section::get_by_id(section_id) {
+        Ok(s) => s,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("text_id", &text.id);
+    ctx.insert("document_id", &section.document_id);
+    ctx.insert("paragraphs", &paragraphs);
+
+    let rendered = data.tmpl.render("texts/section_blame.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+// The text partial with its translation status, term warnings and QA issues
+fn render_text(data: &AppData, ctx: &mut Context, text: Text, lang: &str, document_view: &str) -> HttpResponse {
+
+    // Determine view of text to render
+    let redact = match document_view {
+        "internal" => false,
+        _ => true,
+    };
+
+    let text = LatestText::get_from(text, true, redact);
+
+    ctx.insert("text", &text);
+    ctx.insert("document_view", &document_view);
+    ctx.insert("translation_status", &translation_status(text.id));
+    ctx.insert("translation_out_of_date", &translation_out_of_date(text.id, lang));
+    ctx.insert("text_term_warnings", &term_warnings(text.id, lang));
+    ctx.insert("text_qa_issues", &qa_issues(text.id, lang));
+
+    let rendered = data.tmpl.render("texts/text.html", ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+// Whether a machine translation of the text is still queued or has failed
+fn translation_status(text_id: Uuid) -> Option<String> {
+    Job::get_translation_statuses(vec![text_id])
+        .expect("Unable to load translation jobs")
+        .remove(&text_id)
+}
+
+// Whether the text needs translating again from the other language
+fn translation_out_of_date(text_id: Uuid, lang: &str) -> bool {
+    TextVersion::get_out_of_date(vec![text_id], lang)
+        .expect("Unable to compare translations")
+        .contains(&text_id)
+}
+
+// Terms the text doesn't render as the termbase approves
+fn term_warnings(text_id: Uuid, lang: &str) -> Vec<ReadableTermWarning> {
+    TermWarning::get_map(vec![text_id], lang)
+        .expect("Unable to load term warnings")
+        .remove(&text_id)
+        .unwrap_or_default()
+}
+
+// Where the text differs from the other language in numbers, dates, links, markdown or redactions
+fn qa_issues(text_id: Uuid, lang: &str) -> Vec<QaIssue> {
+    QaIssue::get_map(vec![text_id], lang)
+        .expect("Unable to compare translations")
+        .remove(&text_id)
+        .unwrap_or_default()
+}
+
+// Head revision of the text in the other language, offered to editors translating from it
+fn other_lang_head(text_id: Uuid, lang: &str) -> Option<i32> {
+    let other_lang = match lang {
+        "fr" => "en",
+        _ => "fr",
+    };
+
+    TextVersion::get_head(text_id, other_lang).ok().map(|h| h.version_number)
+}
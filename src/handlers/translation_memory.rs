@@ -0,0 +1,49 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web, ResponseError};
+use actix_identity::{Identity};
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context, get_clearance};
+use crate::models::{Text, TranslationPair};
+use crate::errors::CustomError;
+
+// Matches shown for each sentence
+const SUGGESTIONS_PER_SEGMENT: usize = 3;
+
+#[get("/{lang}/translation_suggestions/{text_id}")]
+/// Translation memory matches for the sentences of the other language, shown while editing a text in lang
+pub async fn translation_suggestions(
+    data: web::Data<AppData>,
+    web::Path((lang, text_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let clearance = get_clearance(&session_user);
+
+    let suggestions = match Text::get_text_by_id(text_id, &lang)
+        .and_then(|t| t.check_clearance(&clearance))
+        .and_then(|_| TranslationPair::suggest_for_text(text_id, &lang, &clearance, SUGGESTIONS_PER_SEGMENT)) {
+            Ok(s) => s,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+    ctx.insert("text_id", &text_id);
+    ctx.insert("suggestions", &suggestions);
+
+    let rendered = data.tmpl.render("texts/translation_suggestions.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
@@ -0,0 +1,40 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web, ResponseError};
+use actix_identity::{Identity};
+
+use crate::{AppData, generate_basic_context, get_clearance};
+use crate::models::{StaleTranslation};
+use crate::errors::CustomError;
+
+#[get("/{lang}/translation_queue")]
+/// Sections whose translation is out of date, most important documents first
+pub async fn translation_queue(
+    data: web::Data<AppData>,
+    web::Path(lang): web::Path<String>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let queue = match StaleTranslation::get_queue(&lang, &get_clearance(&session_user)) {
+        Ok(q) => q,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("queue", &queue);
+
+    let rendered = data.tmpl.render("texts/translation_queue.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
@@ -1,35 +1,366 @@
-use actix_web::{post, web, Responder, HttpResponse, Error};
+use std::collections::BTreeMap;
+
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web, ResponseError};
+use actix_identity::{Identity};
 use actix_multipart::Multipart;
-use serde::{Serialize, Deserialize};
 use futures::{StreamExt, TryStreamExt};
-use docx_rs::*;
-use std::io::Write;
+use uuid::Uuid;
+
+use crate::{AppData, generate_basic_context, get_clearance};
+use crate::docx::{parse_docx, import_docx_document};
+use crate::xliff::{parse_xliff, import_document_xliff};
+use crate::termbase::parse_terms;
+use crate::models::{Template, Document, User, Term, Job, validate_classification, check_clearance};
+use crate::errors::CustomError;
+
+// Legacy briefing notes are small, but embedded images can make them large
+const MAX_DOCX_BYTES: usize = 20 * 1024 * 1024;
+
+// Translated files are text only
+const MAX_XLIFF_BYTES: usize = 10 * 1024 * 1024;
+
+// Termbases are text only too
+const MAX_TERMBASE_BYTES: usize = 10 * 1024 * 1024;
 
-use actix_web::{middleware, App, HttpServer};
-use std::io::*;
+#[get("/{lang}/import_docx_form/{template_id}")]
+/// Form to upload a .docx file and create a document from a template
+pub async fn import_docx_form(
+    data: web::Data<AppData>,
+    web::Path((lang, template_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, _session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    } else {
+
+        let (template, sections) = Template::get_readable_by_id(template_id, &lang).expect("Unable to load template");
+
+        let mut ordered_sections = BTreeMap::new();
+
+        for (_k, v) in sections {
+            ordered_sections.insert(v.order_number, v);
+        }
+
+        ctx.insert("template", &template);
+        ctx.insert("sections", &ordered_sections);
+
+        let rendered = data.tmpl.render("document_core/import_docx.html", &ctx).unwrap();
+        HttpResponse::Ok().body(rendered)
+    }
+}
+
+#[post("/{lang}/import_docx/{template_id}")]
+/// Parse an uploaded .docx file, map its headings to the template sections
+/// and create the document. Reports headings that didn't match a section.
+pub async fn import_docx(
+    data: web::Data<AppData>,
+    web::Path((lang, template_id)): web::Path<(String, Uuid)>,
+    mut payload: Multipart,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let mut fields: BTreeMap<String, String> = BTreeMap::new();
+    let mut file_bytes: Vec<u8> = Vec::new();
 
-#[post("/upload_files")]
-async fn upload_file(mut payload: Multipart, file_path: String) -> Option<bool> {
     // iterate over multipart stream
     while let Ok(Some(mut field)) = payload.try_next().await {
-        let content_type = field.content_disposition().unwrap();
-        //let filename = content_type.get_filename().unwrap();
-        let filepath = format!(".{}", file_path);
+        let name = field.content_disposition()
+            .and_then(|cd| cd.get_name().map(|n| n.to_string()))
+            .unwrap_or_default();
 
-        // File::create is blocking operation, use threadpool
-        let mut f = web::block(|| std::fs::File::create(filepath))
-            .await
-            .unwrap();
+        let mut bytes: Vec<u8> = Vec::new();
 
         // Field in turn is stream of *Bytes* object
         while let Some(chunk) = field.next().await {
-            let data = chunk.unwrap();
-            // filesystem operations are blocking, we have to use threadpool
-            f = web::block(move || f.write_all(&data).map(|_| f))
-                .await
-                .unwrap();
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    let err = CustomError::new(400, format!("Unable to read upload: {}", e));
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
+
+            if bytes.len() + chunk.len() > MAX_DOCX_BYTES {
+                let err = CustomError::new(413, "Uploaded file is too large".to_string());
+                println!("{}", &err);
+                return err.error_response()
+            };
+
+            bytes.extend_from_slice(&chunk);
         }
+
+        if name == "file" {
+            file_bytes = bytes;
+        } else {
+            fields.insert(name, String::from_utf8_lossy(&bytes).to_string());
+        };
     }
 
-    Some(true)
-}
\ No newline at end of file
+    let parsed = match parse_docx(&file_bytes) {
+        Ok(p) => p,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let machine_translate = match fields.get("machine_translate").map(|s| s.as_str()) {
+        Some("true") => true,
+        _ => false,
+    };
+
+    let security_classification = fields.get("security_classification")
+        .cloned()
+        .unwrap_or_else(|| "unclassified".to_string());
+
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+    if let Err(err) = validate_classification(&security_classification)
+        .and_then(|_| check_clearance(&user.clearance, &security_classification)) {
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let report = match import_docx_document(
+        &parsed,
+        template_id,
+        fields.get("title").cloned().unwrap_or_default(),
+        fields.get("purpose").cloned().unwrap_or_default(),
+        &lang,
+        user.id,
+        security_classification,
+        machine_translate,
+    ) {
+        Ok(r) => r,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("report", &report);
+
+    let rendered = data.tmpl.render("document_core/import_docx_report.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[get("/{lang}/import_xliff_form/{document_id}")]
+/// Form to upload a translated XLIFF file for a document
+pub async fn import_xliff_form(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id)): web::Path<(String, Uuid)>,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let (document, _sections) = match Document::get_all_readable_by_id(
+        document_id, &lang, false, false, &get_clearance(&session_user)) {
+            Ok(d) => d,
+            Err(err) => {
+                println!("{}", &err);
+                return err.error_response()
+            },
+        };
+
+    ctx.insert("document", &document);
+
+    let rendered = data.tmpl.render("document_core/import_xliff.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/import_xliff/{document_id}")]
+/// Save the targets of an uploaded XLIFF file as human translations of the document's texts.
+/// Reports units that were imported, unchanged or skipped.
+pub async fn import_xliff(
+    data: web::Data<AppData>,
+    web::Path((lang, document_id)): web::Path<(String, Uuid)>,
+    mut payload: Multipart,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "user".to_string() &&
+        role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let mut file_bytes: Vec<u8> = Vec::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let name = field.content_disposition()
+            .and_then(|cd| cd.get_name().map(|n| n.to_string()))
+            .unwrap_or_default();
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    let err = CustomError::new(400, format!("Unable to read upload: {}", e));
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
+
+            if bytes.len() + chunk.len() > MAX_XLIFF_BYTES {
+                let err = CustomError::new(413, "Uploaded file is too large".to_string());
+                println!("{}", &err);
+                return err.error_response()
+            };
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        if name == "file" {
+            file_bytes = bytes;
+        };
+    }
+
+    let parsed = match parse_xliff(&file_bytes) {
+        Ok(p) => p,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+    let report = match import_document_xliff(document_id, &parsed, user.id, &user.clearance) {
+        Ok(r) => r,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    ctx.insert("report", &report);
+
+    let rendered = data.tmpl.render("document_core/import_xliff_report.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
+
+#[post("/{lang}/import_terms")]
+/// Add the terms of an uploaded .csv or .tbx file to the termbase.
+/// Reports terms that were created, updated or unchanged.
+pub async fn import_terms(
+    data: web::Data<AppData>,
+    web::Path(lang): web::Path<String>,
+    mut payload: Multipart,
+    id: Identity,
+    req:HttpRequest) -> impl Responder {
+
+    let (mut ctx, session_user, role, _lang) = generate_basic_context(id, &lang, req.uri().path());
+
+    if role != "admin".to_string() {
+        let err = CustomError::new(
+            406,
+            "Not authorized".to_string(),
+        );
+        println!("{}", &err);
+        return err.error_response()
+    };
+
+    let mut file_name = String::new();
+    let mut file_bytes: Vec<u8> = Vec::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let (name, field_file_name) = field.content_disposition()
+            .map(|cd| (
+                cd.get_name().unwrap_or_default().to_string(),
+                cd.get_filename().unwrap_or_default().to_string(),
+            ))
+            .unwrap_or_default();
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    let err = CustomError::new(400, format!("Unable to read upload: {}", e));
+                    println!("{}", &err);
+                    return err.error_response()
+                },
+            };
+
+            if bytes.len() + chunk.len() > MAX_TERMBASE_BYTES {
+                let err = CustomError::new(413, "Uploaded file is too large".to_string());
+                println!("{}", &err);
+                return err.error_response()
+            };
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        if name == "file" {
+            file_name = field_file_name;
+            file_bytes = bytes;
+        };
+    }
+
+    let user = User::find_from_slug(&session_user).expect("Unable to find user");
+
+    let entries = match parse_terms(&file_name, &file_bytes, user.id) {
+        Ok(e) => e,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    let report = match Term::import(&entries) {
+        Ok(r) => r,
+        Err(err) => {
+            println!("{}", &err);
+            return err.error_response()
+        },
+    };
+
+    Job::enqueue_terminology_check().expect("Unable to queue terminology check");
+
+    ctx.insert("report", &report);
+
+    let rendered = data.tmpl.render("terms/term_import_report.html", &ctx).unwrap();
+    HttpResponse::Ok().body(rendered)
+}
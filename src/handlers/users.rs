@@ -5,8 +5,8 @@ use actix_identity::{Identity};
 use inflector::Inflector;
 use serde::{Deserialize};
 
-use crate::{AppData, extract_identity_data, generate_basic_context};
-use crate::models::{User, Document};
+use crate::{AppData, extract_identity_data, generate_basic_context, get_clearance};
+use crate::models::{User, Document, SECURITY_CLASSIFICATIONS, validate_classification};
 use crate::handlers::DeleteForm;
 use crate::errors::CustomError;
 
@@ -22,6 +22,7 @@ pub struct AdminUserForm {
     email: String,
     role: String,
     validated: String,
+    clearance: String,
 }
 
 #[get("/{lang}/user_index")]
@@ -88,7 +89,8 @@ pub async fn user_page_handler(
                 ctx.insert("user", &user);
         
                 // Add Additional User Data Here
-                let documents = Document::get_readable_by_created_by(user.id, &lang)
+                // listed at the reader's clearance, not the author's
+                let documents = Document::get_readable_by_created_by(user.id, &lang, &get_clearance(&session_user))
                     .expect("Unable to retrieve documents");
 
                 ctx.insert("documents", &documents);
@@ -248,6 +250,7 @@ pub async fn admin_edit_user(
         Ok(user) => {
 
             ctx.insert("user", &user);
+            ctx.insert("classifications", &SECURITY_CLASSIFICATIONS);
         
             let rendered = data.tmpl.render("users/admin_edit_user.html", &ctx).unwrap();
             return HttpResponse::Ok().body(rendered)
@@ -292,6 +295,15 @@ pub async fn admin_edit_user_post(
             user.validated = validated;
             user.role = form.role.to_lowercase().trim().to_owned();
 
+            let clearance = form.clearance.to_lowercase().trim().to_owned();
+
+            if let Err(err) = validate_classification(&clearance) {
+                println!("{}", &err);
+                return err.error_response()
+            };
+
+            user.clearance = clearance;
+
             // update user email
             if &form.email.to_lowercase().trim() != &user.email {
                 user.email = form.email.to_lowercase().trim().to_owned();
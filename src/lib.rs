@@ -3,6 +3,12 @@ pub mod handlers;
 pub mod database;
 pub mod errors;
 pub mod schema;
+pub mod docx;
+pub mod encryption;
+pub mod realtime;
+pub mod translation;
+pub mod xliff;
+pub mod termbase;
 
 use std::collections::BTreeMap;
 use tera::{Tera, Context};
@@ -22,6 +28,8 @@ use sendgrid::SGClient;
 use models::{User, Template, InsertableTemplate, InsertableTemplateSection, TemplateSection,
     Document, InsertableDocument, Section, InsertableSection, InsertableText, Text};
 use errors::CustomError;
+use encryption::Keyring;
+use translation::Translator;
 
 #[macro_use]
 extern crate diesel;
@@ -35,9 +43,16 @@ extern crate lazy_static;
 const APP_NAME: &str = "Data Docs";
 
 lazy_static! {
-    // Set up MagicCrypt for encryption and decryption
-    // We would want to rotate our secret key in production
-    static ref MAGIC_CRYPT: magic_crypt::MagicCrypt256 = magic_crypt::new_magic_crypt!(env::var("SECRET_KEY").expect("Unable to find secret key"), 256);
+    // Versioned keys for encryption and decryption of Text content
+    static ref KEYRING: Keyring = Keyring::from_env().expect("Unable to load encryption keys");
+
+    // Machine translation provider picked by TRANSLATION_PROVIDER
+    static ref TRANSLATOR: Box<dyn Translator> = translation::from_env().expect("Unable to configure machine translation");
+}
+
+/// The configured machine translation provider
+pub fn translator() -> &'static dyn Translator {
+    TRANSLATOR.as_ref()
 }
 
 #[derive(Clone, Debug)]
@@ -300,6 +315,14 @@ pub fn extract_identity_data(id: &Identity) -> (String, String) {
     (session_user, role)
 }
 
+/// Security clearance of the signed in user. Anonymous readers are unclassified.
+pub fn get_clearance(session_user: &str) -> String {
+    match models::User::find_slim_from_slug(&session_user.to_string()) {
+        Ok(u) => u.clearance,
+        _ => models::UNCLASSIFIED.to_string(),
+    }
+}
+
 /// Generate context, session_user and role from id and lang
 pub fn generate_email_context(
     id: Identity,
@@ -348,32 +371,61 @@ pub fn generate_unique_code(mut characters: usize, dashes: bool) -> String {
     rand_string
 }
 
-// Run Rake on Text and return JSON struct of keywords
-pub fn run_rake(content: &str) -> Result<serde_json::Value, CustomError> {
-    let sw = StopWords::from_file("stop_word_list.txt")
+/// Stop word list used by RAKE for lang
+pub fn stop_word_file(lang: &str) -> &'static str {
+    match lang {
+        "fr" => "stop_word_list_fr.txt",
+        _ => "stop_word_list.txt",
+    }
+}
+
+/// Split French elisions (l'état, d'une, qu'il) so the article or pronoun
+/// is a separate word that RAKE can drop as a stop word
+pub fn split_elisions(content: &str) -> String {
+
+    lazy_static! {
+        static ref ELISION: Regex = Regex::new(r"(?i)\b(jusqu|lorsqu|puisqu|quoiqu|qu|c|d|j|l|m|n|s|t)['\u{2019}]").unwrap();
+    }
+
+    ELISION.replace_all(content, "$1' ").to_string()
+}
+
+// Run Rake on Text in lang and return JSON struct of keywords
+pub fn run_rake(content: &str, lang: &str) -> Result<serde_json::Value, CustomError> {
+    let sw = StopWords::from_file(stop_word_file(lang))
         .expect("Unable to load stopwords");
 
+    let content = match lang {
+        "fr" => split_elisions(content),
+        _ => content.to_string(),
+    };
+
     let r = Rake::new(sw);
     let keywords = r.run(&content);
 
     Ok(serde_json::to_value(&keywords).unwrap())
 }
 
+/// Number of keywords shown with a text
+const KEYWORDS_SHOWN: usize = 5;
+
 pub fn get_keyword_html(json: Option<serde_json::Value>) -> String {
     // get keywords from text
-    let keywords: String = match json {
-        Some(v) => {
-            let kws: Vec<rake::KeywordScore> = serde_json::from_value(v).unwrap();
-            let mut return_string = String::from("<ul>");
-            for i in 0..1 {
-                return_string.push_str(format!("<li>{:?}: {}</li>", kws[i].keyword, kws[i].score).as_str());
-            };
-            return_string.push_str("</ul>");
-            return_string
-        },
-        None => "None".to_string(),
+    let kws: Vec<rake::KeywordScore> = match json {
+        Some(v) => serde_json::from_value(v).unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    if kws.is_empty() {
+        return "None".to_string()
+    };
+
+    let mut return_string = String::from("<ul>");
+    for k in kws.iter().take(KEYWORDS_SHOWN) {
+        return_string.push_str(format!("<li>{}: {:.2}</li>", k.keyword, k.score).as_str());
     };
-    keywords
+    return_string.push_str("</ul>");
+    return_string
 }
 
 /// Find text redactions in document and format for either open (redacted) or normal reading (internal)
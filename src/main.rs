@@ -10,6 +10,7 @@ use sendgrid::SGClient;
 use data_docs::handlers;
 use data_docs::AppData;
 use data_docs::database;
+use data_docs::models::{Text, Job, TranslationPair};
 
 use fluent_templates::{FluentLoader, static_loader};
 // https://lib.rs/crates/fluent-templates
@@ -25,6 +26,9 @@ static_loader! {
     };
 }
 
+// Texts loaded at a time when re-encrypting
+const REENCRYPT_BATCH_SIZE: i64 = 500;
+
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
 
@@ -32,6 +36,35 @@ async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_web=info");
     env_logger::init();
 
+    // `data_docs reencrypt-texts [batch size]` moves stored texts to the active key and exits
+    let args: Vec<String> = env::args().collect();
+
+    if args.get(1).map(|a| a.as_str()) == Some("reencrypt-texts") {
+        let batch_size = args.get(2)
+            .and_then(|b| b.parse::<i64>().ok())
+            .unwrap_or(REENCRYPT_BATCH_SIZE);
+
+        database::init();
+
+        let report = Text::reencrypt_all(batch_size).expect("Unable to re-encrypt texts");
+
+        println!("Re-encrypted {} of {} texts with the active keys", report.updated, report.total);
+        return Ok(())
+    };
+
+    // `data_docs rebuild-translation-memory` learns from texts translated before the memory existed and exits
+    if args.get(1).map(|a| a.as_str()) == Some("rebuild-translation-memory") {
+        database::init();
+
+        return match TranslationPair::rebuild() {
+            Ok(learned) => {
+                println!("Translation memory built with {} new sentence pairs", learned);
+                Ok(())
+            },
+            Err(err) => Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Unable to build translation memory: {}", err))),
+        }
+    };
+
     let environment = env::var("ENVIRONMENT");
 
     let environment = match environment {
@@ -49,6 +82,12 @@ async fn main() -> std::io::Result<()> {
 
     database::init();
 
+    // a misconfigured provider stops the server here rather than in a background translation
+    println!("Machine translation provider: {}", data_docs::translator().name());
+
+    // translations, summaries and keyword suggestions queued by edits
+    actix_rt::spawn(Job::run_worker());
+
     // SendGrid email API
     let sendgrid_var = env::var("SENDGRID_API_KEY");
     let sendgrid_key: String;
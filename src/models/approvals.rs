@@ -1,16 +1,315 @@
+use serde::{Serialize, Deserialize};
 use uuid::Uuid;
-use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::prelude::*;
 
+use crate::database;
+use crate::schema::{authorizations, decisions};
+use crate::errors::CustomError;
+use crate::models::{User, Document};
+
+#[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Identifiable, Associations, PartialEq, Clone)]
+#[table_name = "authorizations"]
+#[belongs_to(Document)]
+/// An approver named in the approval chain of a document.
+/// Approvers are asked for their decision in order_number order.
+pub struct Authorization {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub user_id: Uuid,
+    pub order_number: i32,
+    pub created_at: NaiveDateTime,
+    pub removed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "authorizations"]
+pub struct InsertableAuthorization {
+    pub document_id: Uuid,
+    pub user_id: Uuid,
+    pub order_number: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Associations, PartialEq, Clone)]
+#[table_name = "decisions"]
+#[belongs_to(Document)]
+#[belongs_to(Authorization)]
+/// A decision made by an approver: approved, rejected or returned for changes.
 pub struct Decision {
-    id: Uuid,
-    document_id: Uuid,
-    decision: String,
-    date_time: NaiveDateTime,
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub authorization_id: Uuid,
+    pub user_id: Uuid,
+    pub decision: String,
+    pub comment: String,
+    pub date_time: NaiveDateTime,
 }
 
-pub struct Authorization {
-    id: Uuid,
-    user_id: Uuid,
-    action: String,
-    document: NaiveDateTime,
-}
\ No newline at end of file
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "decisions"]
+pub struct InsertableDecision {
+    pub document_id: Uuid,
+    pub authorization_id: Uuid,
+    pub user_id: Uuid,
+    pub decision: String,
+    pub comment: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+// A human readable approver and where they are in the chain
+pub struct ReadableAuthorization {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub approver: String,
+    pub order_number: i32,
+    // approved, pending (waiting on this approver) or waiting (waiting on an earlier approver)
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+// A human readable decision for the approval history
+pub struct ReadableDecision {
+    pub id: Uuid,
+    pub approver: String,
+    pub decision: String,
+    pub comment: String,
+    pub date_time: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// The full approval chain for a document with its history of decisions.
+/// A rejection or a return for changes restarts the chain from the first approver.
+pub struct ApprovalChain {
+    pub document_id: Uuid,
+    pub approvers: Vec<ReadableAuthorization>,
+    pub history: Vec<ReadableDecision>,
+    pub current_approver_id: Option<Uuid>,
+    pub complete: bool,
+}
+
+impl Authorization {
+    /// Add an approver at the end of the chain, or move them there if they are already in it.
+    /// A removed approver who is put back gets a new authorization, so they decide again:
+    /// decisions made under the old one stay in the history but don't count towards the chain.
+    pub fn create(authorization: &InsertableAuthorization) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = conn.transaction::<Self, CustomError, _>(|| {
+            let moved: Option<Self> = diesel::update(authorizations::table
+                .filter(authorizations::document_id.eq(authorization.document_id)
+                .and(authorizations::user_id.eq(authorization.user_id))
+                .and(authorizations::removed.eq(false))))
+                .set(authorizations::order_number.eq(authorization.order_number))
+                .get_result(&conn)
+                .optional()?;
+
+            match moved {
+                Some(a) => Ok(a),
+                None => Ok(diesel::insert_into(authorizations::table)
+                    .values(authorization)
+                    .get_result(&conn)?),
+            }
+        })?;
+
+        Ok(v)
+    }
+
+    pub fn get_by_id(id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = authorizations::table
+            .filter(authorizations::id.eq(id))
+            .first(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Approvers in the chain, without those who have been removed
+    pub fn get_by_document_id(document_id: Uuid) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = authorizations::table
+            .filter(authorizations::document_id.eq(document_id))
+            .filter(authorizations::removed.eq(false))
+            .order(authorizations::order_number.asc())
+            .load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Remove an approver from the chain. The authorization is kept so their decisions stay in the history.
+    pub fn remove(id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::update(authorizations::table.filter(authorizations::id.eq(id)))
+            .set(authorizations::removed.eq(true))
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+}
+
+impl InsertableAuthorization {
+    /// Add an approver to the end of the existing chain for a document
+    pub fn new(document_id: Uuid, user_id: Uuid) -> Result<Self, CustomError> {
+
+        let existing = Authorization::get_by_document_id(document_id)?;
+
+        let order_number = match existing.last() {
+            Some(a) => a.order_number + 1,
+            None => 0,
+        };
+
+        Ok(InsertableAuthorization {
+            document_id,
+            user_id,
+            order_number,
+        })
+    }
+}
+
+impl Decision {
+    pub fn create(decision: &InsertableDecision) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::insert_into(decisions::table)
+            .values(decision)
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn get_by_document_id(document_id: Uuid) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = decisions::table
+            .filter(decisions::document_id.eq(document_id))
+            .order(decisions::date_time.asc())
+            .load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+}
+
+impl InsertableDecision {
+    pub fn new(
+        document_id: Uuid,
+        authorization_id: Uuid,
+        user_id: Uuid,
+        decision: &str,
+        comment: String,
+    ) -> Result<Self, CustomError> {
+
+        let decision = match decision {
+            "approved" | "rejected" | "returned" => decision.to_string(),
+            _ => return Err(CustomError::new(400, format!("Unknown decision: {}", decision))),
+        };
+
+        Ok(InsertableDecision {
+            document_id,
+            authorization_id,
+            user_id,
+            decision,
+            comment,
+        })
+    }
+}
+
+impl ApprovalChain {
+    pub fn get_by_document_id(document_id: Uuid) -> Result<Self, CustomError> {
+
+        let authorizations = Authorization::get_by_document_id(document_id)?;
+        let decisions = Decision::get_by_document_id(document_id)?;
+
+        let mut user_ids: Vec<Uuid> = authorizations.iter().map(|a| a.user_id).collect();
+        user_ids.extend(decisions.iter().map(|d| d.user_id));
+
+        let users = User::get_user_email_map(user_ids)?;
+
+        // Only approvals made since the last rejection or return count towards the chain
+        let restart_index = match decisions.iter().rposition(|d| d.decision != "approved") {
+            Some(i) => i + 1,
+            None => 0,
+        };
+
+        let approved_ids: Vec<Uuid> = decisions[restart_index..]
+            .iter()
+            .map(|d| d.authorization_id)
+            .collect();
+
+        let mut current_approver_id = None;
+        let mut approvers = Vec::new();
+
+        for a in authorizations.iter() {
+            let status = if approved_ids.contains(&a.id) {
+                "approved"
+            } else if current_approver_id.is_none() {
+                current_approver_id = Some(a.user_id);
+                "pending"
+            } else {
+                "waiting"
+            };
+
+            approvers.push(ReadableAuthorization {
+                id: a.id,
+                user_id: a.user_id,
+                approver: users.get(&a.user_id).cloned().unwrap_or_default(),
+                order_number: a.order_number,
+                status: status.to_string(),
+            });
+        };
+
+        let history = decisions
+            .iter()
+            .map(|d| ReadableDecision {
+                id: d.id,
+                approver: users.get(&d.user_id).cloned().unwrap_or_default(),
+                decision: d.decision.to_owned(),
+                comment: d.comment.to_owned(),
+                date_time: d.date_time,
+            })
+            .collect();
+
+        // A document without any named approvers has not been approved
+        let complete = !approvers.is_empty() && current_approver_id.is_none();
+
+        Ok(ApprovalChain {
+            document_id,
+            approvers,
+            history,
+            current_approver_id,
+            complete,
+        })
+    }
+
+    /// Record a decision from the approver the chain is currently waiting on
+    pub fn record_decision(
+        document_id: Uuid,
+        user_id: Uuid,
+        decision: &str,
+        comment: String,
+    ) -> Result<Decision, CustomError> {
+
+        let chain = ApprovalChain::get_by_document_id(document_id)?;
+
+        if chain.current_approver_id != Some(user_id) {
+            return Err(CustomError::new(406, "Not the current approver for this document".to_string()))
+        };
+
+        let authorization = chain.approvers
+            .iter()
+            .find(|a| a.status == "pending")
+            .ok_or(CustomError::new(406, "No pending approval for this document".to_string()))?;
+
+        let insertable_decision = InsertableDecision::new(
+            document_id,
+            authorization.id,
+            user_id,
+            decision,
+            comment,
+        )?;
+
+        Decision::create(&insertable_decision)
+    }
+}
@@ -0,0 +1,52 @@
+use crate::errors::CustomError;
+
+/// Security classifications from lowest to highest.
+/// A user can read a document when their clearance is at or above its classification.
+pub const SECURITY_CLASSIFICATIONS: [&str; 4] = [
+    "unclassified",
+    "protected_a",
+    "protected_b",
+    "secret",
+];
+
+pub const UNCLASSIFIED: &str = "unclassified";
+
+/// Clearance for background work (metadata, keywords, search index) that doesn't return content to a user
+pub const SYSTEM_CLEARANCE: &str = "secret";
+
+/// Position of a classification in SECURITY_CLASSIFICATIONS
+pub fn classification_level(classification: &str) -> Option<usize> {
+    SECURITY_CLASSIFICATIONS.iter().position(|c| *c == classification)
+}
+
+/// Error unless the classification is one of SECURITY_CLASSIFICATIONS
+pub fn validate_classification(classification: &str) -> Result<(), CustomError> {
+    match classification_level(classification) {
+        Some(_) => Ok(()),
+        None => Err(CustomError::new(400, format!("Unknown security classification: {}", classification))),
+    }
+}
+
+/// Whether clearance allows reading content at classification.
+/// Unknown classifications are treated as the highest and unknown clearances as the lowest.
+pub fn has_clearance(clearance: &str, classification: &str) -> bool {
+    let required = classification_level(classification).unwrap_or(SECURITY_CLASSIFICATIONS.len());
+    let held = classification_level(clearance).unwrap_or(0);
+
+    held >= required
+}
+
+/// Not authorized error unless clearance allows reading content at classification
+pub fn check_clearance(clearance: &str, classification: &str) -> Result<(), CustomError> {
+    match has_clearance(clearance, classification) {
+        true => Ok(()),
+        false => Err(CustomError::new(406, "Not authorized: content is above your security clearance".to_string())),
+    }
+}
+
+/// The higher of two classifications
+pub fn highest_classification<'a>(a: &'a str, b: &'a str) -> &'a str {
+    let level = |c: &str| classification_level(c).unwrap_or(SECURITY_CLASSIFICATIONS.len());
+
+    if level(a) >= level(b) { a } else { b }
+}
@@ -11,7 +11,7 @@ use crate::schema::{documents, template_sections, texts, sections};
 use crate::errors::CustomError;
 use crate::models::{InsertableText, Text, TemplateSection,
     ReadableTemplateSection, User, Section, ReadableSection,
-    MetaData, InsertableMetaData};
+    MetaData, InsertableMetaData, check_clearance, has_clearance};
 
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Insertable, Identifiable, Associations, PartialEq, Clone)]
 #[table_name = "documents"]
@@ -24,8 +24,9 @@ pub struct Document {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub security_classification: String,
-    pub published: bool,
     pub created_by_id: Uuid,
+    // draft, in_review, approved, published, withdrawn or archived
+    pub state: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -40,7 +41,7 @@ pub struct ReadableDocument {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub security_classification: String,
-    pub published: bool,
+    pub state: String,
     pub created_by: String,
     pub created_by_id: Uuid,
 }
@@ -66,19 +67,20 @@ impl InsertableDocument {
         machine_translate: bool,
     ) -> Result<Self, CustomError> {
 
-        let insertable_name_text = InsertableText::new(
-            None,
+        // title and purpose are encrypted at the document's classification, like its sections
+        let insertable_name_text = InsertableText::for_document(
             &lang, 
             raw_title_text.to_owned(),
-            created_by_id);
+            created_by_id,
+            &security_classification)?;
 
         let title_text = Text::create(&insertable_name_text, machine_translate)?;
 
-        let insertable_purpose_text = InsertableText::new(
-            None,
+        let insertable_purpose_text = InsertableText::for_document(
             &lang, 
             raw_purpose_text,
-            created_by_id);
+            created_by_id,
+            &security_classification)?;
 
         let purpose_text = Text::create(&insertable_purpose_text, machine_translate)?;
 
@@ -120,13 +122,36 @@ impl Document {
         Ok(document)
     }
 
-    pub fn get_readable_core_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool) -> Result<ReadableDocument, CustomError> {
+    /// Security classifications of the documents the texts are the title or purpose of, by text id
+    pub fn get_classification_map_by_text(text_ids: Vec<Uuid>) -> Result<BTreeMap<Uuid, String>, CustomError> {
+        let conn = database::connection()?;
+
+        let documents = documents::table
+            .filter(documents::title_text_id.eq_any(&text_ids)
+            .or(documents::purpose_text_id.eq_any(&text_ids)))
+            .load::<Self>(&conn)?;
+
+        let mut classifications = BTreeMap::new();
+
+        for d in documents {
+            classifications.insert(d.title_text_id, d.security_classification.to_owned());
+            classifications.insert(d.purpose_text_id, d.security_classification);
+        };
+
+        Ok(classifications)
+    }
+
+    /// Returns a not authorized error without decrypting the title or purpose
+    /// if the document's security classification is above clearance.
+    pub fn get_readable_core_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool, clearance: &str) -> Result<ReadableDocument, CustomError> {
         let conn = database::connection()?;
 
         let document = documents::table
             .filter(documents::id.eq(id))
             .first::<Self>(&conn)?;
 
+        check_clearance(clearance, &document.security_classification)?;
+
         // Get texts for document
         let mut text_ids = Vec::new();
 
@@ -135,7 +160,7 @@ impl Document {
 
         // Todo -> enable markdown for purpose text
         
-        let raw_texts = Text::get_text_map(text_ids, lang)?;
+        let raw_texts = Text::get_text_map(text_ids, lang, clearance)?;
 
         let mut texts: BTreeMap<Uuid, String> = BTreeMap::new();
 
@@ -174,7 +199,7 @@ impl Document {
             purpose_text_id: document.purpose_text_id,
             created_at: document.created_at,
             updated_at: document.updated_at,
-            published: document.published,
+            state: document.state.to_owned(),
             security_classification: document.security_classification.to_string(),
             created_by: user_email,
             created_by_id: document.created_by_id,
@@ -183,7 +208,8 @@ impl Document {
         Ok(readable_document)
     }
 
-    pub fn get_readable_sections_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool) -> Result<BTreeMap<Uuid, ReadableSection>, CustomError> {
+    /// Refuses to decrypt sections above clearance
+    pub fn get_readable_sections_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool, clearance: &str) -> Result<BTreeMap<Uuid, ReadableSection>, CustomError> {
         let conn = database::connection()?;
 
         let sections = sections::table
@@ -195,30 +221,39 @@ impl Document {
         let mut readable_sections: BTreeMap<Uuid, ReadableSection> = BTreeMap::new();
 
         for section in sections.iter() {
-            let rs = ReadableSection::get_by_id(section.id, lang, markdown, redact)?;
+            let rs = ReadableSection::get_by_id(section.id, lang, markdown, redact, clearance)?;
             readable_sections.insert(section.id, rs);
         }
 
         Ok(readable_sections)
     }
 
-    pub fn get_all_readable_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool) -> Result<(ReadableDocument, BTreeMap<Uuid, ReadableSection>), CustomError> {
+    /// The document and its sections. Returns a not authorized error without decrypting
+    /// anything if the document's security classification is above clearance.
+    pub fn get_all_readable_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool, clearance: &str) -> Result<(ReadableDocument, BTreeMap<Uuid, ReadableSection>), CustomError> {
+
+        let classification = Document::get_by_id(id)?.security_classification;
 
-        let document = Document::get_readable_core_by_id(id, lang, false, redact)?;
+        check_clearance(clearance, &classification)?;
+
+        let document = Document::get_readable_core_by_id(id, lang, false, redact, clearance)?;
 
         let readable_sections: BTreeMap<Uuid, ReadableSection> = Document::get_readable_sections_by_id(
-            id, lang, markdown, redact)?;
+            id, lang, markdown, redact, clearance)?;
 
         Ok((document, readable_sections))
     }
 
-    pub fn get_readable_plus_template_sections_by_id(id: Uuid, lang: &str) -> Result<(ReadableDocument, BTreeMap<Uuid, ReadableTemplateSection>), CustomError> {
+    /// Refuses to decrypt if the document's security classification is above clearance
+    pub fn get_readable_plus_template_sections_by_id(id: Uuid, lang: &str, clearance: &str) -> Result<(ReadableDocument, BTreeMap<Uuid, ReadableTemplateSection>), CustomError> {
         let conn = database::connection()?;
 
         let document = documents::table
             .filter(documents::id.eq(id))
             .first::<Self>(&conn)?;
 
+        check_clearance(clearance, &document.security_classification)?;
+
         let sections = template_sections::table
             .filter(template_sections::template_id.eq(document.template_id))
             .load::<TemplateSection>(&conn)?;
@@ -229,7 +264,7 @@ impl Document {
         text_ids.push(document.title_text_id);
         text_ids.push(document.purpose_text_id);
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, clearance)?;
 
         let user_email = User::find_email_from_id(document.created_by_id)?;
 
@@ -243,7 +278,7 @@ impl Document {
             created_at: document.created_at,
             updated_at: document.updated_at,
             security_classification: document.security_classification.to_string(),
-            published: document.published,
+            state: document.state.to_owned(),
             created_by: user_email,
             created_by_id: document.created_by_id,
         };
@@ -260,18 +295,25 @@ impl Document {
         Ok((readable_document, readable_sections))
     }
 
-    pub fn get_all_readable(lang: &str, document_view: &str) -> Result<Vec<ReadableDocument>, CustomError> {
+    /// Documents above clearance are left out
+    pub fn get_all_readable(lang: &str, document_view: &str, state: Option<&str>, clearance: &str) -> Result<Vec<ReadableDocument>, CustomError> {
         let conn = database::connection()?;
 
+        let mut query = documents::table.into_boxed();
+
         // External view only gets published documents
-        let documents = match document_view {
-            "internal" => documents::table
-                .load::<Self>(&conn)?,
-            _ => documents::table
-                .filter(documents::published.eq(true))
-                .load::<Self>(&conn)?,
+        query = match document_view {
+            "internal" => match state {
+                Some(s) => query.filter(documents::state.eq(s.to_owned())),
+                None => query,
+            },
+            _ => query.filter(documents::state.eq("published")),
         };
 
+        let mut documents = query.load::<Self>(&conn)?;
+
+        documents.retain(|d| has_clearance(clearance, &d.security_classification));
+
         let mut text_ids = Vec::new();
         let mut user_ids = Vec::new();
 
@@ -282,7 +324,7 @@ impl Document {
             user_ids.push(document.created_by_id);
         };
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, clearance)?;
         let users = User::get_user_email_map(user_ids)?;
 
         let mut readable_documents = Vec::new();
@@ -298,7 +340,7 @@ impl Document {
                 created_at: document.created_at,
                 updated_at: document.updated_at,
                 security_classification: document.security_classification.to_string(),
-                published: document.published,
+                state: document.state.to_owned(),
                 created_by: users.get(&document.created_by_id).unwrap().to_string(),
                 created_by_id: document.created_by_id,
             };
@@ -309,13 +351,16 @@ impl Document {
         Ok(readable_documents)
     }
 
-    pub fn get_readable_by_created_by(created_by: Uuid, lang: &str) -> Result<Vec<ReadableDocument>, CustomError> {
+    /// Documents above clearance are left out
+    pub fn get_readable_by_created_by(created_by: Uuid, lang: &str, clearance: &str) -> Result<Vec<ReadableDocument>, CustomError> {
         let conn = database::connection()?;
 
-        let documents = documents::table
+        let mut documents = documents::table
             .filter(documents::created_by_id.eq(created_by))
             .load::<Self>(&conn)?;
 
+        documents.retain(|d| has_clearance(clearance, &d.security_classification));
+
         let mut text_ids = Vec::new();
         let mut user_ids = Vec::new();
 
@@ -326,7 +371,7 @@ impl Document {
             user_ids.push(document.created_by_id);
         };
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, clearance)?;
         let users = User::get_user_email_map(user_ids)?;
 
         let mut readable_documents = Vec::new();
@@ -342,7 +387,7 @@ impl Document {
                 created_at: document.created_at,
                 updated_at: document.updated_at,
                 security_classification: document.security_classification.to_string(),
-                published: document.published,
+                state: document.state.to_owned(),
                 created_by: users.get(&document.created_by_id).unwrap().to_string(),
                 created_by_id: document.created_by_id,
             };
@@ -377,4 +422,34 @@ impl Document {
             .get_result(&conn)?;
         Ok(v)
     }
+
+    /// Update unless the document was saved after base_updated_at, in which case fail with an edit conflict
+    pub fn update_if_unchanged(&self, base_updated_at: NaiveDateTime) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+        let v: Option<Self> = diesel::update(documents::table)
+            .filter(documents::id.eq(self.id)
+            .and(documents::updated_at.eq(base_updated_at)))
+            .set(self)
+            .get_result(&conn)
+            .optional()?;
+
+        v.ok_or_else(|| CustomError::edit_conflict("Edit conflict: the document was saved by someone else".to_string()))
+    }
+
+    /// Delete a document with its sections and texts, including its title and purpose
+    pub fn delete(id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let document = Document::get_by_id(id)?;
+
+        conn.transaction::<usize, CustomError, _>(|| {
+            let res = diesel::delete(documents::table.filter(documents::id.eq(id))).execute(&conn)?;
+
+            diesel::delete(texts::table
+                .filter(texts::id.eq_any(vec![document.title_text_id, document.purpose_text_id])))
+                .execute(&conn)?;
+
+            Ok(res)
+        })
+    }
 }
\ No newline at end of file
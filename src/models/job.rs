@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl, sql_types};
+use chrono::{Duration, prelude::*};
+
+use crate::database;
+use crate::schema::{jobs};
+use crate::errors::CustomError;
+use crate::models::{Text, TextVersion, InsertableMetaData, MetaData, KeywordSuggestion, TranslationPair, TermWarning, machine_translate_text};
+
+/// Kinds of background job
+pub const TRANSLATE_TEXT: &str = "translate_text";
+pub const UPDATE_METADATA: &str = "update_metadata";
+pub const SUGGEST_KEYWORDS: &str = "suggest_keywords";
+pub const LEARN_TRANSLATION: &str = "learn_translation";
+pub const CHECK_TERMINOLOGY: &str = "check_terminology";
+
+/// Attempts before a job is moved to the dead state
+pub const JOB_MAX_ATTEMPTS: i32 = 5;
+
+// Delay before the first retry, doubled after each failure up to JOB_MAX_BACKOFF_SECONDS
+const JOB_BACKOFF_SECONDS: i64 = 30;
+const JOB_MAX_BACKOFF_SECONDS: i64 = 3600;
+
+// A job is stopped after running this long. One still marked running after it is
+// assumed lost with its server and is run again.
+const JOB_TIMEOUT_MINUTES: i64 = 15;
+
+// Seconds between checks for due jobs when the queue is empty
+const JOB_POLL_SECONDS: u64 = 5;
+
+#[derive(Debug, Serialize, Deserialize, Queryable, QueryableByName, Identifiable, PartialEq, Clone)]
+#[table_name = "jobs"]
+/// Background work saved in the database so it is retried on failure and survives restarts
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    pub text_id: Option<Uuid>,
+    pub document_id: Option<Uuid>,
+    pub lang: String,
+    pub base_version: Option<i32>,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Job {
+    /// Queue a job unless the same job is already waiting to run.
+    /// A waiting job takes the newer base_version.
+    pub fn enqueue(kind: &str, text_id: Option<Uuid>, document_id: Option<Uuid>, lang: &str, base_version: Option<i32>) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        // one statement against jobs__pending_idx, so two saves at once queue a single job
+        let v = diesel::sql_query(
+            "INSERT INTO jobs (kind, text_id, document_id, lang, base_version, max_attempts) VALUES ($1, $2, $3, $4, $5, $6) \
+            ON CONFLICT (kind, \
+                COALESCE(text_id, '00000000-0000-0000-0000-000000000000'), \
+                COALESCE(document_id, '00000000-0000-0000-0000-000000000000'), \
+                lang) WHERE status = 'pending' \
+            DO UPDATE SET base_version = EXCLUDED.base_version \
+            RETURNING *")
+            .bind::<sql_types::Text, _>(kind)
+            .bind::<sql_types::Nullable<sql_types::Uuid>, _>(text_id)
+            .bind::<sql_types::Nullable<sql_types::Uuid>, _>(document_id)
+            .bind::<sql_types::Text, _>(lang)
+            .bind::<sql_types::Nullable<sql_types::Integer>, _>(base_version)
+            .bind::<sql_types::Integer, _>(JOB_MAX_ATTEMPTS)
+            .get_result::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Machine translate a text from lang into the other language.
+    /// The translation is skipped if the other language is edited before the job runs.
+    pub fn enqueue_translation(text_id: Uuid, lang: &str) -> Result<Self, CustomError> {
+        let target_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        // 0 when the other language has no revision yet
+        let base_version = TextVersion::get_heads(vec![text_id], target_lang)?
+            .get(&text_id)
+            .map(|h| h.version_number)
+            .unwrap_or(0);
+
+        Job::enqueue(TRANSLATE_TEXT, Some(text_id), None, lang, Some(base_version))
+    }
+
+    /// Add the sentences of a newly translated text to the translation memory
+    pub fn enqueue_learning(text_id: Uuid, lang: &str) -> Result<Self, CustomError> {
+        Job::enqueue(LEARN_TRANSLATION, Some(text_id), None, lang, None)
+    }
+
+    /// Check every text against the termbase after it changes
+    pub fn enqueue_terminology_check() -> Result<Self, CustomError> {
+        Job::enqueue(CHECK_TERMINOLOGY, None, None, "en", None)
+    }
+
+    /// Regenerate the summary and metadata of a document, then suggest keywords from it
+    pub fn enqueue_document_analysis(document_id: Uuid, lang: &str) -> Result<(), CustomError> {
+        Job::enqueue(UPDATE_METADATA, None, Some(document_id), lang, None)?;
+        Job::enqueue(SUGGEST_KEYWORDS, None, Some(document_id), lang, None)?;
+
+        Ok(())
+    }
+
+    pub fn get_by_id(id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = jobs::table
+            .filter(jobs::id.eq(id))
+            .first::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Most recent jobs first, optionally only those with status
+    pub fn get_recent(status: Option<&str>, limit: i64) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let mut query = jobs::table
+            .order(jobs::created_at.desc())
+            .limit(limit)
+            .into_boxed();
+
+        if let Some(s) = status {
+            query = query.filter(jobs::status.eq(s.to_string()));
+        };
+
+        let v = query.load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// text_id -> "pending" or "failed" for texts with an unfinished or dead translation.
+    /// A pending job hides older failures of the same text.
+    pub fn get_translation_statuses(text_ids: Vec<Uuid>) -> Result<BTreeMap<Uuid, String>, CustomError> {
+        let conn = database::connection()?;
+
+        let unfinished = jobs::table
+            .filter(jobs::kind.eq(TRANSLATE_TEXT))
+            .filter(jobs::text_id.eq_any(text_ids))
+            .filter(jobs::status.ne("done"))
+            .order(jobs::created_at)
+            .load::<Self>(&conn)?;
+
+        Ok(Job::translation_statuses(unfinished))
+    }
+
+    /// Like get_translation_statuses, but only for translations into lang
+    pub fn get_translation_statuses_into(text_ids: Vec<Uuid>, lang: &str) -> Result<BTreeMap<Uuid, String>, CustomError> {
+        let conn = database::connection()?;
+
+        // jobs are queued in the language translated from
+        let source_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let unfinished = jobs::table
+            .filter(jobs::kind.eq(TRANSLATE_TEXT))
+            .filter(jobs::text_id.eq_any(text_ids))
+            .filter(jobs::lang.eq(source_lang))
+            .filter(jobs::status.ne("done"))
+            .order(jobs::created_at)
+            .load::<Self>(&conn)?;
+
+        Ok(Job::translation_statuses(unfinished))
+    }
+
+    // One status per text, from its unfinished jobs oldest first
+    fn translation_statuses(unfinished: Vec<Self>) -> BTreeMap<Uuid, String> {
+        let mut statuses = BTreeMap::new();
+
+        for job in unfinished {
+            let status = match job.status.as_str() {
+                "dead" => "failed",
+                _ => "pending",
+            };
+
+            let text_id = job.text_id.expect("Translation jobs have a text");
+
+            if statuses.get(&text_id).map(|s: &String| s.as_str()) != Some("pending") {
+                statuses.insert(text_id, status.to_string());
+            };
+        };
+
+        statuses
+    }
+
+    /// Run a dead or waiting job again with a fresh set of attempts.
+    /// If the same work has been queued again since, that job is returned instead.
+    pub fn retry(id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let job = Job::get_by_id(id)?;
+
+        if job.status == "running" || job.status == "done" {
+            return Err(CustomError::new(400, format!("Job {} is {}", id, job.status)))
+        };
+
+        if let Some(waiting) = job.pending_duplicate()? {
+            return Ok(waiting)
+        };
+
+        let now = Utc::now().naive_utc();
+
+        let v = diesel::update(jobs::table.filter(jobs::id.eq(id)))
+            .set((
+                jobs::status.eq("pending"),
+                jobs::attempts.eq(0),
+                jobs::run_at.eq(now),
+                jobs::updated_at.eq(now),
+            ))
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Take the next due job, marking it running so no other worker picks it up
+    fn claim() -> Result<Option<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let now = Utc::now().naive_utc();
+
+        // jobs left running by a server that stopped are run again, unless out of attempts
+        let lost = jobs::table
+            .filter(jobs::status.eq("running"))
+            .filter(jobs::updated_at.lt(now - Duration::minutes(JOB_TIMEOUT_MINUTES)));
+
+        let lost_error = format!("Still running after {} minutes", JOB_TIMEOUT_MINUTES);
+
+        diesel::update(lost.filter(jobs::attempts.ge(jobs::max_attempts)))
+            .set((
+                jobs::status.eq("dead"),
+                jobs::last_error.eq(&lost_error),
+                jobs::updated_at.eq(now),
+            ))
+            .execute(&conn)?;
+
+        for job in lost.load::<Self>(&conn)? {
+            // the same work queued again while this one ran is left to that job
+            let status = match job.pending_duplicate()? {
+                Some(_) => "done",
+                None => "pending",
+            };
+
+            diesel::update(jobs::table.filter(jobs::id.eq(job.id)))
+                .set((
+                    jobs::status.eq(status),
+                    jobs::last_error.eq(&lost_error),
+                ))
+                .execute(&conn)?;
+        };
+
+        conn.transaction::<Option<Self>, CustomError, _>(|| {
+            let next = jobs::table
+                .filter(jobs::status.eq("pending"))
+                .filter(jobs::run_at.le(now))
+                .order(jobs::run_at)
+                .for_update()
+                .skip_locked()
+                .first::<Self>(&conn)
+                .optional()?;
+
+            let job = match next {
+                Some(j) => j,
+                None => return Ok(None),
+            };
+
+            let v = diesel::update(jobs::table.filter(jobs::id.eq(job.id)))
+                .set((
+                    jobs::status.eq("running"),
+                    jobs::attempts.eq(job.attempts + 1),
+                    jobs::updated_at.eq(now),
+                ))
+                .get_result(&conn)?;
+
+            Ok(Some(v))
+        })
+    }
+
+    fn finish(&self, result: Result<(), CustomError>) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let now = Utc::now().naive_utc();
+
+        let (status, run_at, last_error) = match result {
+            Ok(()) => ("done", self.run_at, self.last_error.clone()),
+            Err(err) if self.attempts >= self.max_attempts => ("dead", self.run_at, Some(err.to_string())),
+            // the same work was queued again while this one ran and that job will do it
+            Err(err) if self.pending_duplicate()?.is_some() => ("done", self.run_at, Some(err.to_string())),
+            Err(err) => {
+                let backoff = (JOB_BACKOFF_SECONDS << (self.attempts - 1).clamp(0, 16)).min(JOB_MAX_BACKOFF_SECONDS);
+
+                ("pending", now + Duration::seconds(backoff), Some(err.to_string()))
+            },
+        };
+
+        let v = diesel::update(jobs::table.filter(jobs::id.eq(self.id)))
+            .set((
+                jobs::status.eq(status),
+                jobs::run_at.eq(run_at),
+                jobs::last_error.eq(last_error),
+                jobs::updated_at.eq(now),
+            ))
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    // Another job waiting to do the same work. Only one can be pending at a time.
+    fn pending_duplicate(&self) -> Result<Option<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = jobs::table
+            .filter(jobs::id.ne(self.id))
+            .filter(jobs::kind.eq(&self.kind))
+            .filter(jobs::text_id.is_not_distinct_from(self.text_id))
+            .filter(jobs::document_id.is_not_distinct_from(self.document_id))
+            .filter(jobs::lang.eq(&self.lang))
+            .filter(jobs::status.eq("pending"))
+            .first::<Self>(&conn)
+            .optional()?;
+
+        Ok(v)
+    }
+
+    async fn perform(&self) -> Result<(), CustomError> {
+        let lang = Arc::new(self.lang.to_owned());
+
+        match self.kind.as_str() {
+            TRANSLATE_TEXT => {
+                let text_id = self.text_id
+                    .ok_or_else(|| CustomError::new(400, "Translation job without a text".to_string()))?;
+
+                let text = Text::get_text_by_id(text_id, &self.lang)?;
+
+                machine_translate_text(Arc::new(vec![text]), lang, vec![self.base_version]).await?;
+            },
+            UPDATE_METADATA => {
+                let document_id = self.document_id
+                    .ok_or_else(|| CustomError::new(400, "Metadata job without a document".to_string()))?;
+
+                let md = InsertableMetaData::update_document(Arc::new(document_id), lang).await?;
+
+                let current_md = MetaData::get_by_document_id(document_id)?;
+
+                MetaData::update_from_metadata(current_md.id, &md)?;
+            },
+            SUGGEST_KEYWORDS => {
+                let document_id = self.document_id
+                    .ok_or_else(|| CustomError::new(400, "Keyword job without a document".to_string()))?;
+
+                KeywordSuggestion::suggest_for_document(document_id, lang).await?;
+            },
+            LEARN_TRANSLATION => {
+                let text_id = self.text_id
+                    .ok_or_else(|| CustomError::new(400, "Translation memory job without a text".to_string()))?;
+
+                TranslationPair::learn_text(text_id)?;
+            },
+            CHECK_TERMINOLOGY => {
+                TermWarning::check_all()?;
+            },
+            k => return Err(CustomError::new(400, format!("Unknown job kind {}", k))),
+        };
+
+        Ok(())
+    }
+
+    /// Run due jobs one at a time for as long as the server is up.
+    /// Several servers can share the queue; each job is claimed by one of them.
+    pub async fn run_worker() {
+        loop {
+            let job = match Job::claim() {
+                Ok(Some(j)) => j,
+                Ok(None) => {
+                    tokio::time::delay_for(std::time::Duration::from_secs(JOB_POLL_SECONDS)).await;
+                    continue
+                },
+                Err(err) => {
+                    println!("Unable to claim job: {}", &err);
+                    tokio::time::delay_for(std::time::Duration::from_secs(JOB_POLL_SECONDS)).await;
+                    continue
+                },
+            };
+
+            // run in its own task so a panic fails the job instead of stopping the worker
+            let running = job.clone();
+
+            let timeout = std::time::Duration::from_secs(JOB_TIMEOUT_MINUTES as u64 * 60);
+
+            let result = match tokio::spawn(async move { tokio::time::timeout(timeout, running.perform()).await }).await {
+                Ok(Ok(r)) => r,
+                Ok(Err(_)) => Err(CustomError::new(500, format!("Job timed out after {} minutes", JOB_TIMEOUT_MINUTES))),
+                Err(err) => Err(CustomError::new(500, format!("Job panicked: {}", err))),
+            };
+
+            if let Err(err) = &result {
+                println!("Job {} {} failed on attempt {}: {}", job.kind, job.id, job.attempts, err);
+            };
+
+            if let Err(err) = job.finish(result) {
+                println!("Unable to record result of job {}: {}", job.id, &err);
+            };
+        };
+    }
+}
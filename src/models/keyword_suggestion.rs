@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::NaiveDateTime;
+
+use crate::{database, run_rake, process_text_redactions};
+use crate::schema::{keyword_suggestions};
+use crate::errors::CustomError;
+use crate::models::{Document, MetaData, TaxonomyEntry, InsertableTaxonomyEntry, machine_translate_string, SYSTEM_CLEARANCE};
+
+// Number of RAKE terms taken from a document each time it is analysed
+const SUGGESTED_KEYWORDS: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Associations, PartialEq, Clone)]
+#[table_name = "keyword_suggestions"]
+#[belongs_to(Document)]
+/// A RAKE term from a document, matched to a KeyWord or waiting for a curator
+pub struct KeywordSuggestion {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub keyword_id: Option<Uuid>,
+    pub term: String,
+    pub lang: String,
+    pub en_string: String,
+    pub fr_string: String,
+    pub score: f64,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "keyword_suggestions"]
+pub struct InsertableKeywordSuggestion {
+    pub document_id: Uuid,
+    pub keyword_id: Option<Uuid>,
+    pub term: String,
+    pub lang: String,
+    pub en_string: String,
+    pub fr_string: String,
+    pub score: f64,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A pending suggestion with the title of its document in the language of the user
+pub struct ReadableKeywordSuggestion {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub document_title: String,
+    pub term: String,
+    pub en_string: String,
+    pub fr_string: String,
+    pub score: f64,
+}
+
+impl KeywordSuggestion {
+    /// Add a suggestion unless the document already has one for the term, e.g. from a concurrent run
+    pub fn create(suggestion: &InsertableKeywordSuggestion) -> Result<Option<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::insert_into(keyword_suggestions::table)
+            .values(suggestion)
+            .on_conflict_do_nothing()
+            .get_result(&conn)
+            .optional()?;
+
+        Ok(v)
+    }
+
+    pub fn get_by_id(id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = keyword_suggestions::table
+            .filter(keyword_suggestions::id.eq(id))
+            .first::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn get_pending() -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = keyword_suggestions::table
+            .filter(keyword_suggestions::status.eq("pending"))
+            .order((keyword_suggestions::document_id, keyword_suggestions::score.desc()))
+            .load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Pending suggestions for the curation queue, grouped by document
+    pub fn get_pending_readable(lang: &str) -> Result<Vec<ReadableKeywordSuggestion>, CustomError> {
+        let pending = KeywordSuggestion::get_pending()?;
+
+        let mut titles: BTreeMap<Uuid, String> = BTreeMap::new();
+        let mut readable = Vec::new();
+
+        for s in pending {
+            if !titles.contains_key(&s.document_id) {
+                let md = MetaData::get_by_document_id(s.document_id)?;
+
+                let title = match lang {
+                    "fr" => md.searchable_title_fr,
+                    _ => md.searchable_title_en,
+                };
+
+                titles.insert(s.document_id, title);
+            };
+
+            readable.push(ReadableKeywordSuggestion {
+                id: s.id,
+                document_id: s.document_id,
+                document_title: titles[&s.document_id].clone(),
+                term: s.term,
+                en_string: s.en_string,
+                fr_string: s.fr_string,
+                score: s.score,
+            });
+        };
+
+        Ok(readable)
+    }
+
+    fn set_status(&self, status: &str, keyword_id: Option<Uuid>, en_string: &str, fr_string: &str) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::update(keyword_suggestions::table.filter(keyword_suggestions::id.eq(self.id)))
+            .set((
+                keyword_suggestions::status.eq(status),
+                keyword_suggestions::keyword_id.eq(keyword_id),
+                keyword_suggestions::en_string.eq(en_string),
+                keyword_suggestions::fr_string.eq(fr_string),
+            ))
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Take the top RAKE terms across the sections of a document in lang.
+    /// Terms matching an existing KeyWord are attached to the document's MetaData.
+    /// New terms are translated and wait for a curator. Terms already suggested
+    /// for the document or rejected for any document are skipped.
+    pub async fn suggest_for_document(document_id: Uuid, lang: Arc<String>) -> Result<Vec<Self>, CustomError> {
+
+        let l = lang.as_str();
+
+        let (_document, sections) = Document::get_all_readable_by_id(document_id, l, false, false, SYSTEM_CLEARANCE)?;
+
+        // Score terms on the redacted text so redacted content never becomes a keyword
+        let mut scores: BTreeMap<String, f64> = BTreeMap::new();
+
+        for (_id, section) in sections {
+            let content = process_text_redactions(section.content, true);
+
+            let keywords: Vec<rake::KeywordScore> = serde_json::from_value(run_rake(&content, l)?)
+                .unwrap_or_default();
+
+            for k in keywords {
+                let term = k.keyword.trim().to_lowercase();
+
+                if is_keyword_term(&term) {
+                    *scores.entry(term).or_insert(0.0) += k.score;
+                };
+            };
+        };
+
+        let conn = database::connection()?;
+
+        let known: Vec<String> = keyword_suggestions::table
+            .filter(keyword_suggestions::document_id.eq(document_id)
+            .or(keyword_suggestions::status.eq("rejected")))
+            .select(keyword_suggestions::term)
+            .load::<String>(&conn)?;
+
+        let mut ranked: Vec<(String, f64)> = scores
+            .into_iter()
+            .filter(|(term, _)| !known.contains(term))
+            .collect();
+
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        ranked.truncate(SUGGESTED_KEYWORDS);
+
+        if ranked.is_empty() {
+            return Ok(Vec::new())
+        };
+
+        let vocabulary = TaxonomyEntry::get_all("keywords")?;
+
+        let mut matched = Vec::new();
+        let mut new_terms = Vec::new();
+
+        for (term, score) in ranked {
+            match vocabulary.iter().find(|k| k.name(l).to_lowercase() == term) {
+                Some(k) => matched.push((term, score, k.clone())),
+                None => new_terms.push((term, score)),
+            };
+        };
+
+        let mut suggestions = Vec::new();
+
+        // Existing keywords are already curated, so attach them straight away
+        let metadata = MetaData::get_by_document_id(document_id)?;
+        let mut keyword_ids = metadata.keyword_ids.clone().unwrap_or_default();
+
+        for (term, score, keyword) in matched {
+            if !keyword_ids.contains(&keyword.id) {
+                keyword_ids.push(keyword.id);
+            };
+
+            suggestions.extend(KeywordSuggestion::create(&InsertableKeywordSuggestion {
+                document_id,
+                keyword_id: Some(keyword.id),
+                term,
+                lang: l.to_string(),
+                en_string: keyword.en_string,
+                fr_string: keyword.fr_string,
+                score,
+                status: "accepted".to_string(),
+            })?);
+        };
+
+        MetaData::update_taxonomy(metadata.id, metadata.subject_id, metadata.category_id, keyword_ids)?;
+
+        if !new_terms.is_empty() {
+            let translations = machine_translate_string(
+                Arc::new(new_terms.iter().map(|(t, _)| t.to_owned()).collect()),
+                lang.clone(),
+            ).await?;
+
+            for ((term, score), translation) in new_terms.into_iter().zip(translations) {
+                let translation = translation.trim().to_lowercase();
+
+                let (en_string, fr_string) = match l {
+                    "fr" => (translation, term.clone()),
+                    _ => (term.clone(), translation),
+                };
+
+                suggestions.extend(KeywordSuggestion::create(&InsertableKeywordSuggestion {
+                    document_id,
+                    keyword_id: None,
+                    term,
+                    lang: l.to_string(),
+                    en_string,
+                    fr_string,
+                    score,
+                    status: "pending".to_string(),
+                })?);
+            };
+        };
+
+        Ok(suggestions)
+    }
+
+    /// Accept a suggestion with names checked by the curator.
+    /// Reuses a KeyWord with the same name or adds it to the vocabulary, then attaches it to the document.
+    pub fn accept(id: Uuid, en_string: &str, fr_string: &str) -> Result<Self, CustomError> {
+
+        let suggestion = KeywordSuggestion::get_by_id(id)?;
+
+        let entry = InsertableTaxonomyEntry::new(en_string, fr_string, "", "");
+
+        let existing = TaxonomyEntry::get_all("keywords")?
+            .into_iter()
+            .find(|k| k.en_string.to_lowercase() == entry.en_string.to_lowercase()
+                || k.fr_string.to_lowercase() == entry.fr_string.to_lowercase());
+
+        let keyword = match existing {
+            Some(k) => k,
+            None => TaxonomyEntry::create("keywords", &entry)?,
+        };
+
+        let metadata = MetaData::get_by_document_id(suggestion.document_id)?;
+        let mut keyword_ids = metadata.keyword_ids.clone().unwrap_or_default();
+
+        if !keyword_ids.contains(&keyword.id) {
+            keyword_ids.push(keyword.id);
+        };
+
+        MetaData::update_taxonomy(metadata.id, metadata.subject_id, metadata.category_id, keyword_ids)?;
+
+        suggestion.set_status("accepted", Some(keyword.id), &entry.en_string, &entry.fr_string)
+    }
+
+    /// Reject a suggestion. The term won't be suggested again for any document.
+    pub fn reject(id: Uuid) -> Result<Self, CustomError> {
+
+        let suggestion = KeywordSuggestion::get_by_id(id)?;
+
+        suggestion.set_status("rejected", None, &suggestion.en_string, &suggestion.fr_string)
+    }
+}
+
+// Skip numbers, single characters and terms left over from redacted text
+fn is_keyword_term(term: &str) -> bool {
+    term.chars().any(|c| c.is_alphabetic())
+        && term.chars().count() > 2
+        && term.chars().all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '\'')
+}
@@ -0,0 +1,215 @@
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::prelude::*;
+
+use crate::{database, realtime};
+use crate::schema::{documents, document_transitions};
+use crate::errors::CustomError;
+use crate::realtime::DocumentEvent;
+use crate::models::{Document, User, ApprovalChain, QaIssue};
+
+/// Lifecycle states for a Document in the order they are usually reached
+pub const DOCUMENT_STATES: [&str; 6] = [
+    "draft",
+    "in_review",
+    "approved",
+    "published",
+    "withdrawn",
+    "archived",
+];
+
+/// Allowed transitions as (from_state, to_state, roles permitted to make the transition).
+/// "owner" is the user who created the document. A document in review is approved
+/// by its approval chain, not moved there by hand.
+const TRANSITIONS: [(&str, &str, &[&str]); 10] = [
+    ("draft", "in_review", &["user", "admin"]),
+    ("draft", "archived", &["admin"]),
+    ("in_review", "draft", &["user", "admin"]),
+    ("approved", "draft", &["user", "admin"]),
+    ("approved", "published", &["owner", "admin"]),
+    ("published", "withdrawn", &["admin"]),
+    ("withdrawn", "published", &["admin"]),
+    ("withdrawn", "draft", &["admin"]),
+    ("withdrawn", "archived", &["admin"]),
+    ("archived", "draft", &["admin"]),
+];
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Associations, PartialEq, Clone)]
+#[table_name = "document_transitions"]
+#[belongs_to(Document)]
+/// A record of a Document moving from one lifecycle state to another
+pub struct DocumentTransition {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub from_state: String,
+    pub to_state: String,
+    pub user_id: Uuid,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "document_transitions"]
+pub struct InsertableDocumentTransition {
+    pub document_id: Uuid,
+    pub from_state: String,
+    pub to_state: String,
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+// A human readable transition for the document history
+pub struct ReadableDocumentTransition {
+    pub from_state: String,
+    pub to_state: String,
+    pub user: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Return the states a user with role can move a document to from from_state.
+/// owner is whether the user created the document.
+pub fn allowed_transitions(from_state: &str, role: &str, owner: bool) -> Vec<String> {
+    TRANSITIONS
+        .iter()
+        .filter(|(from, _to, roles)| *from == from_state && (roles.contains(&role) || (owner && roles.contains(&"owner"))))
+        .map(|(_from, to, _roles)| to.to_string())
+        .collect()
+}
+
+impl DocumentTransition {
+    pub fn create(transition: &InsertableDocumentTransition) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::insert_into(document_transitions::table)
+            .values(transition)
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn get_by_document_id(document_id: Uuid) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = document_transitions::table
+            .filter(document_transitions::document_id.eq(document_id))
+            .order(document_transitions::created_at.asc())
+            .load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Move a document to to_state if the role allows it and record who made the change.
+    /// Only the document's creator or an admin can publish it.
+    pub fn apply(document_id: Uuid, to_state: &str, user_id: Uuid, role: &str) -> Result<Document, CustomError> {
+
+        let document = Document::get_by_id(document_id)?;
+
+        let owner = document.created_by_id == user_id;
+
+        if !allowed_transitions(&document.state, role, owner).contains(&to_state.to_string()) {
+            return Err(CustomError::new(
+                406,
+                format!("Transition from {} to {} not allowed for {}", &document.state, to_state, role),
+            ))
+        };
+
+        // the open view would show in one language what is redacted in the other
+        if to_state == "published" {
+            let mismatched = QaIssue::get_blocking(document_id)?;
+
+            if !mismatched.is_empty() {
+                return Err(CustomError::qa_blocked(format!(
+                    "Redactions differ between the English and French versions of {} text(s)", mismatched.len())))
+            };
+        };
+
+        DocumentTransition::record(document, to_state, user_id)
+    }
+
+    /// Update the state of the document and add the transition to its history without checks.
+    /// Fails with an edit conflict if the document has left the state it was loaded in,
+    /// so of two concurrent transitions from the same state only the first is made.
+    pub fn record(document: Document, to_state: &str, user_id: Uuid) -> Result<Document, CustomError> {
+
+        let transition = InsertableDocumentTransition {
+            document_id: document.id,
+            from_state: document.state.to_owned(),
+            to_state: to_state.to_string(),
+            user_id,
+        };
+
+        let conn = database::connection()?;
+
+        // the state and its history change together.
+        // Only the state is written so concurrent edits to the rest of the document are kept.
+        let (document, transition) = conn.transaction::<(Document, Self), CustomError, _>(|| {
+            let document: Document = diesel::update(documents::table)
+                .filter(documents::id.eq(document.id)
+                .and(documents::state.eq(&transition.from_state)))
+                .set((
+                    documents::state.eq(&transition.to_state),
+                    documents::updated_at.eq(chrono::Utc::now().naive_utc()),
+                ))
+                .get_result::<Document>(&conn)
+                .optional()?
+                .ok_or_else(|| CustomError::edit_conflict(format!(
+                    "Document {} is no longer {}", document.id, &transition.from_state)))?;
+
+            let transition: Self = diesel::insert_into(document_transitions::table)
+                .values(&transition)
+                .get_result(&conn)?;
+
+            Ok((document, transition))
+        })?;
+
+        realtime::publish(document.id, DocumentEvent::StateChanged {
+            from_state: transition.from_state,
+            state: transition.to_state,
+        });
+
+        Ok(document)
+    }
+
+    /// Move a document in review forward or back after an approver has made a decision.
+    /// A complete chain approves the document and a rejection or return sends it back to draft.
+    pub fn sync_with_approvals(document_id: Uuid, user_id: Uuid) -> Result<Document, CustomError> {
+
+        let document = Document::get_by_id(document_id)?;
+
+        if document.state != "in_review" {
+            return Ok(document)
+        };
+
+        let chain = ApprovalChain::get_by_document_id(document_id)?;
+
+        let last_decision = chain.history.last().map(|d| d.decision.as_str());
+
+        match last_decision {
+            Some("rejected") | Some("returned") => DocumentTransition::record(document, "draft", user_id),
+            _ if chain.complete => DocumentTransition::record(document, "approved", user_id),
+            _ => Ok(document),
+        }
+    }
+}
+
+impl ReadableDocumentTransition {
+    pub fn get_by_document_id(document_id: Uuid) -> Result<Vec<Self>, CustomError> {
+
+        let transitions = DocumentTransition::get_by_document_id(document_id)?;
+
+        let users = User::get_user_email_map(transitions.iter().map(|t| t.user_id).collect())?;
+
+        let readable_transitions = transitions
+            .into_iter()
+            .map(|t| ReadableDocumentTransition {
+                from_state: t.from_state,
+                to_state: t.to_state,
+                user: users.get(&t.user_id).cloned().unwrap_or_default(),
+                created_at: t.created_at,
+            })
+            .collect();
+
+        Ok(readable_transitions)
+    }
+}
@@ -9,7 +9,7 @@ use std::sync::Arc;
 use crate::{database};
 use crate::schema::{metadata, categories, subjects, keywords};
 use crate::errors::CustomError;
-use crate::models::{Document, machine_translate_string};
+use crate::models::{Document, User, TaxonomyEntry, ReadableTaxonomyEntry, machine_translate_string, SYSTEM_CLEARANCE};
 
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Identifiable, Associations, PartialEq, Clone)]
 #[table_name = "metadata"]
@@ -34,18 +34,29 @@ pub struct ReadableMetaData {
     pub document_id: Uuid,
     pub title: String,
     pub author: String,
-    pub subject: TextLink,
-    pub category: TextLink,
+    pub subject: Option<TextLink>,
+    pub category: Option<TextLink>,
     pub summary_text: String,
     pub keywords: Vec<TextLink>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TextLink {
+    pub id: Uuid,
     pub text: String,
     pub link: String,
 }
 
+impl From<ReadableTaxonomyEntry> for TextLink {
+    fn from(entry: ReadableTaxonomyEntry) -> Self {
+        TextLink {
+            id: entry.id,
+            text: entry.name,
+            link: entry.link,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Insertable, AsChangeset, Clone)]
 #[table_name = "metadata"]
 pub struct InsertableMetaData {
@@ -61,7 +72,8 @@ pub struct InsertableMetaData {
 }
 
 impl ReadableMetaData {
-    pub fn from_metadata(metadata: MetaData, lang: &str) -> Self {
+    /// Resolve the author and taxonomy entries in lang with links to their pages
+    pub fn from_metadata(metadata: MetaData, lang: &str) -> Result<Self, CustomError> {
 
         let title = match lang {
             "en" => metadata.searchable_title_en,
@@ -73,31 +85,35 @@ impl ReadableMetaData {
             _ => metadata.summary_text_fr,
         };
 
-        let subject = TextLink {
-            text: "Health".to_string(),
-            link: "link".to_string(),
+        let subject = match metadata.subject_id {
+            Some(id) => Some(TaxonomyEntry::get_by_id("subjects", id)?.to_readable("subjects", lang).into()),
+            None => None,
         };
 
-        let category = TextLink {
-            text: "Health".to_string(),
-            link: "link".to_string(),
+        let category = match metadata.category_id {
+            Some(id) => Some(TaxonomyEntry::get_by_id("categories", id)?.to_readable("categories", lang).into()),
+            None => None,
         };
 
-        let keyword = TextLink {
-            text: "Wastewater".to_string(),
-            link: "link".to_string(),
-        };
+        let mut keywords: Vec<TextLink> = TaxonomyEntry::get_by_ids("keywords", metadata.keyword_ids.unwrap_or_default())?
+            .iter()
+            .map(|k| k.to_readable("keywords", lang).into())
+            .collect();
 
-        ReadableMetaData {
+        keywords.sort_by(|a, b| a.text.cmp(&b.text));
+
+        let author = User::find_email_from_id(metadata.author_id)?;
+
+        Ok(ReadableMetaData {
             id: metadata.id,
             document_id: metadata.document_id,
             title,
-            author: "default@email.com".to_string(),
+            author,
             subject,
             category,
             summary_text,
-            keywords: vec![keyword],
-        }
+            keywords,
+        })
     }
 }
 
@@ -111,6 +127,7 @@ impl InsertableMetaData {
             l,
             false,
             false,
+            SYSTEM_CLEARANCE,
         )
             .expect("Unable to retrieve readable document");
 
@@ -238,6 +255,28 @@ impl MetaData {
         Ok(v)
     }
 
+    /// Set the subject, category and keywords describing a document
+    pub fn update_taxonomy(
+        id: Uuid,
+        subject_id: Option<Uuid>,
+        category_id: Option<Uuid>,
+        keyword_ids: Vec<Uuid>,
+    ) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::update(metadata::table)
+            .filter(metadata::id.eq(id))
+            .set((
+                metadata::subject_id.eq(subject_id),
+                metadata::category_id.eq(category_id),
+                metadata::keyword_ids.eq(Some(keyword_ids)),
+                metadata::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
     pub fn update_from_metadata(id: Uuid, metadata: &InsertableMetaData) -> Result<Self, CustomError> {
         let conn = database::connection()?;
         let v = diesel::update(metadata::table)
@@ -251,29 +290,29 @@ impl MetaData {
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Insertable, Identifiable, Associations, PartialEq, Clone)]
 #[table_name = "subjects"]
 pub struct Subject {
-    id: Uuid,
-    en_string: String,
-    fr_string: String,
-    en_description: Option<String>,
-    fr_description: Option<String>,
+    pub id: Uuid,
+    pub en_string: String,
+    pub fr_string: String,
+    pub en_description: Option<String>,
+    pub fr_description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Insertable, Identifiable, Associations, PartialEq, Clone)]
 #[table_name = "categories"]
 pub struct Category {
-    id: Uuid,
-    en_string: String,
-    fr_string: String,
-    en_description: Option<String>,
-    fr_description: Option<String>,
+    pub id: Uuid,
+    pub en_string: String,
+    pub fr_string: String,
+    pub en_description: Option<String>,
+    pub fr_description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Insertable, Identifiable, Associations, PartialEq, Clone)]
 #[table_name = "keywords"]
 pub struct KeyWord {
-    id: Uuid,
-    en_string: String,
-    fr_string: String,
-    en_description: Option<String>,
-    fr_description: Option<String>,
+    pub id: Uuid,
+    pub en_string: String,
+    pub fr_string: String,
+    pub en_description: Option<String>,
+    pub fr_description: Option<String>,
 }
\ No newline at end of file
@@ -1,20 +1,46 @@
 mod user;
 mod document;
 mod text;
+mod text_version;
 mod email;
 mod authentication;
 mod template;
 mod section;
+mod section_lock;
 mod template_section;
 mod metadata;
+mod approvals;
+mod lifecycle;
+mod search;
+mod taxonomy;
+mod keyword_suggestion;
+mod classification;
+mod job;
+mod stale_translation;
+mod translation_pair;
+mod term;
+mod translation_qa;
 
 
 pub use user::*;
 pub use document::*;
 pub use text::*;
+pub use text_version::*;
 pub use email::*;
 pub use authentication::*;
 pub use template::*;
 pub use section::*;
+pub use section_lock::*;
 pub use template_section::*;
-pub use metadata::*;
\ No newline at end of file
+pub use metadata::*;
+pub use approvals::*;
+pub use lifecycle::*;
+pub use search::*;
+pub use taxonomy::*;
+pub use keyword_suggestion::*;
+pub use classification::*;
+pub use job::*;
+pub use stale_translation::*;
+pub use translation_pair::*;
+pub use term::*;
+pub use translation_qa::*;
\ No newline at end of file
@@ -0,0 +1,785 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::{NaiveDate, NaiveDateTime};
+use pulldown_cmark::{Parser, Options, Event, Tag};
+use rust_stemmers::{Algorithm, Stemmer};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{database, process_text_redactions};
+use crate::schema::{search_terms, texts, sections, documents, metadata, templates, subjects, categories, keywords};
+use crate::errors::CustomError;
+use crate::models::{Document, Section, Text, TextVersion, Template, MetaData, Subject, Category, KeyWord, User,
+    UNCLASSIFIED, has_clearance};
+
+// Relative weight of a match by where it was found
+const TITLE_WEIGHT: f64 = 3.0;
+const PURPOSE_WEIGHT: f64 = 1.5;
+const SECTION_WEIGHT: f64 = 1.0;
+const KEYWORD_BOOST: f64 = 1.5;
+
+// Words either side of the first match in a snippet
+const SNIPPET_WORDS: usize = 15;
+
+lazy_static! {
+    // Key for hashing terms in the index. Falls back on the content key.
+    static ref SEARCH_KEY: String = env::var("SEARCH_INDEX_KEY")
+        .or_else(|_| env::var("SECRET_KEY"))
+        .expect("Unable to find search index key");
+
+    static ref EN_STEMMER: Stemmer = Stemmer::create(Algorithm::English);
+    static ref FR_STEMMER: Stemmer = Stemmer::create(Algorithm::French);
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone)]
+#[table_name = "search_terms"]
+/// A hashed word stem found in the latest version of a Text
+pub struct SearchTerm {
+    pub text_id: Uuid,
+    pub lang: String,
+    pub term: String,
+    pub internal_count: i32,
+    pub open_count: i32,
+    pub keyword: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+/// Query and facet filters for a search. Values are kept as strings so a search can be saved as a URL.
+pub struct SearchFilters {
+    pub q: Option<String>,
+    pub template_id: Option<String>,
+    pub security_classification: Option<String>,
+    pub state: Option<String>,
+    pub subject_id: Option<String>,
+    pub category_id: Option<String>,
+    pub keyword_id: Option<String>,
+    pub author_id: Option<String>,
+    // inclusive dates as YYYY-MM-DD
+    pub from_date: Option<String>,
+    pub to_date: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A ranked Document matching a search with a highlighted snippet of the best matching text
+pub struct SearchResult {
+    pub document_id: Uuid,
+    pub template_id: Uuid,
+    pub title_text: String,
+    pub security_classification: String,
+    pub state: String,
+    pub created_by_id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub score: f64,
+    pub matched_in: Vec<String>,
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// The results of a search with facet counts over every matching document
+pub struct SearchResults {
+    pub total: usize,
+    pub results: Vec<SearchResult>,
+    pub facets: Vec<Facet>,
+    // query string to save or share the search
+    pub link: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Counts of the values of one filter. name is the query parameter for the filter.
+pub struct Facet {
+    pub name: String,
+    pub values: Vec<FacetValue>,
+    pub clear_link: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FacetValue {
+    pub value: String,
+    pub label: String,
+    pub count: usize,
+    pub selected: bool,
+    pub link: String,
+}
+
+impl SearchTerm {
+
+    /// Replace the index entries for a text with the terms in its latest content.
+    /// Redacted spans only count towards the internal view.
+    pub fn index_text(text: &Text) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        diesel::delete(search_terms::table
+            .filter(search_terms::text_id.eq(text.id)
+            .and(search_terms::lang.eq(&text.lang))))
+            .execute(&conn)?;
+
+        let content = TextVersion::get_head(text.id, &text.lang)?.decrypt()?;
+
+        // placeholder for a translation that hasn't been written yet
+        if content == "default_translation_traduction_par_defaut" {
+            return Ok(0)
+        };
+
+        let mut counts: BTreeMap<String, (i32, i32)> = BTreeMap::new();
+
+        for stem in stem_terms(&plain_text(&process_text_redactions(content.clone(), false)), &text.lang) {
+            counts.entry(hash_term(&stem)).or_insert((0, 0)).0 += 1;
+        };
+
+        for stem in stem_terms(&plain_text(&process_text_redactions(content, true)), &text.lang) {
+            counts.entry(hash_term(&stem)).or_insert((0, 0)).1 += 1;
+        };
+
+        let keywords: BTreeSet<String> = keyword_stems(&text.keywords, &text.lang)
+            .iter()
+            .map(|s| hash_term(s))
+            .collect();
+
+        let terms: Vec<SearchTerm> = counts
+            .into_iter()
+            .map(|(term, (internal_count, open_count))| SearchTerm {
+                text_id: text.id,
+                lang: text.lang.to_owned(),
+                keyword: keywords.contains(&term),
+                term,
+                internal_count,
+                open_count,
+            })
+            .collect();
+
+        let inserted = diesel::insert_into(search_terms::table)
+            .values(&terms)
+            .execute(&conn)?;
+
+        Ok(inserted)
+    }
+
+    /// Index every text. Used to build the index for texts created before it existed.
+    pub fn rebuild() -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let all_texts = texts::table.load::<Text>(&conn)?;
+
+        for text in all_texts.iter() {
+            SearchTerm::index_text(text)?;
+        };
+
+        Ok(all_texts.len())
+    }
+
+    pub fn rebuild_if_empty() -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let count: i64 = search_terms::table.count().get_result(&conn)?;
+
+        if count > 0 {
+            return Ok(0)
+        };
+
+        SearchTerm::rebuild()
+    }
+}
+
+impl SearchFilters {
+
+    /// The trimmed search query
+    pub fn query(&self) -> &str {
+        self.q.as_deref().unwrap_or("").trim()
+    }
+
+    fn fields(&self) -> Vec<(&'static str, &Option<String>)> {
+        vec![
+            ("q", &self.q),
+            ("template_id", &self.template_id),
+            ("security_classification", &self.security_classification),
+            ("state", &self.state),
+            ("subject_id", &self.subject_id),
+            ("category_id", &self.category_id),
+            ("keyword_id", &self.keyword_id),
+            ("author_id", &self.author_id),
+            ("from_date", &self.from_date),
+            ("to_date", &self.to_date),
+        ]
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut Option<String>> {
+        match name {
+            "q" => Some(&mut self.q),
+            "template_id" => Some(&mut self.template_id),
+            "security_classification" => Some(&mut self.security_classification),
+            "state" => Some(&mut self.state),
+            "subject_id" => Some(&mut self.subject_id),
+            "category_id" => Some(&mut self.category_id),
+            "keyword_id" => Some(&mut self.keyword_id),
+            "author_id" => Some(&mut self.author_id),
+            "from_date" => Some(&mut self.from_date),
+            "to_date" => Some(&mut self.to_date),
+            _ => None,
+        }
+    }
+
+    /// Query string for a link to this search
+    pub fn to_query_string(&self) -> String {
+        self.fields()
+            .into_iter()
+            .filter_map(|(name, value)| non_empty(value).map(|v| format!("{}={}", name, encode_query_value(v))))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+
+    /// Query string for this search with filter set to value, or removed if value is None
+    pub fn link_with(&self, filter: &str, value: Option<&str>) -> String {
+        let mut filters = self.clone();
+
+        if let Some(field) = filters.field_mut(filter) {
+            *field = value.map(|v| v.to_string());
+        };
+
+        filters.to_query_string()
+    }
+
+    fn matches(&self, document: &Document, metadata: Option<&MetaData>) -> bool {
+
+        let uuid = |v: &Option<String>| non_empty(v).and_then(|s| Uuid::parse_str(s).ok());
+        let date = |v: &Option<String>| non_empty(v).and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+
+        if let Some(id) = uuid(&self.template_id) {
+            if document.template_id != id { return false };
+        };
+
+        if let Some(c) = non_empty(&self.security_classification) {
+            if document.security_classification != c { return false };
+        };
+
+        if let Some(s) = non_empty(&self.state) {
+            if document.state != s { return false };
+        };
+
+        if let Some(id) = uuid(&self.author_id) {
+            if document.created_by_id != id { return false };
+        };
+
+        if let Some(d) = date(&self.from_date) {
+            if document.created_at.date() < d { return false };
+        };
+
+        if let Some(d) = date(&self.to_date) {
+            if document.created_at.date() > d { return false };
+        };
+
+        if let Some(id) = uuid(&self.subject_id) {
+            if metadata.and_then(|m| m.subject_id) != Some(id) { return false };
+        };
+
+        if let Some(id) = uuid(&self.category_id) {
+            if metadata.and_then(|m| m.category_id) != Some(id) { return false };
+        };
+
+        if let Some(id) = uuid(&self.keyword_id) {
+            let has_keyword = metadata
+                .and_then(|m| m.keyword_ids.as_ref())
+                .map(|ids| ids.contains(&id))
+                .unwrap_or(false);
+
+            if !has_keyword { return false };
+        };
+
+        true
+    }
+}
+
+impl SearchResult {
+
+    /// Search titles, purposes and section content of documents in lang and narrow by filters.
+    /// Without a query all documents matching the filters are returned, most recent first.
+    /// The open view only searches redacted content of published documents.
+    /// Documents above clearance are left out of results and facet counts
+    pub fn search(filters: &SearchFilters, lang: &str, internal: bool, clearance: &str, limit: usize) -> Result<SearchResults, CustomError> {
+        let conn = database::connection()?;
+
+        let query_stems: BTreeSet<String> = stem_terms(filters.query(), lang).into_iter().collect();
+
+        // document_id -> (score, text for the snippet, fields matched)
+        let mut candidates: BTreeMap<Uuid, (f64, Option<Uuid>, Vec<String>)> = BTreeMap::new();
+
+        let mut matched_documents: Vec<Document> = if query_stems.is_empty() {
+            documents::table.load::<Document>(&conn)?
+        } else {
+            for (document_id, score, best_text_id, matched_in) in rank_documents(&query_stems, lang, internal)? {
+                candidates.insert(document_id, (score, Some(best_text_id), matched_in));
+            };
+
+            documents::table
+                .filter(documents::id.eq_any(candidates.keys().cloned().collect::<Vec<Uuid>>()))
+                .load::<Document>(&conn)?
+        };
+
+        if !internal {
+            matched_documents.retain(|d| d.state == "published");
+        };
+
+        matched_documents.retain(|d| has_clearance(clearance, &d.security_classification));
+
+        let document_metadata: BTreeMap<Uuid, MetaData> = metadata::table
+            .filter(metadata::document_id.eq_any(matched_documents.iter().map(|d| d.id).collect::<Vec<Uuid>>()))
+            .load::<MetaData>(&conn)?
+            .into_iter()
+            .map(|m| (m.document_id, m))
+            .collect();
+
+        matched_documents.retain(|d| filters.matches(d, document_metadata.get(&d.id)));
+
+        let facets = Facet::build_all(&matched_documents, &document_metadata, filters, lang, internal)?;
+
+        if query_stems.is_empty() {
+            matched_documents.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        } else {
+            matched_documents.sort_by(|a, b| {
+                candidates[&b.id].0.partial_cmp(&candidates[&a.id].0).unwrap_or(std::cmp::Ordering::Equal)
+            });
+        };
+
+        let mut total = matched_documents.len();
+
+        let mut results = Vec::new();
+
+        for document in matched_documents.iter() {
+
+            if results.len() == limit {
+                break
+            };
+
+            let (score, best_text_id, matched_in) = candidates
+                .get(&document.id)
+                .cloned()
+                .unwrap_or((0.0, None, Vec::new()));
+
+            let readable = Document::get_readable_core_by_id(document.id, lang, false, !internal, clearance)?;
+
+            // Without a match, show the start of the purpose
+            let snippet_text = Text::get_text_by_id(best_text_id.unwrap_or(document.purpose_text_id), lang)?;
+
+            // a text above the user's clearance leaves the document out rather than failing the search
+            if snippet_text.check_clearance(clearance).is_err() {
+                total -= 1;
+                continue
+            };
+
+            let content = TextVersion::get_head(snippet_text.id, lang)?.decrypt()?;
+
+            let snippet = highlight_snippet(
+                &plain_text(&process_text_redactions(content, !internal)),
+                &query_stems,
+                lang,
+            );
+
+            results.push(SearchResult {
+                document_id: document.id,
+                template_id: document.template_id,
+                title_text: plain_text(&readable.title_text),
+                security_classification: document.security_classification.to_owned(),
+                state: document.state.to_owned(),
+                created_by_id: document.created_by_id,
+                created_at: document.created_at,
+                updated_at: document.updated_at,
+                score,
+                matched_in,
+                snippet,
+            });
+        };
+
+        Ok(SearchResults {
+            total,
+            results,
+            facets,
+            link: filters.to_query_string(),
+        })
+    }
+}
+
+impl Facet {
+
+    /// Count the values of each facet over the documents matching a search
+    pub fn build_all(
+        documents: &[Document],
+        document_metadata: &BTreeMap<Uuid, MetaData>,
+        filters: &SearchFilters,
+        lang: &str,
+        internal: bool,
+    ) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let mut templates: BTreeMap<String, usize> = BTreeMap::new();
+        let mut classifications: BTreeMap<String, usize> = BTreeMap::new();
+        let mut states: BTreeMap<String, usize> = BTreeMap::new();
+        let mut authors: BTreeMap<String, usize> = BTreeMap::new();
+        let mut subjects: BTreeMap<String, usize> = BTreeMap::new();
+        let mut categories: BTreeMap<String, usize> = BTreeMap::new();
+        let mut keywords: BTreeMap<String, usize> = BTreeMap::new();
+
+        for d in documents.iter() {
+            *templates.entry(d.template_id.to_string()).or_insert(0) += 1;
+            *classifications.entry(d.security_classification.to_owned()).or_insert(0) += 1;
+            *states.entry(d.state.to_owned()).or_insert(0) += 1;
+            *authors.entry(d.created_by_id.to_string()).or_insert(0) += 1;
+
+            if let Some(m) = document_metadata.get(&d.id) {
+                if let Some(id) = m.subject_id {
+                    *subjects.entry(id.to_string()).or_insert(0) += 1;
+                };
+
+                if let Some(id) = m.category_id {
+                    *categories.entry(id.to_string()).or_insert(0) += 1;
+                };
+
+                for id in m.keyword_ids.iter().flatten() {
+                    *keywords.entry(id.to_string()).or_insert(0) += 1;
+                };
+            };
+        };
+
+        let ids = |counts: &BTreeMap<String, usize>| -> Vec<Uuid> {
+            counts.keys().filter_map(|k| Uuid::parse_str(k).ok()).collect()
+        };
+
+        // Labels for each facet value
+        let template_names: BTreeMap<Uuid, Uuid> = templates::table
+            .filter(templates::id.eq_any(ids(&templates)))
+            .load::<Template>(&conn)?
+            .into_iter()
+            .map(|t| (t.id, t.name_text_id))
+            .collect();
+
+        let template_texts = Text::get_text_map(template_names.values().cloned().collect(), lang, UNCLASSIFIED)?;
+
+        let template_labels: BTreeMap<String, String> = template_names
+            .iter()
+            .map(|(id, text_id)| (id.to_string(), template_texts.get(text_id).cloned().unwrap_or_default()))
+            .collect();
+
+        let localized = |en: String, fr: String| if lang == "fr" { fr } else { en };
+
+        let subject_labels: BTreeMap<String, String> = subjects::table
+            .filter(subjects::id.eq_any(ids(&subjects)))
+            .load::<Subject>(&conn)?
+            .into_iter()
+            .map(|s| (s.id.to_string(), localized(s.en_string, s.fr_string)))
+            .collect();
+
+        let category_labels: BTreeMap<String, String> = categories::table
+            .filter(categories::id.eq_any(ids(&categories)))
+            .load::<Category>(&conn)?
+            .into_iter()
+            .map(|c| (c.id.to_string(), localized(c.en_string, c.fr_string)))
+            .collect();
+
+        let keyword_labels: BTreeMap<String, String> = keywords::table
+            .filter(keywords::id.eq_any(ids(&keywords)))
+            .load::<KeyWord>(&conn)?
+            .into_iter()
+            .map(|k| (k.id.to_string(), localized(k.en_string, k.fr_string)))
+            .collect();
+
+        let mut facets = vec![
+            Facet::new("template_id", templates, &template_labels, &filters.template_id, filters),
+            Facet::new("security_classification", classifications, &BTreeMap::new(), &filters.security_classification, filters),
+            Facet::new("subject_id", subjects, &subject_labels, &filters.subject_id, filters),
+            Facet::new("category_id", categories, &category_labels, &filters.category_id, filters),
+            Facet::new("keyword_id", keywords, &keyword_labels, &filters.keyword_id, filters),
+        ];
+
+        // Workflow state and authors are only part of the internal view
+        if internal {
+            let author_labels: BTreeMap<String, String> = User::get_user_email_map(ids(&authors))?
+                .into_iter()
+                .map(|(id, email)| (id.to_string(), email))
+                .collect();
+
+            facets.push(Facet::new("state", states, &BTreeMap::new(), &filters.state, filters));
+            facets.push(Facet::new("author_id", authors, &author_labels, &filters.author_id, filters));
+        };
+
+        Ok(facets)
+    }
+
+    /// Facet values by count. Values without a label use the value itself.
+    fn new(
+        name: &str,
+        counts: BTreeMap<String, usize>,
+        labels: &BTreeMap<String, String>,
+        selected: &Option<String>,
+        filters: &SearchFilters,
+    ) -> Self {
+
+        let mut values: Vec<FacetValue> = counts
+            .into_iter()
+            .map(|(value, count)| FacetValue {
+                label: labels.get(&value).cloned().unwrap_or_else(|| value.clone()),
+                selected: non_empty(selected) == Some(value.as_str()),
+                link: filters.link_with(name, Some(&value)),
+                value,
+                count,
+            })
+            .collect();
+
+        values.sort_by(|a, b| b.count.cmp(&a.count).then(a.label.cmp(&b.label)));
+
+        Facet {
+            name: name.to_string(),
+            values,
+            clear_link: filters.link_with(name, None),
+        }
+    }
+}
+
+/// Rank documents against the query stems.
+/// Returns (document_id, score, best matching text_id, fields matched) without checking state.
+fn rank_documents(query_stems: &BTreeSet<String>, lang: &str, internal: bool) -> Result<Vec<(Uuid, f64, Uuid, Vec<String>)>, CustomError> {
+    let conn = database::connection()?;
+
+    let query_terms: Vec<String> = query_stems.iter().map(|s| hash_term(s)).collect();
+
+    let mut hits = search_terms::table
+        .filter(search_terms::term.eq_any(&query_terms)
+        .and(search_terms::lang.eq(lang)))
+        .load::<SearchTerm>(&conn)?;
+
+    if !internal {
+        hits.retain(|h| h.open_count > 0);
+    };
+
+    if hits.is_empty() {
+        return Ok(Vec::new())
+    };
+
+    // Inverse document frequency over all texts in lang
+    let total_texts: i64 = texts::table
+        .filter(texts::lang.eq(lang))
+        .count()
+        .get_result(&conn)?;
+
+    let mut frequency: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for h in hits.iter() {
+        *frequency.entry(&h.term).or_insert(0) += 1;
+    };
+
+    let text_ids: Vec<Uuid> = hits.iter().map(|h| h.text_id).collect::<BTreeSet<Uuid>>().into_iter().collect();
+
+    // Find the document and field for each text
+    let mut text_fields: BTreeMap<Uuid, (Uuid, &str)> = BTreeMap::new();
+
+    let core_documents = documents::table
+        .filter(documents::title_text_id.eq_any(&text_ids)
+        .or(documents::purpose_text_id.eq_any(&text_ids)))
+        .load::<Document>(&conn)?;
+
+    for d in core_documents.iter() {
+        text_fields.insert(d.title_text_id, (d.id, "title"));
+        text_fields.insert(d.purpose_text_id, (d.id, "purpose"));
+    };
+
+    let section_texts = texts::table
+        .filter(texts::id.eq_any(&text_ids)
+        .and(texts::lang.eq(lang)))
+        .select((texts::id, texts::section_id))
+        .load::<(Uuid, Option<Uuid>)>(&conn)?;
+
+    let section_ids: Vec<Uuid> = section_texts.iter().filter_map(|(_id, s)| *s).collect();
+
+    let document_sections: BTreeMap<Uuid, Uuid> = sections::table
+        .filter(sections::id.eq_any(section_ids))
+        .load::<Section>(&conn)?
+        .into_iter()
+        .map(|s| (s.id, s.document_id))
+        .collect();
+
+    for (text_id, section_id) in section_texts {
+        if let Some(document_id) = section_id.and_then(|s| document_sections.get(&s)) {
+            text_fields.insert(text_id, (*document_id, "section"));
+        };
+    };
+
+    // Score each text, then sum by document
+    let mut text_scores: BTreeMap<Uuid, f64> = BTreeMap::new();
+    let mut document_terms: BTreeMap<Uuid, BTreeSet<String>> = BTreeMap::new();
+    let mut document_fields: BTreeMap<Uuid, BTreeSet<String>> = BTreeMap::new();
+
+    for h in hits.iter() {
+        let (document_id, field) = match text_fields.get(&h.text_id) {
+            Some(f) => *f,
+            None => continue,
+        };
+
+        let count = if internal { h.internal_count } else { h.open_count };
+
+        let idf = ((total_texts as f64 + 1.0) / (frequency[h.term.as_str()] as f64)).ln();
+
+        let weight = match field {
+            "title" => TITLE_WEIGHT,
+            "purpose" => PURPOSE_WEIGHT,
+            _ => SECTION_WEIGHT,
+        };
+
+        let boost = if h.keyword { KEYWORD_BOOST } else { 1.0 };
+
+        *text_scores.entry(h.text_id).or_insert(0.0) += (1.0 + (count as f64).ln()) * idf * weight * boost;
+
+        document_terms.entry(document_id).or_insert_with(BTreeSet::new).insert(h.term.to_owned());
+        document_fields.entry(document_id).or_insert_with(BTreeSet::new).insert(field.to_string());
+    };
+
+    let mut document_scores: BTreeMap<Uuid, (f64, Uuid, f64)> = BTreeMap::new();
+
+    for (text_id, score) in text_scores {
+        let (document_id, _field) = text_fields[&text_id];
+
+        // keep the best scoring text for the snippet
+        let entry = document_scores.entry(document_id).or_insert((0.0, text_id, score));
+        entry.0 += score;
+
+        if score > entry.2 {
+            entry.1 = text_id;
+            entry.2 = score;
+        };
+    };
+
+    let ranked = document_scores
+        .into_iter()
+        .map(|(id, (score, best_text_id, _))| {
+            // favour documents that match more of the query
+            let coverage = document_terms[&id].len() as f64 / query_terms.len() as f64;
+            (id, score * coverage, best_text_id, document_fields[&id].iter().cloned().collect())
+        })
+        .collect();
+
+    Ok(ranked)
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(|v| v.trim()).filter(|v| !v.is_empty())
+}
+
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b' ' => "+".to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Split content into lower case words
+pub fn tokenize(content: &str) -> Vec<String> {
+    content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Tokenize content and reduce each word to its stem for lang
+pub fn stem_terms(content: &str, lang: &str) -> Vec<String> {
+    let stemmer: &Stemmer = match lang {
+        "fr" => &FR_STEMMER,
+        _ => &EN_STEMMER,
+    };
+
+    tokenize(content)
+        .iter()
+        .map(|w| stemmer.stem(w).to_string())
+        .collect()
+}
+
+/// Render markdown to plain text for indexing and snippets
+pub fn plain_text(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+
+    let mut text = String::new();
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Heading(..)) | Event::End(Tag::Item)
+            | Event::End(Tag::TableCell) => text.push(' '),
+            _ => (),
+        }
+    };
+
+    text.trim().to_string()
+}
+
+/// Keyed hash of a term, so stored terms can be matched without being readable
+pub fn hash_term(stem: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SEARCH_KEY.as_bytes())
+        .expect("HMAC can take a key of any size");
+
+    mac.update(stem.as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn keyword_stems(keywords: &Option<serde_json::Value>, lang: &str) -> Vec<String> {
+    let kws: Vec<rake::KeywordScore> = match keywords {
+        Some(v) => serde_json::from_value(v.clone()).unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    kws.iter()
+        .flat_map(|k| stem_terms(&k.keyword, lang))
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Return the words around the first match in content with matches wrapped in <mark>.
+/// The content is escaped so the snippet is safe to render as html.
+fn highlight_snippet(content: &str, query_stems: &BTreeSet<String>, lang: &str) -> String {
+    let words: Vec<&str> = content.split_whitespace().collect();
+
+    let is_match = |w: &str| stem_terms(w, lang).iter().any(|s| query_stems.contains(s));
+
+    let first = words.iter().position(|w| is_match(w)).unwrap_or(0);
+
+    let start = first.saturating_sub(SNIPPET_WORDS);
+    let end = (first + SNIPPET_WORDS + 1).min(words.len());
+
+    let mut snippet: Vec<String> = Vec::new();
+
+    if start > 0 {
+        snippet.push("&hellip;".to_string());
+    };
+
+    for w in words[start..end].iter() {
+        if is_match(w) {
+            snippet.push(format!("<mark>{}</mark>", escape_html(w)));
+        } else {
+            snippet.push(escape_html(w));
+        };
+    };
+
+    if end < words.len() {
+        snippet.push("&hellip;".to_string());
+    };
+
+    snippet.join(" ")
+}
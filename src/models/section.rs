@@ -1,16 +1,16 @@
-use magic_crypt::MagicCryptTrait;
 use serde::{Serialize, Deserialize};
 use std::str;
+use std::collections::BTreeMap;
 use uuid::Uuid;
 use diesel::prelude::*;
 use diesel::{QueryDsl};
 use chrono::prelude::*;
 use pulldown_cmark::{html, Options, Parser};
 
-use crate::{database, get_keyword_html, process_text_redactions, MAGIC_CRYPT};
-use crate::schema::{sections};
+use crate::{database, get_keyword_html, process_text_redactions};
+use crate::schema::{sections, documents};
 use crate::errors::CustomError;
-use crate::models::{Text, Document, TemplateSection};
+use crate::models::{Text, TextVersion, Document, TemplateSection, check_clearance};
 
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Insertable, Queryable, Associations, Identifiable, Clone)]
 #[table_name = "sections"]
@@ -51,6 +51,32 @@ impl Section {
         Ok(v)
     }
 
+    /// Security classification of the document a section belongs to
+    pub fn get_classification(id: Uuid) -> Result<String, CustomError> {
+        let conn = database::connection()?;
+
+        let classification = sections::table
+            .inner_join(documents::table)
+            .filter(sections::id.eq(id))
+            .select(documents::security_classification)
+            .first::<String>(&conn)?;
+
+        Ok(classification)
+    }
+
+    /// Security classifications of the documents the sections belong to, by section id
+    pub fn get_classification_map(ids: Vec<Uuid>) -> Result<BTreeMap<Uuid, String>, CustomError> {
+        let conn = database::connection()?;
+
+        let classifications = sections::table
+            .inner_join(documents::table)
+            .filter(sections::id.eq_any(ids))
+            .select((sections::id, documents::security_classification))
+            .load::<(Uuid, String)>(&conn)?;
+
+        Ok(classifications.into_iter().collect())
+    }
+
     pub fn update(&self) -> Result<Self, CustomError> {
         let conn = database::connection()?;
         let v = diesel::update(sections::table)
@@ -98,13 +124,16 @@ pub struct ReadableSection {
     pub keywords: String,
     pub lang: String,
     pub character_limit: i32,
+    // head revision of the text, sent back with edits to detect conflicts
+    pub version_number: i32,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
 impl ReadableSection {
 
-    pub fn get_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool) -> Result<ReadableSection, CustomError> {
+    /// Refuses to decrypt content above clearance
+    pub fn get_by_id(id: Uuid, lang: &str, markdown: bool, redact: bool, clearance: &str) -> Result<ReadableSection, CustomError> {
         let conn = database::connection()?;
 
         let section: Section = sections::table
@@ -116,12 +145,11 @@ impl ReadableSection {
         let text = Text::get_text_by_section_id(section.id, lang)
             .expect("Unable to retrieve text");
 
-        let decrypted_content = {
+        check_clearance(clearance, &text.classification()?)?;
 
-            let encrypted_content = &text.content.last().unwrap().clone();
+        let head = TextVersion::get_head(text.id, lang)?;
 
-            MAGIC_CRYPT.decrypt_base64_to_string(encrypted_content).expect("Unable to decrypt")
-        };
+        let decrypted_content = head.decrypt()?;
 
         let processed_text = process_text_redactions(decrypted_content.clone(), redact);
 
@@ -153,6 +181,7 @@ impl ReadableSection {
             keywords,
             lang: lang.to_string(),
             character_limit: template_section.character_limit,
+            version_number: head.version_number,
             created_at: section.created_at,
             updated_at: section.updated_at,
         };
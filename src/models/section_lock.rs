@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl, sql_types};
+use chrono::{Duration, prelude::*};
+
+use crate::database;
+use crate::schema::{section_locks, users};
+use crate::errors::CustomError;
+
+/// Minutes without activity before a section lock lapses
+pub const SECTION_LOCK_MINUTES: i64 = 10;
+
+#[derive(Debug, Serialize, Deserialize, Queryable, QueryableByName, Insertable, Identifiable, PartialEq, Clone)]
+#[table_name = "section_locks"]
+#[primary_key(section_id)]
+/// A soft lock on a section while someone edits it. Others can still save; they are warned.
+pub struct SectionLock {
+    pub section_id: Uuid,
+    pub user_id: Uuid,
+    pub locked_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A lock with the name of the user holding it
+pub struct ReadableSectionLock {
+    pub section_id: Uuid,
+    pub user_name: String,
+    pub user_slug: String,
+    pub locked_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+impl SectionLock {
+    /// Take a free or lapsed lock, or renew the user's own lock.
+    /// Returns the lock holding the section afterwards, which is someone else's if they are still editing.
+    pub fn acquire(section_id: Uuid, user_id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let now = Utc::now().naive_utc();
+        let expires_at = now + Duration::minutes(SECTION_LOCK_MINUTES);
+
+        // one statement, so two editors opening a free section can't both take it.
+        // Renewing keeps locked_at, taking a free or lapsed lock starts it again.
+        let acquired = diesel::sql_query(
+            "INSERT INTO section_locks (section_id, user_id, locked_at, expires_at) VALUES ($1, $2, $3, $4) \
+            ON CONFLICT (section_id) DO UPDATE SET \
+                locked_at = CASE WHEN section_locks.user_id = EXCLUDED.user_id AND section_locks.expires_at > EXCLUDED.locked_at \
+                    THEN section_locks.locked_at ELSE EXCLUDED.locked_at END, \
+                user_id = EXCLUDED.user_id, \
+                expires_at = EXCLUDED.expires_at \
+            WHERE section_locks.expires_at < EXCLUDED.locked_at OR section_locks.user_id = EXCLUDED.user_id \
+            RETURNING *")
+            .bind::<sql_types::Uuid, _>(section_id)
+            .bind::<sql_types::Uuid, _>(user_id)
+            .bind::<sql_types::Timestamp, _>(now)
+            .bind::<sql_types::Timestamp, _>(expires_at)
+            .get_result::<Self>(&conn)
+            .optional()?;
+
+        if let Some(lock) = acquired {
+            return Ok(lock)
+        };
+
+        // someone else is editing, unless they let go in the meantime
+        let current = section_locks::table
+            .filter(section_locks::section_id.eq(section_id))
+            .first::<Self>(&conn)
+            .optional()?;
+
+        match current {
+            Some(lock) => Ok(lock),
+            None => SectionLock::acquire(section_id, user_id),
+        }
+    }
+
+    /// Give up the user's lock, e.g. after saving
+    pub fn release(section_id: Uuid, user_id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let res = diesel::delete(section_locks::table
+            .filter(section_locks::section_id.eq(section_id)
+            .and(section_locks::user_id.eq(user_id))))
+            .execute(&conn)?;
+
+        Ok(res)
+    }
+
+    /// Remove a lock whoever holds it. For admins.
+    pub fn break_lock(section_id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let res = diesel::delete(section_locks::table
+            .filter(section_locks::section_id.eq(section_id)))
+            .execute(&conn)?;
+
+        Ok(res)
+    }
+
+    /// Unexpired locks on sections by section_id
+    pub fn get_active_map(section_ids: Vec<Uuid>) -> Result<BTreeMap<Uuid, ReadableSectionLock>, CustomError> {
+        let conn = database::connection()?;
+
+        let locks = section_locks::table
+            .inner_join(users::table)
+            .filter(section_locks::section_id.eq_any(section_ids)
+            .and(section_locks::expires_at.gt(Utc::now().naive_utc())))
+            .select((
+                section_locks::section_id,
+                users::user_name,
+                users::slug,
+                section_locks::locked_at,
+                section_locks::expires_at,
+            ))
+            .load::<(Uuid, String, String, NaiveDateTime, NaiveDateTime)>(&conn)?;
+
+        let mut treemap = BTreeMap::new();
+
+        for (section_id, user_name, user_slug, locked_at, expires_at) in locks {
+            treemap.insert(section_id, ReadableSectionLock {
+                section_id,
+                user_name,
+                user_slug,
+                locked_at,
+                expires_at,
+            });
+        };
+
+        Ok(treemap)
+    }
+
+    /// Unexpired lock on a section
+    pub fn get_active(section_id: Uuid) -> Result<Option<ReadableSectionLock>, CustomError> {
+        Ok(SectionLock::get_active_map(vec![section_id])?.remove(&section_id))
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::NaiveDateTime;
+
+use crate::database;
+use crate::schema::{texts, sections, documents};
+use crate::errors::CustomError;
+use crate::models::{TextVersion, MetaData, ReadableSection, has_clearance};
+
+/// Order of documents in the translator queue by lifecycle state. Published documents are read
+/// by the public, so their translations come first.
+pub const TRANSLATION_PRIORITY: [&str; 6] = [
+    "published",
+    "approved",
+    "in_review",
+    "draft",
+    "withdrawn",
+    "archived",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A section whose text in lang no longer matches its text in source_lang
+pub struct StaleTranslation {
+    pub document_id: Uuid,
+    pub document_title: String,
+    pub document_state: String,
+    pub section_id: Uuid,
+    pub section_header: String,
+    pub text_id: Uuid,
+    pub lang: String,
+    pub source_lang: String,
+    pub source_version: i32,
+    pub source_updated_at: NaiveDateTime,
+}
+
+impl StaleTranslation {
+    /// Every out of date section text the clearance allows reading, by document priority
+    /// and then longest out of date first. Titles and headers are in display_lang.
+    pub fn get_queue(display_lang: &str, clearance: &str) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let section_texts: Vec<(Uuid, Uuid, Uuid, String, String)> = texts::table
+            .inner_join(sections::table.inner_join(documents::table))
+            .select((texts::id, sections::id, documents::id, documents::state, documents::security_classification))
+            .distinct()
+            .load(&conn)?;
+
+        let section_texts: Vec<(Uuid, Uuid, Uuid, String)> = section_texts
+            .into_iter()
+            .filter(|(_, _, _, _, classification)| has_clearance(clearance, classification))
+            .map(|(text_id, section_id, document_id, state, _)| (text_id, section_id, document_id, state))
+            .collect();
+
+        let text_ids: Vec<Uuid> = section_texts.iter().map(|t| t.0).collect();
+
+        let mut heads = BTreeMap::new();
+
+        for lang in ["en", "fr"] {
+            heads.insert(lang, TextVersion::get_heads(text_ids.clone(), lang)?);
+        };
+
+        let mut titles: BTreeMap<Uuid, String> = BTreeMap::new();
+        let mut queue = Vec::new();
+
+        for (text_id, section_id, document_id, state) in section_texts {
+            for (lang, source_lang) in [("en", "fr"), ("fr", "en")] {
+                let (head, source) = match (heads[lang].get(&text_id), heads[source_lang].get(&text_id)) {
+                    (Some(h), Some(s)) => (h, s),
+                    _ => continue,
+                };
+
+                if !head.is_out_of_date(source) {
+                    continue
+                };
+
+                if !titles.contains_key(&document_id) {
+                    let md = MetaData::get_by_document_id(document_id)?;
+
+                    let title = match display_lang {
+                        "fr" => md.searchable_title_fr,
+                        _ => md.searchable_title_en,
+                    };
+
+                    titles.insert(document_id, title);
+                };
+
+                let section = ReadableSection::get_by_id(section_id, display_lang, false, true, clearance)?;
+
+                queue.push(StaleTranslation {
+                    document_id,
+                    document_title: titles[&document_id].clone(),
+                    document_state: state.clone(),
+                    section_id,
+                    section_header: section.header_text,
+                    text_id,
+                    lang: lang.to_string(),
+                    source_lang: source_lang.to_string(),
+                    source_version: source.version_number,
+                    source_updated_at: source.created_at,
+                });
+            };
+        };
+
+        queue.sort_by_key(|s| (
+            TRANSLATION_PRIORITY.iter().position(|p| *p == s.document_state).unwrap_or(TRANSLATION_PRIORITY.len()),
+            s.source_updated_at,
+        ));
+
+        Ok(queue)
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+
+use crate::database;
+use crate::schema::{subjects, categories, keywords, metadata};
+use crate::errors::CustomError;
+use crate::models::MetaData;
+
+/// The bilingual vocabularies used to describe documents in MetaData
+pub const TAXONOMIES: [&str; 3] = [
+    "subjects",
+    "categories",
+    "keywords",
+];
+
+#[derive(Debug, Serialize, Deserialize, Queryable, PartialEq, Clone)]
+/// An entry from one of the subjects, categories or keywords tables.
+/// The tables share the same columns, so entries are loaded the same way.
+pub struct TaxonomyEntry {
+    pub id: Uuid,
+    pub en_string: String,
+    pub fr_string: String,
+    pub en_description: Option<String>,
+    pub fr_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InsertableTaxonomyEntry {
+    pub en_string: String,
+    pub fr_string: String,
+    pub en_description: Option<String>,
+    pub fr_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A taxonomy entry in the language of the user
+pub struct ReadableTaxonomyEntry {
+    pub id: Uuid,
+    pub taxonomy: String,
+    pub name: String,
+    pub description: String,
+    pub link: String,
+}
+
+fn unknown_taxonomy(taxonomy: &str) -> CustomError {
+    CustomError::new(408, format!("Unknown taxonomy: {}", taxonomy))
+}
+
+impl TaxonomyEntry {
+
+    pub fn get_all(taxonomy: &str) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let entries = match taxonomy {
+            "subjects" => subjects::table.order(subjects::en_string.asc()).load::<Self>(&conn)?,
+            "categories" => categories::table.order(categories::en_string.asc()).load::<Self>(&conn)?,
+            "keywords" => keywords::table.order(keywords::en_string.asc()).load::<Self>(&conn)?,
+            _ => return Err(unknown_taxonomy(taxonomy)),
+        };
+
+        Ok(entries)
+    }
+
+    pub fn get_by_id(taxonomy: &str, id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let entry = match taxonomy {
+            "subjects" => subjects::table.filter(subjects::id.eq(id)).first::<Self>(&conn)?,
+            "categories" => categories::table.filter(categories::id.eq(id)).first::<Self>(&conn)?,
+            "keywords" => keywords::table.filter(keywords::id.eq(id)).first::<Self>(&conn)?,
+            _ => return Err(unknown_taxonomy(taxonomy)),
+        };
+
+        Ok(entry)
+    }
+
+    pub fn get_by_ids(taxonomy: &str, ids: Vec<Uuid>) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let entries = match taxonomy {
+            "subjects" => subjects::table.filter(subjects::id.eq_any(ids)).load::<Self>(&conn)?,
+            "categories" => categories::table.filter(categories::id.eq_any(ids)).load::<Self>(&conn)?,
+            "keywords" => keywords::table.filter(keywords::id.eq_any(ids)).load::<Self>(&conn)?,
+            _ => return Err(unknown_taxonomy(taxonomy)),
+        };
+
+        Ok(entries)
+    }
+
+    pub fn create(taxonomy: &str, entry: &InsertableTaxonomyEntry) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        entry.validate()?;
+
+        let v = match taxonomy {
+            "subjects" => diesel::insert_into(subjects::table)
+                .values((
+                    subjects::en_string.eq(&entry.en_string),
+                    subjects::fr_string.eq(&entry.fr_string),
+                    subjects::en_description.eq(&entry.en_description),
+                    subjects::fr_description.eq(&entry.fr_description),
+                ))
+                .get_result(&conn)?,
+            "categories" => diesel::insert_into(categories::table)
+                .values((
+                    categories::en_string.eq(&entry.en_string),
+                    categories::fr_string.eq(&entry.fr_string),
+                    categories::en_description.eq(&entry.en_description),
+                    categories::fr_description.eq(&entry.fr_description),
+                ))
+                .get_result(&conn)?,
+            "keywords" => diesel::insert_into(keywords::table)
+                .values((
+                    keywords::en_string.eq(&entry.en_string),
+                    keywords::fr_string.eq(&entry.fr_string),
+                    keywords::en_description.eq(&entry.en_description),
+                    keywords::fr_description.eq(&entry.fr_description),
+                ))
+                .get_result(&conn)?,
+            _ => return Err(unknown_taxonomy(taxonomy)),
+        };
+
+        Ok(v)
+    }
+
+    pub fn update(taxonomy: &str, id: Uuid, entry: &InsertableTaxonomyEntry) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        entry.validate()?;
+
+        let v = match taxonomy {
+            "subjects" => diesel::update(subjects::table.filter(subjects::id.eq(id)))
+                .set((
+                    subjects::en_string.eq(&entry.en_string),
+                    subjects::fr_string.eq(&entry.fr_string),
+                    subjects::en_description.eq(&entry.en_description),
+                    subjects::fr_description.eq(&entry.fr_description),
+                ))
+                .get_result(&conn)?,
+            "categories" => diesel::update(categories::table.filter(categories::id.eq(id)))
+                .set((
+                    categories::en_string.eq(&entry.en_string),
+                    categories::fr_string.eq(&entry.fr_string),
+                    categories::en_description.eq(&entry.en_description),
+                    categories::fr_description.eq(&entry.fr_description),
+                ))
+                .get_result(&conn)?,
+            "keywords" => diesel::update(keywords::table.filter(keywords::id.eq(id)))
+                .set((
+                    keywords::en_string.eq(&entry.en_string),
+                    keywords::fr_string.eq(&entry.fr_string),
+                    keywords::en_description.eq(&entry.en_description),
+                    keywords::fr_description.eq(&entry.fr_description),
+                ))
+                .get_result(&conn)?,
+            _ => return Err(unknown_taxonomy(taxonomy)),
+        };
+
+        Ok(v)
+    }
+
+    /// Delete an entry and remove it from any MetaData that uses it
+    pub fn delete(taxonomy: &str, id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let res = match taxonomy {
+            "subjects" => {
+                diesel::update(metadata::table.filter(metadata::subject_id.eq(id)))
+                    .set(metadata::subject_id.eq(None::<Uuid>))
+                    .execute(&conn)?;
+
+                diesel::delete(subjects::table.filter(subjects::id.eq(id))).execute(&conn)?
+            },
+            "categories" => {
+                diesel::update(metadata::table.filter(metadata::category_id.eq(id)))
+                    .set(metadata::category_id.eq(None::<Uuid>))
+                    .execute(&conn)?;
+
+                diesel::delete(categories::table.filter(categories::id.eq(id))).execute(&conn)?
+            },
+            "keywords" => {
+                let tagged = metadata::table
+                    .filter(metadata::keyword_ids.is_not_null())
+                    .load::<MetaData>(&conn)?;
+
+                for mut m in tagged {
+                    if let Some(ids) = m.keyword_ids.as_mut() {
+                        if ids.contains(&id) {
+                            ids.retain(|k| *k != id);
+                            MetaData::update(&m)?;
+                        };
+                    };
+                };
+
+                diesel::delete(keywords::table.filter(keywords::id.eq(id))).execute(&conn)?
+            },
+            _ => return Err(unknown_taxonomy(taxonomy)),
+        };
+
+        Ok(res)
+    }
+
+    /// Names of entries by id in lang
+    pub fn get_name_map(taxonomy: &str, ids: Vec<Uuid>, lang: &str) -> Result<BTreeMap<Uuid, String>, CustomError> {
+        let entries = TaxonomyEntry::get_by_ids(taxonomy, ids)?;
+
+        Ok(entries
+            .into_iter()
+            .map(|e| (e.id, e.name(lang).to_string()))
+            .collect())
+    }
+
+    pub fn name(&self, lang: &str) -> &str {
+        match lang {
+            "fr" => &self.fr_string,
+            _ => &self.en_string,
+        }
+    }
+
+    pub fn to_readable(&self, taxonomy: &str, lang: &str) -> ReadableTaxonomyEntry {
+        let description = match lang {
+            "fr" => &self.fr_description,
+            _ => &self.en_description,
+        };
+
+        ReadableTaxonomyEntry {
+            id: self.id,
+            taxonomy: taxonomy.to_string(),
+            name: self.name(lang).to_string(),
+            description: description.clone().unwrap_or_default(),
+            link: format!("/{}/taxonomy/{}/{}", lang, taxonomy, self.id),
+        }
+    }
+}
+
+impl InsertableTaxonomyEntry {
+    pub fn new(en_string: &str, fr_string: &str, en_description: &str, fr_description: &str) -> Self {
+
+        let description = |d: &str| match d.trim() {
+            "" => None,
+            d => Some(d.to_string()),
+        };
+
+        InsertableTaxonomyEntry {
+            en_string: en_string.trim().to_string(),
+            fr_string: fr_string.trim().to_string(),
+            en_description: description(en_description),
+            fr_description: description(fr_description),
+        }
+    }
+
+    fn validate(&self) -> Result<(), CustomError> {
+        if self.en_string.is_empty() || self.fr_string.is_empty() {
+            return Err(CustomError::new(400, "English and French names are required".to_string()))
+        };
+
+        Ok(())
+    }
+}
@@ -6,12 +6,11 @@ use diesel::{BelongingToDsl, QueryDsl};
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 use inflector::Inflector;
-use std::sync::Arc;
 
 use crate::errors::CustomError;
 use crate::database;
 use crate::schema::{templates, texts};
-use crate::models::{Text, InsertableText, TemplateSection, ReadableTemplateSection, machine_translate_text};
+use crate::models::{Text, InsertableText, TemplateSection, ReadableTemplateSection, Job, UNCLASSIFIED};
 
 #[derive(Debug, Serialize, Deserialize, Insertable, AsChangeset, Queryable, Identifiable)]
 /// Core data structure which to build a Document
@@ -137,7 +136,7 @@ impl Template {
         text_ids.push(template.name_text_id);
         text_ids.push(template.purpose_text_id);
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, UNCLASSIFIED)?;
 
         let readable_template = ReadableTemplate {
             id: template.id,
@@ -184,7 +183,7 @@ impl Template {
             text_ids.push(section.help_text_id);
         }
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, UNCLASSIFIED)?;
 
         let readable_template = ReadableTemplate {
             id: template.id,
@@ -238,7 +237,7 @@ impl Template {
             text_ids.push(template.purpose_text_id);
         };
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, UNCLASSIFIED)?;
 
         let mut readable_templates = Vec::new();
 
@@ -284,7 +283,7 @@ impl Template {
 
         let sections = sections.grouped_by(&templates);
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, UNCLASSIFIED)?;
 
         let v = templates
             .into_iter()
@@ -349,17 +348,8 @@ impl InsertableTemplate {
         let purpose_text = Text::create(&insertable_purpose_text, machine_translate)?;
 
         // translations
-        let mut texts_to_translate = Vec::new();
-        texts_to_translate.push(name_text.clone());
-        texts_to_translate.push(purpose_text.clone());
-
-        let t = Arc::new(texts_to_translate);
-
-        let _translated = tokio::spawn(
-            machine_translate_text(
-                t, Arc::new(lang.to_string())
-            )
-        );
+        Job::enqueue_translation(name_text.id, &lang)?;
+        Job::enqueue_translation(purpose_text.id, &lang)?;
 
 
         Ok(InsertableTemplate {
@@ -6,7 +6,7 @@ use uuid::Uuid;
 use crate::errors::CustomError;
 use crate::database;
 use crate::schema::{template_sections, texts};
-use crate::models::{Text, InsertableText, Template};
+use crate::models::{Text, InsertableText, Template, UNCLASSIFIED};
 
 #[derive(Debug, Serialize, Deserialize, AsChangeset, Insertable, Queryable, Associations, Identifiable, Clone)]
 #[table_name = "template_sections"]
@@ -117,7 +117,7 @@ impl TemplateSection {
         text_ids.push(template_section.header_text_id);
         text_ids.push(template_section.help_text_id);
 
-        let texts = Text::get_text_map(text_ids, lang)?;
+        let texts = Text::get_text_map(text_ids, lang, UNCLASSIFIED)?;
 
         let limit = if let Some(i) = template_section.character_limit {
             i
@@ -0,0 +1,393 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::{NaiveDateTime, Utc};
+
+use crate::database;
+use crate::schema::{terms, term_warnings, texts};
+use crate::errors::CustomError;
+use crate::models::{TextVersion, plain_text, stem_terms};
+
+const PLACEHOLDER: &str = "default_translation_traduction_par_defaut";
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Clone)]
+#[table_name = "terms"]
+/// An approved English and French rendering of a term, with renderings that shouldn't be used
+pub struct Term {
+    pub id: Uuid,
+    pub en_term: String,
+    pub fr_term: String,
+    pub en_deprecated: Vec<String>,
+    pub fr_deprecated: Vec<String>,
+    pub note: Option<String>,
+    pub created_by_id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable, Clone)]
+#[table_name = "terms"]
+pub struct InsertableTerm {
+    pub en_term: String,
+    pub fr_term: String,
+    pub en_deprecated: Vec<String>,
+    pub fr_deprecated: Vec<String>,
+    pub note: Option<String>,
+    pub created_by_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Clone)]
+/// A term found in one language of a text whose other language doesn't use the approved rendering
+pub struct TermWarning {
+    pub id: Uuid,
+    pub text_id: Uuid,
+    pub lang: String,
+    pub term_id: Uuid,
+    pub found: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable, Clone, PartialEq)]
+#[table_name = "term_warnings"]
+pub struct InsertableTermWarning {
+    pub text_id: Uuid,
+    pub lang: String,
+    pub term_id: Uuid,
+    pub found: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A warning for the version of a text in lang: source_term is in the other version,
+/// approved_term is how lang should render it and found the deprecated rendering used instead, if any
+pub struct ReadableTermWarning {
+    pub term_id: Uuid,
+    pub lang: String,
+    pub source_term: String,
+    pub approved_term: String,
+    pub found: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+/// Outcome of importing a termbase file
+pub struct TermImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl Term {
+    pub fn get_all() -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = terms::table
+            .order(terms::en_term.asc())
+            .load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn get_by_id(id: Uuid) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = terms::table
+            .filter(terms::id.eq(id))
+            .first::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn create(term: &InsertableTerm) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        term.validate()?;
+
+        let v = diesel::insert_into(terms::table)
+            .values(term)
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn update(id: Uuid, term: &InsertableTerm) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        term.validate()?;
+
+        let v = diesel::update(terms::table.filter(terms::id.eq(id)))
+            .set((
+                terms::en_term.eq(&term.en_term),
+                terms::fr_term.eq(&term.fr_term),
+                terms::en_deprecated.eq(&term.en_deprecated),
+                terms::fr_deprecated.eq(&term.fr_deprecated),
+                terms::note.eq(&term.note),
+                terms::updated_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Delete a term. Its warnings go with it.
+    pub fn delete(id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let res = diesel::delete(terms::table.filter(terms::id.eq(id))).execute(&conn)?;
+
+        Ok(res)
+    }
+
+    /// Add imported terms. A term already in the termbase with the same English and French
+    /// renderings (ignoring case) takes the imported deprecated renderings and note.
+    pub fn import(entries: &[InsertableTerm]) -> Result<TermImportReport, CustomError> {
+        let mut existing: BTreeMap<(String, String), Term> = Term::get_all()?
+            .into_iter()
+            .map(|t| ((t.en_term.to_lowercase(), t.fr_term.to_lowercase()), t))
+            .collect();
+
+        let mut report = TermImportReport::default();
+
+        for entry in entries {
+            let key = (entry.en_term.to_lowercase(), entry.fr_term.to_lowercase());
+
+            match existing.get(&key) {
+                Some(t) if t.en_deprecated == entry.en_deprecated
+                    && t.fr_deprecated == entry.fr_deprecated
+                    && (t.note == entry.note || entry.note.is_none()) => {
+                    report.unchanged += 1;
+                },
+                Some(t) => {
+                    let mut merged = entry.clone();
+
+                    merged.en_term = t.en_term.to_owned();
+                    merged.fr_term = t.fr_term.to_owned();
+                    merged.note = entry.note.clone().or_else(|| t.note.clone());
+
+                    let v = Term::update(t.id, &merged)?;
+
+                    existing.insert(key, v);
+                    report.updated += 1;
+                },
+                None => {
+                    let v = Term::create(entry)?;
+
+                    existing.insert(key, v);
+                    report.created += 1;
+                },
+            };
+        };
+
+        Ok(report)
+    }
+
+    /// (source, target) pairs for a translation from source_lang, one per source term
+    pub fn glossary(source_lang: &str) -> Result<Vec<(String, String)>, CustomError> {
+        let mut seen = BTreeSet::new();
+        let mut entries = Vec::new();
+
+        for t in Term::get_all()? {
+            let (source, target) = match source_lang {
+                "fr" => (t.fr_term, t.en_term),
+                _ => (t.en_term, t.fr_term),
+            };
+
+            if seen.insert(source.to_lowercase()) {
+                entries.push((source, target));
+            };
+        };
+
+        Ok(entries)
+    }
+
+    pub fn term(&self, lang: &str) -> &str {
+        match lang {
+            "fr" => &self.fr_term,
+            _ => &self.en_term,
+        }
+    }
+
+    pub fn deprecated(&self, lang: &str) -> &[String] {
+        match lang {
+            "fr" => &self.fr_deprecated,
+            _ => &self.en_deprecated,
+        }
+    }
+}
+
+impl InsertableTerm {
+    pub fn new(
+        en_term: &str,
+        fr_term: &str,
+        en_deprecated: Vec<String>,
+        fr_deprecated: Vec<String>,
+        note: &str,
+        created_by_id: Uuid,
+    ) -> Self {
+
+        let note = match note.trim() {
+            "" => None,
+            n => Some(n.to_string()),
+        };
+
+        InsertableTerm {
+            en_term: en_term.trim().to_string(),
+            fr_term: fr_term.trim().to_string(),
+            en_deprecated: clean_variants(en_deprecated),
+            fr_deprecated: clean_variants(fr_deprecated),
+            note,
+            created_by_id,
+        }
+    }
+
+    fn validate(&self) -> Result<(), CustomError> {
+        if self.en_term.is_empty() || self.fr_term.is_empty() {
+            return Err(CustomError::new(400, "English and French terms are required".to_string()))
+        };
+
+        Ok(())
+    }
+}
+
+impl TermWarning {
+    /// Compare the English and French versions of a text against the termbase and replace its warnings
+    pub fn check_text(text_id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        diesel::delete(term_warnings::table.filter(term_warnings::text_id.eq(text_id)))
+            .execute(&conn)?;
+
+        let (en, fr) = match (TextVersion::get_head(text_id, "en"), TextVersion::get_head(text_id, "fr")) {
+            (Ok(en), Ok(fr)) => (en.decrypt()?, fr.decrypt()?),
+            _ => return Ok(0),
+        };
+
+        // nothing to compare until the text is translated
+        if en == PLACEHOLDER || fr == PLACEHOLDER {
+            return Ok(0)
+        };
+
+        let warnings = check_versions(text_id, &en, &fr, &Term::get_all()?);
+
+        let inserted = diesel::insert_into(term_warnings::table)
+            .values(&warnings)
+            .execute(&conn)?;
+
+        Ok(inserted)
+    }
+
+    /// Check every text again. Run after the termbase changes.
+    pub fn check_all() -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let text_ids: Vec<Uuid> = texts::table
+            .filter(texts::lang.eq("fr"))
+            .select(texts::id)
+            .load(&conn)?;
+
+        let mut warnings = 0;
+
+        for id in text_ids {
+            warnings += TermWarning::check_text(id)?;
+        };
+
+        Ok(warnings)
+    }
+
+    /// Warnings for the versions of texts in lang, by text
+    pub fn get_map(text_ids: Vec<Uuid>, lang: &str) -> Result<BTreeMap<Uuid, Vec<ReadableTermWarning>>, CustomError> {
+        let conn = database::connection()?;
+
+        let warnings = term_warnings::table
+            .inner_join(terms::table)
+            .filter(term_warnings::text_id.eq_any(text_ids))
+            .filter(term_warnings::lang.eq(lang))
+            .order(terms::en_term.asc())
+            .load::<(TermWarning, Term)>(&conn)?;
+
+        let other_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let mut map: BTreeMap<Uuid, Vec<ReadableTermWarning>> = BTreeMap::new();
+
+        for (w, t) in warnings {
+            map.entry(w.text_id).or_default().push(ReadableTermWarning {
+                term_id: t.id,
+                lang: w.lang,
+                source_term: t.term(other_lang).to_string(),
+                approved_term: t.term(lang).to_string(),
+                found: w.found,
+            });
+        };
+
+        Ok(map)
+    }
+}
+
+/// Split a list of renderings separated by semicolons or new lines
+pub fn parse_variants(variants: &str) -> Vec<String> {
+    clean_variants(variants.split([';', '\n']).map(|v| v.to_string()).collect())
+}
+
+fn clean_variants(variants: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+
+    for v in variants {
+        let v = v.trim();
+
+        if !v.is_empty() && !cleaned.iter().any(|c| c.eq_ignore_ascii_case(v)) {
+            cleaned.push(v.to_string());
+        };
+    };
+
+    cleaned
+}
+
+// Each term found in one version must be rendered as approved in the other.
+// A deprecated rendering is flagged even when the approved one is also used.
+fn check_versions(text_id: Uuid, en: &str, fr: &str, terms: &[Term]) -> Vec<InsertableTermWarning> {
+    let stems: BTreeMap<&str, Vec<String>> = [
+        ("en", stem_terms(&plain_text(en), "en")),
+        ("fr", stem_terms(&plain_text(fr), "fr")),
+    ].into_iter().collect();
+
+    let mut warnings = Vec::new();
+
+    for term in terms {
+        for (source_lang, lang) in [("en", "fr"), ("fr", "en")] {
+            if !contains_term(&stems[source_lang], term.term(source_lang), source_lang) {
+                continue
+            };
+
+            let found = term.deprecated(lang)
+                .iter()
+                .find(|d| contains_term(&stems[lang], d, lang))
+                .cloned();
+
+            if found.is_some() || !contains_term(&stems[lang], term.term(lang), lang) {
+                warnings.push(InsertableTermWarning {
+                    text_id,
+                    lang: lang.to_string(),
+                    term_id: term.id,
+                    found,
+                });
+            };
+        };
+    };
+
+    warnings
+}
+
+// Whether the words of term appear together in stems, ignoring case and inflection
+fn contains_term(stems: &[String], term: &str, lang: &str) -> bool {
+    let term_stems = stem_terms(term, lang);
+
+    if term_stems.is_empty() {
+        return false
+    };
+
+    stems.windows(term_stems.len()).any(|w| w == term_stems.as_slice())
+}
@@ -1,4 +1,3 @@
-use magic_crypt::MagicCryptTrait;
 use uuid::Uuid;
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
@@ -8,32 +7,30 @@ use std::str;
 use std::iter::zip;
 use std::sync::{Arc};
 use pulldown_cmark::{html, Options, Parser};
-use deepl_api::{DeepL, TranslatableTextList};
 
-use crate::{database, run_rake, get_keyword_html, process_text_redactions, MAGIC_CRYPT};
-use crate::schema::texts;
+use crate::{database, realtime, run_rake, get_keyword_html, process_text_redactions, translator, KEYRING};
+use crate::encryption::Keyring;
+use crate::schema::{texts, sections};
 use crate::errors::CustomError;
-use crate::models::{InsertableMetaData, MetaData, Section};
+use crate::realtime::DocumentEvent;
+use crate::models::{Document, Section, User, SearchTerm, TextVersion, Job, TranslationPair, TranslationDraft, Term, TermWarning, QaIssue,
+    UNCLASSIFIED, check_clearance, highest_classification};
 
 
-#[derive(Debug, Serialize, Deserialize, AsChangeset, Queryable, Insertable, Clone)]
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone)]
 #[table_name = "texts"]
+/// A text in one language. Its revisions are TextVersions.
 pub struct Text {
     pub id: Uuid,
     // section_id for the majority of user-entered texts. Exceptions are for texts about documents
     // Might want to make this a different data type
     pub section_id: Option<Uuid>,
     pub lang: String,
-    pub content: Vec<String>,
     pub keywords: Option<serde_json::Value>,
-    pub translated: Vec<bool>,
-    pub machine_translation: Vec<bool>,
-    pub created_at: Vec<NaiveDateTime>,
-    pub created_by_id: Vec<Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-/// A struct for the text that is returned to the user (latest element of vec of changes)
+/// A struct for the text that is returned to the user (head revision)
 pub struct LatestText {
     pub id: Uuid,
     pub section_id: Option<Uuid>,
@@ -44,16 +41,16 @@ pub struct LatestText {
     pub machine_translation: bool,
     pub created_at: NaiveDateTime,
     pub created_by_id: Uuid,
+    // revision the content comes from, sent back with edits to detect conflicts
+    pub version_number: i32,
 }
 
 impl LatestText {
     pub fn get_from(text: Text, markdown: bool, redact: bool) -> Self {
 
-        let decrypted_content = {
-            let encrypted_content = &text.content.last().unwrap().clone()[..];
-            
-            MAGIC_CRYPT.decrypt_base64_to_string(encrypted_content).expect("Unable to decrypt string")
-        };
+        let head = TextVersion::get_head(text.id, &text.lang).expect("Unable to retrieve text version");
+
+        let decrypted_content = head.decrypt().expect("Unable to decrypt string");
 
         let processed_text = process_text_redactions(decrypted_content.clone(), redact);
 
@@ -81,10 +78,11 @@ impl LatestText {
             lang: text.lang,
             content: content,
             keywords,
-            translated: *text.translated.last().unwrap(),
-            machine_translation: *text.machine_translation.last().unwrap(),
-            created_at: *text.created_at.last().unwrap(),
-            created_by_id: *text.created_by_id.last().unwrap(),
+            translated: head.translated,
+            machine_translation: head.machine_translation,
+            created_at: head.created_at,
+            created_by_id: head.created_by_id,
+            version_number: head.version_number,
         }
     }
 }
@@ -92,10 +90,17 @@ impl LatestText {
 impl Text {
     pub fn create(text: &InsertableText, machine_translation: bool) -> Result<Self, CustomError> {
 
+        // without a provider the other language starts as the placeholder
+        let machine_translation = machine_translation && translator().enabled();
+
         let conn = database::connection()?;
 
         let v: Text = diesel::insert_into(texts::table)
-            .values(text)
+            .values((
+                texts::section_id.eq(text.section_id),
+                texts::lang.eq(&text.lang),
+                texts::keywords.eq(&text.keywords),
+            ))
             .get_result(&conn)?;
 
         let translation_lang = match text.lang.as_str() {
@@ -103,47 +108,123 @@ impl Text {
             _ => "en",
         };
 
-        let l = Arc::new(v.lang.to_owned().clone());
-
-        if machine_translation {
-            let _translate = tokio::spawn(
-                machine_translate_text(
-                    Arc::new(vec![v.clone()]), 
-                    l,
-                )
-            );
-        } else {
+        // The placeholder is saved before the content so it is older and shows as out of date
+        if !machine_translation {
             let mut translated_text = v.clone();
     
             translated_text.lang = translation_lang.to_string();
     
-            let encrypted_content = MAGIC_CRYPT.encrypt_str_to_base64("default_translation_traduction_par_defaut");
-    
-            translated_text.content = vec![encrypted_content];
+            // encrypted at the same classification as the content
+            let encrypted_content = KEYRING.encrypt_for(
+                &Keyring::key_of(&text.content).0, "default_translation_traduction_par_defaut")?;
     
             let _t: Text = diesel::insert_into(texts::table)
                 .values(&translated_text)
                 .get_result(&conn)?;
+
+            TextVersion::append(
+                v.id,
+                translation_lang,
+                encrypted_content,
+                false,
+                None,
+                text.created_by_id,
+                None,
+            )?;
         };
 
+        TextVersion::append(
+            v.id,
+            &v.lang,
+            text.content.to_owned(),
+            text.machine_translation,
+            None,
+            text.created_by_id,
+            None,
+        )?;
+
+        SearchTerm::index_text(&v)?;
+
+        if machine_translation {
+            Job::enqueue_translation(v.id, &v.lang)?;
+        };
+
+        v.publish_update(text.created_by_id)?;
 
         Ok(v)
     }
 
-    pub fn update_or_create(text: &Text) -> Result<Self, CustomError> {
+    /// Save the Text, creating it in its language if needed, and add content as its head revision.
+    /// A source_version records the content as a translation of that revision of the other language.
+    /// With a base_version, fails with an edit conflict if another revision was saved since.
+    /// Refused if it would leave a redaction in one language of a published document.
+    pub fn update_or_create(
+        text: &Text,
+        content: &str,
+        machine_translation: bool,
+        source_version: Option<i32>,
+        created_by_id: Uuid,
+        base_version: Option<i32>,
+    ) -> Result<Self, CustomError> {
+
+        QaIssue::check_save(text.id, &text.lang, content)?;
 
         let conn = database::connection()?;
 
-        let v: Text = diesel::insert_into(texts::table)
+        diesel::insert_into(texts::table)
             .values(text)
             .on_conflict((texts::id, texts::lang))
-            .do_update()
-            .set(text)
+            .do_nothing()
+            .execute(&conn)?;
+
+        let encrypted_content = KEYRING.encrypt_for(&text.document_classification()?, content)?;
+
+        TextVersion::append(text.id, &text.lang, encrypted_content, machine_translation, source_version, created_by_id, base_version)?;
+
+        let v: Text = diesel::update(texts::table
+            .filter(texts::id.eq(text.id)
+            .and(texts::lang.eq(&text.lang))))
+            .set(texts::keywords.eq(&text.keywords))
             .get_result(&conn)?;
 
+        SearchTerm::index_text(&v)?;
+
+        TermWarning::check_text(v.id)?;
+
+        // human translations go into the translation memory
+        if !machine_translation && source_version.is_some() {
+            Job::enqueue_learning(v.id, &v.lang)?;
+        };
+
+        v.publish_update(created_by_id)?;
+
         Ok(v)
     }
 
+    // Tell everyone with the document open that a section changed so their pages refresh it,
+    // whether it was saved from the editor, an import or a background job
+    fn publish_update(&self, updated_by_id: Uuid) -> Result<(), CustomError> {
+        let section_id = match self.section_id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let section = Section::get_by_id(section_id)?;
+
+        let updated_by = User::find(updated_by_id)
+            .map(|u| u.user_name)
+            .unwrap_or_default();
+
+        realtime::publish(section.document_id, DocumentEvent::SectionUpdated {
+            section_id,
+            text_id: self.id,
+            lang: self.lang.to_owned(),
+            updated_by,
+        });
+
+        Ok(())
+    }
+
     pub fn get_text_by_id(text_id: Uuid, lang: &str) -> Result<Text, CustomError> {
         let conn = database::connection()?;
         let text = texts::table
@@ -154,6 +235,13 @@ impl Text {
         Ok(text)
     }
 
+    /// Delete a Text in both languages with its revisions
+    pub fn delete(text_id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+        let res = diesel::delete(texts::table.filter(texts::id.eq(text_id))).execute(&conn)?;
+        Ok(res)
+    }
+
     pub fn get_text_by_section_id(section_id: Uuid, lang: &str) -> Result<Text, CustomError> {
         let conn = database::connection()?;
         let text = texts::table
@@ -164,30 +252,123 @@ impl Text {
         Ok(text)
     }
 
-    pub fn get_text_map(ids: Vec<Uuid>, lang: &str) -> Result<BTreeMap<Uuid, String>, CustomError> {
+    /// Head content of texts by id. Refuses to decrypt if any text is above clearance.
+    pub fn get_text_map(ids: Vec<Uuid>, lang: &str, clearance: &str) -> Result<BTreeMap<Uuid, String>, CustomError> {
         let conn = database::connection()?;
         let texts = texts::table
-            .filter(texts::id.eq_any(ids)
+            .filter(texts::id.eq_any(&ids)
             .and(texts::lang.eq(lang)))
             .load::<Text>(&conn)?;
 
-        let mut treemap = BTreeMap::new();
+        let heads = TextVersion::get_heads(ids, lang)?;
 
-        for t in texts {
+        let classifications = document_classifications(&texts)?;
 
-            let decrypted_content = {
-                let encrypted_content = &t.content.last().unwrap().clone();
-                
-                MAGIC_CRYPT.decrypt_base64_to_string(encrypted_content).expect("Unable to decrypt string")
-            };
-    
-            // get the latest version of the text
-            treemap.insert(t.id, decrypted_content);
+        for t in texts.iter() {
+            let document_classification = classifications
+                .get(&t.id)
+                .map(|c| c.as_str())
+                .unwrap_or(UNCLASSIFIED);
+
+            let key_classification = heads
+                .get(&t.id)
+                .map(|h| h.key_classification())
+                .unwrap_or_else(|| UNCLASSIFIED.to_string());
+
+            check_clearance(clearance, highest_classification(document_classification, &key_classification))?;
+        };
+
+        let mut treemap = BTreeMap::new();
+
+        for (id, head) in heads {
+            treemap.insert(id, head.decrypt()?);
         };
 
         Ok(treemap)
     }
 
+    /// Classification of the document a section, title or purpose text belongs to. Template texts are unclassified.
+    pub fn document_classification(&self) -> Result<String, CustomError> {
+        match self.section_id {
+            Some(id) => Section::get_classification(id),
+            None => Ok(Document::get_classification_map_by_text(vec![self.id])?
+                .remove(&self.id)
+                .unwrap_or_else(|| UNCLASSIFIED.to_string())),
+        }
+    }
+
+    /// Classification a reader needs clearance for: the document's, or the key's if the head is still under a higher key.
+    /// Re-encryption moves every revision of a text to the same key.
+    pub fn classification(&self) -> Result<String, CustomError> {
+        let document_classification = self.document_classification()?;
+
+        let head = TextVersion::get_head(self.id, &self.lang)?;
+
+        Ok(highest_classification(&document_classification, &head.key_classification()).to_string())
+    }
+
+    /// Not authorized error if the text is above clearance
+    pub fn check_clearance(&self, clearance: &str) -> Result<(), CustomError> {
+        check_clearance(clearance, &self.classification()?)
+    }
+
+    /// Every revision, oldest first. Refuses if the document or the key of any revision is above clearance.
+    pub fn get_versions(&self, clearance: &str) -> Result<Vec<TextVersion>, CustomError> {
+        let versions = TextVersion::get_all(self.id, &self.lang)?;
+
+        let classification = versions
+            .iter()
+            .fold(self.document_classification()?, |c, v| highest_classification(&c, &v.key_classification()).to_string());
+
+        check_clearance(clearance, &classification)?;
+
+        Ok(versions)
+    }
+
+    /// Restore the content of an earlier revision as a new head revision
+    pub fn revert(
+        text_id: Uuid,
+        lang: &str,
+        version_number: i32,
+        created_by_id: Uuid,
+        clearance: &str,
+    ) -> Result<Self, CustomError> {
+        let text = Text::get_text_by_id(text_id, lang)?;
+
+        let versions = text.get_versions(clearance)?;
+
+        let version = match versions.iter().find(|v| v.version_number == version_number) {
+            Some(v) => v,
+            None => return Err(CustomError::new(408, format!("Revision {} not found", version_number))),
+        };
+
+        if Some(version) == versions.last() {
+            return Err(CustomError::new(400, format!("Revision {} is already the current revision", version_number)))
+        };
+
+        Text::update(text_id, version.decrypt()?, lang, created_by_id, false)
+    }
+
+    /// Queue a machine translation of the text in lang from its head revision in the other language
+    pub fn retranslate(text_id: Uuid, lang: &str, clearance: &str) -> Result<Job, CustomError> {
+        let text = Text::get_text_by_id(text_id, lang)?;
+
+        text.check_clearance(clearance)?;
+
+        let source_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let source = TextVersion::get_head(text_id, source_lang)?;
+
+        if source.decrypt()? == "default_translation_traduction_par_defaut" {
+            return Err(CustomError::new(400, format!("The {} text hasn't been written yet", source_lang)))
+        };
+
+        Job::enqueue_translation(text_id, source_lang)
+    }
+
     pub fn update(
         text_id: Uuid,
         content: String, 
@@ -195,61 +376,141 @@ impl Text {
         created_by_id:Uuid,
         machine_translation: bool,
     ) -> Result<Self, CustomError> {
-        let conn = database::connection()?;
+        Text::update_from_version(text_id, content, lang, created_by_id, machine_translation, None, None)
+    }
 
+    /// Update a text edited from revision base_version.
+    /// Fails with an edit conflict if someone else saved a revision in the meantime.
+    /// A source_version marks the edit as a translation of that revision of the other language.
+    pub fn update_from_version(
+        text_id: Uuid,
+        content: String, 
+        lang: &str,
+        created_by_id:Uuid,
+        machine_translation: bool,
+        base_version: Option<i32>,
+        source_version: Option<i32>,
+    ) -> Result<Self, CustomError> {
         let mut text = Text::get_text_by_id(text_id, lang).expect("Unable to retrieve text");
 
         if text.section_id != None {
-            text.keywords = Some(run_rake(&content).unwrap());
+            text.keywords = Some(run_rake(&content, lang).unwrap());
         };
 
-        let encrypted_content = MAGIC_CRYPT.encrypt_str_to_base64(content);
+        let v = Text::update_or_create(&text, &content, machine_translation, source_version, created_by_id, base_version)?;
 
-        
-        text.content.push(encrypted_content);
-        text.translated.push(false);
-        text.machine_translation.push(machine_translation);
-        text.created_by_id.push(created_by_id);
-        text.created_at.push(chrono::Utc::now().naive_utc());
-        
-        
-        let v: Text = diesel::update(texts::table)
-            .filter(texts::id.eq(text_id)
-            .and(texts::lang.eq(lang)))
-            .set(&text)
-            .get_result(&conn)?;
+        // a translation is not translated back over its source
+        if machine_translation && source_version.is_none() {
+            Job::enqueue_translation(v.id, lang)?;
+        };
 
-        let l = Arc::new(lang.to_owned().clone());
+        if let Some(id) = text.section_id {
+            let section = Section::get_by_id(id)?;
 
-        if machine_translation {
-            let _translate = tokio::spawn(machine_translate_text(Arc::new(vec![v.clone()]), l));
+            Job::enqueue_document_analysis(section.document_id, lang)?;
         };
 
-        match text.section_id {
-            Some(id) => {
-                // This is convoluted as heck. Fix it.
-                let section = Section::get_by_id(id).expect("Unable to load section");
-                
-                let l: Arc<String> = Arc::new(lang.to_string());
-                let _r = tokio::spawn(async move {
-                    let md = InsertableMetaData::update_document(
-                        Arc::new(section.document_id), 
-                        l)
-                            .await
-                            .expect("Unable to update document metadata");
-
-                    let current_md = MetaData::get_by_document_id(section.document_id).expect("Unable to find metadata");
-    
-                    MetaData::update_from_metadata(current_md.id, &md)
-                        .expect("Unable to update MetaData");
-                        }
-                );
-            },
-            None => (),
+        Ok(v)
+    }
+
+    /// Re-encrypt every revision of the content with the active key for classification.
+    /// Returns false if the text was already up to date.
+    fn reencrypt(&self, classification: &str) -> Result<bool, CustomError> {
+        let mut updated = false;
+
+        for version in TextVersion::get_all(self.id, &self.lang)? {
+            if version.reencrypt(classification)? {
+                updated = true;
+            };
         };
 
-        Ok(v)
+        Ok(updated)
     }
+
+    /// Re-encrypt every revision of every Text with the active key for its document's classification,
+    /// batch_size rows at a time. Rows already up to date are skipped, so an interrupted run can be restarted.
+    pub fn reencrypt_all(batch_size: i64) -> Result<ReencryptionReport, CustomError> {
+        let conn = database::connection()?;
+
+        let total: i64 = texts::table.count().get_result(&conn)?;
+
+        let mut report = ReencryptionReport {
+            total,
+            checked: 0,
+            updated: 0,
+        };
+
+        let mut offset = 0;
+
+        loop {
+            let batch = texts::table
+                .order((texts::id, texts::lang))
+                .limit(batch_size)
+                .offset(offset)
+                .load::<Text>(&conn)?;
+
+            if batch.is_empty() {
+                break
+            };
+
+            let classifications = document_classifications(&batch)?;
+
+            for t in &batch {
+                let classification = classifications
+                    .get(&t.id)
+                    .map(|c| c.as_str())
+                    .unwrap_or(UNCLASSIFIED);
+
+                if t.reencrypt(classification)? {
+                    report.updated += 1;
+                };
+            };
+
+            offset += batch.len() as i64;
+            report.checked = offset;
+
+            println!("Re-encryption: checked {} of {} texts, {} updated", report.checked, report.total, report.updated);
+        };
+
+        Ok(report)
+    }
+
+    /// Re-encrypt the title, purpose and section texts of a document and its translation memory after its classification changes
+    pub fn reencrypt_document(document_id: Uuid, classification: &str) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let document = Document::get_by_id(document_id)?;
+
+        let mut texts = texts::table
+            .inner_join(sections::table)
+            .filter(sections::document_id.eq(document_id))
+            .select(texts::all_columns)
+            .load::<Text>(&conn)?;
+
+        texts.extend(texts::table
+            .filter(texts::id.eq_any(vec![document.title_text_id, document.purpose_text_id]))
+            .load::<Text>(&conn)?);
+
+        let mut updated = 0;
+
+        for t in texts {
+            if t.reencrypt(classification)? {
+                updated += 1;
+            };
+        };
+
+        TranslationPair::reclassify_document(document_id, classification)?;
+
+        Ok(updated)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Outcome of moving stored texts to the active encryption key
+pub struct ReencryptionReport {
+    pub total: i64,
+    pub checked: i64,
+    pub updated: i64,
 }
 
 impl From<InsertableText> for Text {
@@ -264,26 +525,21 @@ impl From<InsertableText> for Text {
             id: Uuid::new_v4(),
             section_id: text.section_id,
             lang: text.lang,
-            content: text.content,
             keywords,
-            translated: text.translated,
-            machine_translation: text.machine_translation,
-            created_at: vec![chrono::Utc::now().naive_utc()],
-            created_by_id: text.created_by_id,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Insertable, AsChangeset)]
-#[table_name = "texts"]
+#[derive(Debug, Serialize, Deserialize)]
+/// A new Text and its first revision. Content is encrypted.
 pub struct InsertableText {
     pub lang: String,
-    pub content: Vec<String>,
+    pub content: String,
     pub keywords: serde_json::Value,
-    pub translated: Vec<bool>,
-    pub machine_translation: Vec<bool>,
+    pub translated: bool,
+    pub machine_translation: bool,
     pub section_id: Option<Uuid>,
-    pub created_by_id: Vec<Uuid>,
+    pub created_by_id: Uuid,
 }
 
 impl InsertableText {
@@ -296,19 +552,15 @@ impl InsertableText {
         created_by_id: Uuid,
     ) -> Self {
         
-        let keywords = run_rake(&content)
+        let keywords = run_rake(&content, &lang)
             .expect("Unable to get keywords");
 
-        let encrypted_content = MAGIC_CRYPT.encrypt_str_to_base64(content);
-
-        let content = vec![encrypted_content];
-        let translated = vec![translated];
-        let machine_translation = vec![machine_translation];
-        let created_by_id = vec![created_by_id];
+        let encrypted_content = encrypt_for_section(section_id, &content)
+            .expect("Unable to encrypt content");
 
         InsertableText {
             lang,
-            content,
+            content: encrypted_content,
             keywords,
             translated,
             machine_translation,
@@ -323,89 +575,111 @@ impl InsertableText {
         created_by_id: Uuid,
     ) -> Self {
 
-        let keywords = run_rake(&content)
+        let keywords = run_rake(&content, lang)
             .expect("Unable to get keywords");
             
-        let encrypted_content = MAGIC_CRYPT.encrypt_str_to_base64(content);
-
-        let content = vec![encrypted_content];
-        let translated = vec![false];
-        let machine_translation = vec![false];
-        let created_by_id = vec![created_by_id];
+        let encrypted_content = encrypt_for_section(section_id, &content)
+            .expect("Unable to encrypt content");
 
         InsertableText {
             lang: lang.to_owned(),
-            content,
+            content: encrypted_content,
             keywords,
-            translated,
-            machine_translation,
+            translated: false,
+            machine_translation: false,
             section_id,
             created_by_id,
         }
     }
-}
 
-pub async fn machine_translate_text<'a>(texts: Arc<Vec<Text>>, current_lang: Arc<String>) -> Result<Vec<Text>, CustomError> {
-    // goes through Text and sends content Vec<String> to DEEPL for translation if translate_all == true
-    // otherwise, translates only the last content string
-    let key = match std::env::var("DEEPL_API_KEY") {
-        Ok(val) if val.len() > 0 => val,
-        _ => {
-            eprintln!("Error: no DEEPL_API_KEY found. Please provide your API key in this environment variable.");
-            std::process::exit(1);
-        }
-    };
+    /// A title or purpose for a document at classification, which doesn't exist yet.
+    /// Fails if no key is configured for the classification.
+    pub fn for_document(
+        lang: &str,
+        content: String,
+        created_by_id: Uuid,
+        classification: &str,
+    ) -> Result<Self, CustomError> {
 
+        let keywords = run_rake(&content, lang)?;
+
+        let encrypted_content = KEYRING.encrypt_for(classification, &content)?;
+
+        Ok(InsertableText {
+            lang: lang.to_owned(),
+            content: encrypted_content,
+            keywords,
+            translated: false,
+            machine_translation: false,
+            section_id: None,
+            created_by_id,
+        })
+    }
+}
+
+pub async fn machine_translate_text<'a>(texts: Arc<Vec<Text>>, current_lang: Arc<String>, base_versions: Vec<Option<i32>>) -> Result<Vec<Text>, CustomError> {
+    // sends the current content of each Text to the translation provider and saves it in the other language.
+    // Sentences already in the translation memory are taken from it and not sent.
+    // A translation is skipped if the other language has moved past its base version, so edits made meanwhile are kept,
+    // or if it would drop or add a redaction in a published document.
     let texts = &*texts.clone();
 
-    let deepl = DeepL::new(key);
-
-    let mut source = "EN".to_string();
-    let mut target = "FR".to_string();
-
-    let translate_lang = match &*current_lang.clone().as_str() {
-        "en" => {
-            "fr".to_string()
-        },
-        "fr" => {
-            source = "FR".to_string();
-            target = "EN".to_string();
-            "en".to_string()
-        },
-        _ => {
-            "fr".to_string()
-        },
+    if !translator().enabled() {
+        println!("Machine translation is disabled. {} text(s) not translated.", texts.len());
+        return Ok(Vec::new())
     };
 
-    let texts_to_translate: Vec<String> = texts
-        .clone()
-        .into_iter()
-        .map(|t| MAGIC_CRYPT.decrypt_base64_to_string(t.content.last().unwrap().clone()).expect("Unable to decrypt content"))
-        .collect::<Vec<String>>();
-
-    // Set up struct for DEEPL translation
-    let translatable_text = TranslatableTextList {
-        source_language: Some(source),
-        target_language: target,
-        texts: texts_to_translate.clone(),
+    let translate_lang = match current_lang.as_str() {
+        "fr" => "en".to_string(),
+        _ => "fr".to_string(),
     };
 
-    // Send to API
-    let translated = deepl.translate(None, translatable_text)
-        .await
-        .expect("Unable to return translations");
+    let heads = texts
+        .iter()
+        .map(|t| TextVersion::get_head(t.id, &t.lang))
+        .collect::<Result<Vec<TextVersion>, CustomError>>()?;
 
-    let mut translated_texts: Vec<Text> = Vec::new();
+    // only pairs the text's own classification could read are reused in it
+    let drafts = zip(texts, &heads)
+        .map(|(t, h)| TranslationDraft::from_memory(&h.decrypt()?, source_lang(&current_lang), &t.classification()?))
+        .collect::<Result<Vec<TranslationDraft>, CustomError>>()?;
+
+    let sentences_to_translate: Vec<String> = drafts
+        .iter()
+        .flat_map(|d| d.missing())
+        .collect();
 
-    for (mut text, tr) in zip(texts.clone(), translated) {
+    let glossary = Term::glossary(source_lang(&current_lang))?;
 
-        let encrypted_content = MAGIC_CRYPT.encrypt_str_to_base64(tr.text.to_string());
+    let mut machine_translated = translator()
+        .translate_with_glossary(sentences_to_translate, source_lang(&current_lang), &translate_lang, glossary)
+        .await?
+        .into_iter();
+
+    let translated: Vec<String> = drafts
+        .into_iter()
+        .map(|d| d.complete(&mut machine_translated))
+        .collect();
+
+    let mut translated_texts: Vec<Text> = Vec::new();
+
+    for (((mut text, head), tr), base_version) in zip(zip(zip(texts.clone(), heads), translated), base_versions) {
 
-        text.content.push(encrypted_content);
         text.lang = translate_lang.clone();
 
-        let v = Text::update_or_create(&text)
-            .expect("Unable to update translated Text");
+        // keywords copied from the source text are in the wrong language
+        if text.section_id != None {
+            text.keywords = Some(run_rake(&tr, &translate_lang)?);
+        };
+
+        let v = match Text::update_or_create(&text, &tr, true, Some(head.version_number), head.created_by_id, base_version) {
+            Ok(v) => v,
+            Err(err) if err.is_edit_conflict() || err.is_qa_blocked() => {
+                println!("Machine translation of text {} skipped: {}", text.id, err);
+                continue
+            },
+            Err(err) => return Err(err),
+        };
 
         translated_texts.push(v);
     };
@@ -414,52 +688,52 @@ pub async fn machine_translate_text<'a>(texts: Arc<Vec<Text>>, current_lang: Arc
 }
 
 pub async fn machine_translate_string<'a>(texts: Arc<Vec<String>>, current_lang: Arc<String>) -> Result<Vec<String>, CustomError> {
-    // goes through Text and sends content Vec<String> to DEEPL for translation if translate_all == true
-    // otherwise, translates only the last content string
-    let key = match std::env::var("DEEPL_API_KEY") {
-        Ok(val) if val.len() > 0 => val,
-        _ => {
-            eprintln!("Error: no DEEPL_API_KEY found. Please provide your API key in this environment variable.");
-            std::process::exit(1);
-        }
+    // translates strings into the other language without saving them
+    let translate_lang = match current_lang.as_str() {
+        "fr" => "en",
+        _ => "fr",
     };
 
-    let deepl = DeepL::new(key);
-
-    let mut source = "EN".to_string();
-    let mut target = "FR".to_string();
-
-    match &*current_lang.clone().as_str() {
-        "en" => {
-            "fr".to_string()
-        },
-        "fr" => {
-            source = "FR".to_string();
-            target = "EN".to_string();
-            "en".to_string()
-        },
-        _ => {
-            "fr".to_string()
-        },
-    };
+    let glossary = Term::glossary(source_lang(&current_lang))?;
 
-    // Set up struct for DEEPL translation
-    let translatable_text = TranslatableTextList {
-        source_language: Some(source),
-        target_language: target,
-        texts: texts.to_vec(),
+    translator()
+        .translate_with_glossary(texts.to_vec(), source_lang(&current_lang), translate_lang, glossary)
+        .await
+}
+
+// Anything other than French is translated as English
+fn source_lang(lang: &str) -> &str {
+    match lang {
+        "fr" => "fr",
+        _ => "en",
+    }
+}
+
+// Encrypt content with the key for the classification of the section's document.
+// Without a section it is a template text and uses the unclassified key.
+fn encrypt_for_section(section_id: Option<Uuid>, content: &str) -> Result<String, CustomError> {
+    let classification = match section_id {
+        Some(id) => Section::get_classification(id)?,
+        None => UNCLASSIFIED.to_string(),
     };
 
-    // Send to API
-    let translated = deepl.translate(None, translatable_text)
-        .await
-        .expect("Unable to return translations");
+    KEYRING.encrypt_for(&classification, content)
+}
+
+// Classification of the document each text belongs to, through its section or as the document's title or purpose.
+// Template texts belong to no document and are left out.
+fn document_classifications(texts: &[Text]) -> Result<BTreeMap<Uuid, String>, CustomError> {
+    let sections = Section::get_classification_map(
+        texts.iter().filter_map(|t| t.section_id).collect())?;
 
-    let mut translated_texts: Vec<String> = Vec::new();
+    let mut classifications = Document::get_classification_map_by_text(
+        texts.iter().filter(|t| t.section_id.is_none()).map(|t| t.id).collect())?;
 
-    for t in translated {
-        translated_texts.push(t.text);
+    for t in texts {
+        if let Some(c) = t.section_id.and_then(|id| sections.get(&id)) {
+            classifications.insert(t.id, c.to_owned());
+        };
     };
 
-    Ok(translated_texts)
-}
\ No newline at end of file
+    Ok(classifications)
+}
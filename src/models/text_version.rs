@@ -0,0 +1,451 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::dsl;
+use chrono::NaiveDateTime;
+use pulldown_cmark::{html, Options, Parser};
+
+use crate::{database, process_text_redactions, KEYRING};
+use crate::encryption::Keyring;
+use crate::schema::{text_versions};
+use crate::errors::CustomError;
+use crate::models::User;
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, PartialEq, Clone)]
+#[table_name = "text_versions"]
+/// One revision of a Text. The head revision has the highest version_number.
+pub struct TextVersion {
+    pub id: Uuid,
+    pub text_id: Uuid,
+    pub lang: String,
+    pub version_number: i32,
+    // ciphertext
+    pub content: String,
+    pub translated: bool,
+    pub machine_translation: bool,
+    pub created_at: NaiveDateTime,
+    pub created_by_id: Uuid,
+    // for translations, the revision of the other language they were made from
+    pub source_version: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "text_versions"]
+pub struct InsertableTextVersion {
+    pub text_id: Uuid,
+    pub lang: String,
+    pub version_number: i32,
+    pub content: String,
+    pub translated: bool,
+    pub machine_translation: bool,
+    pub created_by_id: Uuid,
+    pub source_version: Option<i32>,
+}
+
+impl TextVersion {
+    pub fn create(version: &InsertableTextVersion) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = diesel::insert_into(text_versions::table)
+            .values(version)
+            .get_result(&conn)?;
+
+        Ok(v)
+    }
+
+    /// Add encrypted content as the new head revision of a Text.
+    /// A source_version marks the revision as a translation of that revision of the other language.
+    /// With a base_version, fails with an edit conflict unless base_version is still the head.
+    /// The unique version number catches an edit saved between the check and the insert.
+    pub fn append(
+        text_id: Uuid,
+        lang: &str,
+        content: String,
+        machine_translation: bool,
+        source_version: Option<i32>,
+        created_by_id: Uuid,
+        base_version: Option<i32>,
+    ) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let head: Option<i32> = text_versions::table
+            .filter(text_versions::text_id.eq(text_id)
+            .and(text_versions::lang.eq(lang)))
+            .select(dsl::max(text_versions::version_number))
+            .first(&conn)?;
+
+        let head = head.unwrap_or(0);
+
+        if let Some(base) = base_version {
+            if base != head {
+                return Err(CustomError::edit_conflict(format!(
+                    "Edit conflict: revision {} was saved after revision {}", head, base)))
+            };
+        };
+
+        TextVersion::create(&InsertableTextVersion {
+            text_id,
+            lang: lang.to_owned(),
+            version_number: head + 1,
+            content,
+            translated: source_version.is_some(),
+            machine_translation,
+            created_by_id,
+            source_version,
+        })
+    }
+
+    /// The latest revision of a Text
+    pub fn get_head(text_id: Uuid, lang: &str) -> Result<Self, CustomError> {
+        let conn = database::connection()?;
+
+        let v = text_versions::table
+            .filter(text_versions::text_id.eq(text_id)
+            .and(text_versions::lang.eq(lang)))
+            .order(text_versions::version_number.desc())
+            .first::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    /// The latest revision of each Text by text_id
+    pub fn get_heads(text_ids: Vec<Uuid>, lang: &str) -> Result<BTreeMap<Uuid, Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let heads = text_versions::table
+            .filter(text_versions::text_id.eq_any(text_ids)
+            .and(text_versions::lang.eq(lang)))
+            .distinct_on(text_versions::text_id)
+            .order((text_versions::text_id, text_versions::version_number.desc()))
+            .load::<Self>(&conn)?;
+
+        Ok(heads.into_iter().map(|v| (v.text_id, v)).collect())
+    }
+
+    /// Texts in lang whose head no longer matches the head of the other language
+    pub fn get_out_of_date(text_ids: Vec<Uuid>, lang: &str) -> Result<Vec<Uuid>, CustomError> {
+        let other_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let heads = TextVersion::get_heads(text_ids.clone(), lang)?;
+        let other_heads = TextVersion::get_heads(text_ids, other_lang)?;
+
+        let out_of_date = heads
+            .values()
+            .filter(|h| other_heads.get(&h.text_id).map(|o| h.is_out_of_date(o)).unwrap_or(false))
+            .map(|h| h.text_id)
+            .collect();
+
+        Ok(out_of_date)
+    }
+
+    /// Whether this head revision needs translating again to match other, the head of the other language.
+    /// A translation is out of date once its source has a newer revision. Of two independent edits,
+    /// the older one is out of date. A translation of an older revision of this one leaves this one current.
+    pub fn is_out_of_date(&self, other: &TextVersion) -> bool {
+        if self.source_version == Some(other.version_number) || other.source_version == Some(self.version_number) {
+            return false
+        };
+
+        if other.translated {
+            return false
+        };
+
+        self.translated || self.created_at < other.created_at
+    }
+
+    /// Every revision of a Text, oldest first
+    pub fn get_all(text_id: Uuid, lang: &str) -> Result<Vec<Self>, CustomError> {
+        let conn = database::connection()?;
+
+        let v = text_versions::table
+            .filter(text_versions::text_id.eq(text_id)
+            .and(text_versions::lang.eq(lang)))
+            .order(text_versions::version_number)
+            .load::<Self>(&conn)?;
+
+        Ok(v)
+    }
+
+    pub fn decrypt(&self) -> Result<String, CustomError> {
+        KEYRING.decrypt(&self.content)
+    }
+
+    /// Classification of the key the content is encrypted with
+    pub fn key_classification(&self) -> String {
+        Keyring::key_of(&self.content).0
+    }
+
+    /// Word-level diff from this revision to another
+    pub fn diff(&self, to: &TextVersion) -> Result<Vec<DiffSegment>, CustomError> {
+        Ok(diff_words(&self.decrypt()?, &to.decrypt()?))
+    }
+
+    /// Re-encrypt the content with the active key for classification.
+    /// Returns false if it was already up to date.
+    pub fn reencrypt(&self, classification: &str) -> Result<bool, CustomError> {
+        if !KEYRING.is_stale(&self.content, classification) {
+            return Ok(false)
+        };
+
+        let conn = database::connection()?;
+
+        let content = KEYRING.reencrypt(&self.content, classification)?;
+
+        diesel::update(text_versions::table.filter(text_versions::id.eq(self.id)))
+            .set(text_versions::content.eq(content))
+            .execute(&conn)?;
+
+        Ok(true)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A revision for the history page with the email of its author
+pub struct ReadableTextVersion {
+    pub version_number: i32,
+    pub created_at: NaiveDateTime,
+    pub author: String,
+    pub translated: bool,
+    pub machine_translation: bool,
+    pub source_version: Option<i32>,
+}
+
+impl ReadableTextVersion {
+    /// Revisions newest first
+    pub fn from_versions(versions: &[TextVersion]) -> Result<Vec<Self>, CustomError> {
+        let authors = User::get_user_email_map(versions.iter().map(|v| v.created_by_id).collect())?;
+
+        let readable = versions
+            .iter()
+            .rev()
+            .map(|v| ReadableTextVersion {
+                version_number: v.version_number,
+                created_at: v.created_at,
+                author: authors.get(&v.created_by_id).cloned().unwrap_or_default(),
+                translated: v.translated,
+                machine_translation: v.machine_translation,
+                source_version: v.source_version,
+            })
+            .collect();
+
+        Ok(readable)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A paragraph of the current content with the revision that last changed it
+pub struct BlameParagraph {
+    pub content: String,
+    pub version_number: i32,
+    pub author: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl BlameParagraph {
+    /// Attribute each paragraph of the last revision to the revision since which it is unchanged.
+    /// Content is rendered from markdown without redactions.
+    pub fn from_versions(versions: &[TextVersion]) -> Result<Vec<Self>, CustomError> {
+
+        // paragraphs of the revision seen so far with the index of the revision that wrote each one
+        let mut blame: Vec<(String, usize)> = Vec::new();
+
+        for (index, version) in versions.iter().enumerate() {
+            let content = version.decrypt()?;
+            let paragraphs = split_paragraphs(&content);
+
+            let previous: Vec<&str> = blame.iter().map(|(p, _)| p.as_str()).collect();
+            let current: Vec<&str> = paragraphs.iter().map(|p| p.as_str()).collect();
+
+            let mut next: Vec<(String, usize)> = paragraphs
+                .iter()
+                .map(|p| (p.to_string(), index))
+                .collect();
+
+            for (i, j) in matching_pairs(&previous, &current) {
+                next[j].1 = blame[i].1;
+            };
+
+            blame = next;
+        };
+
+        let authors = User::get_user_email_map(versions.iter().map(|v| v.created_by_id).collect())?;
+
+        let paragraphs = blame
+            .into_iter()
+            .map(|(paragraph, index)| {
+                let version = &versions[index];
+
+                let mut options = Options::empty();
+                options.insert(Options::ENABLE_TABLES);
+
+                let processed_text = process_text_redactions(paragraph, false);
+                let parser = Parser::new_ext(&processed_text, options);
+
+                let mut content = String::new();
+                html::push_html(&mut content, parser);
+
+                BlameParagraph {
+                    content,
+                    version_number: version.version_number,
+                    author: authors.get(&version.created_by_id).cloned().unwrap_or_default(),
+                    created_at: version.created_at,
+                }
+            })
+            .collect();
+
+        Ok(paragraphs)
+    }
+}
+
+// Paragraphs separated by blank lines, without surrounding whitespace
+fn split_paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join("\n").trim().to_string());
+                current.clear();
+            };
+        } else {
+            current.push(line);
+        };
+    };
+
+    if !current.is_empty() {
+        paragraphs.push(current.join("\n").trim().to_string());
+    };
+
+    paragraphs
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// A run of words that is equal, inserted or deleted between two revisions
+pub struct DiffSegment {
+    pub change: String,
+    pub text: String,
+}
+
+// Above this many comparisons the changed middle is treated as entirely replaced
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Word-level diff of two texts. Words keep their trailing whitespace,
+/// so joining the equal and deleted segments gives old and the equal and inserted segments give new.
+pub fn diff_words(old: &str, new: &str) -> Vec<DiffSegment> {
+    let a = split_words(old);
+    let b = split_words(new);
+
+    let mut segments = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    for (next_i, next_j) in matching_pairs(&a, &b) {
+        push_segment(&mut segments, "delete", &a[i..next_i].concat());
+        push_segment(&mut segments, "insert", &b[j..next_j].concat());
+        push_segment(&mut segments, "equal", a[next_i]);
+
+        i = next_i + 1;
+        j = next_j + 1;
+    };
+
+    push_segment(&mut segments, "delete", &a[i..].concat());
+    push_segment(&mut segments, "insert", &b[j..].concat());
+
+    segments
+}
+
+// Index pairs of a longest common subsequence of a and b, in order.
+// The common prefix and suffix are matched directly. A middle too large to compare has no matches.
+fn matching_pairs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let prefix = a.iter()
+        .zip(b.iter())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let suffix = a[prefix..].iter().rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+
+    let (n, m) = (a.len() - prefix - suffix, b.len() - prefix - suffix);
+
+    if n * m <= MAX_DIFF_CELLS {
+        let a_mid = &a[prefix..prefix + n];
+        let b_mid = &b[prefix..prefix + m];
+
+        // lengths[i][j] is the longest common subsequence of a_mid[i..] and b_mid[j..]
+        let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i][j] = if a_mid[i] == b_mid[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            };
+        };
+
+        let (mut i, mut j) = (0, 0);
+
+        while i < n && j < m {
+            if a_mid[i] == b_mid[j] {
+                pairs.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            };
+        };
+    };
+
+    pairs.extend((0..suffix).map(|k| (a.len() - suffix + k, b.len() - suffix + k)));
+
+    pairs
+}
+
+// Words with their trailing whitespace. Leading whitespace is its own word.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_space = false;
+
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_space = true;
+        } else if in_space {
+            words.push(&text[start..i]);
+            start = i;
+            in_space = false;
+        };
+    };
+
+    if start < text.len() {
+        words.push(&text[start..]);
+    };
+
+    words
+}
+
+fn push_segment(segments: &mut Vec<DiffSegment>, change: &str, text: &str) {
+    if text.is_empty() {
+        return
+    };
+
+    match segments.last_mut() {
+        Some(last) if last.change == change => last.text.push_str(text),
+        _ => segments.push(DiffSegment {
+            change: change.to_string(),
+            text: text.to_string(),
+        }),
+    };
+}
@@ -0,0 +1,592 @@
+use std::collections::BTreeSet;
+use std::iter::zip;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::{database, KEYRING};
+use crate::schema::{translation_pairs, texts, sections};
+use crate::errors::CustomError;
+use crate::models::{Text, TextVersion, SECURITY_CLASSIFICATIONS, has_clearance, hash_term, plain_text, stem_terms, tokenize};
+
+/// Lowest similarity, in percent, for a stored pair to be suggested as a fuzzy match
+pub const FUZZY_MATCH_THRESHOLD: u32 = 70;
+
+// Pairs sharing a word with a segment that are scored for fuzzy matches
+const FUZZY_CANDIDATES: i64 = 200;
+
+// Aligned sentences this many times longer than each other are taken to be misaligned
+const MAX_LENGTH_RATIO: f64 = 2.5;
+
+// Stems shorter than this are mostly articles and prepositions and don't pick useful candidates
+const MIN_CANDIDATE_STEM: usize = 4;
+
+const PLACEHOLDER: &str = "default_translation_traduction_par_defaut";
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[table_name = "translation_pairs"]
+/// An English sentence and its human translation, aligned from a Text. Segments are encrypted.
+pub struct TranslationPair {
+    pub id: Uuid,
+    pub text_id: Uuid,
+    pub en_version: i32,
+    pub fr_version: i32,
+    pub en_segment: String,
+    pub fr_segment: String,
+    pub en_hash: String,
+    pub fr_hash: String,
+    pub en_terms: Vec<String>,
+    pub fr_terms: Vec<String>,
+    pub security_classification: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[table_name = "translation_pairs"]
+pub struct InsertableTranslationPair {
+    pub text_id: Uuid,
+    pub en_version: i32,
+    pub fr_version: i32,
+    pub en_segment: String,
+    pub fr_segment: String,
+    pub en_hash: String,
+    pub fr_hash: String,
+    pub en_terms: Vec<String>,
+    pub fr_terms: Vec<String>,
+    pub security_classification: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// A stored translation of a segment like the one being translated. A score of 100 is an exact match.
+pub struct TranslationMatch {
+    pub source: String,
+    pub target: String,
+    pub score: u32,
+    pub text_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Matches for one sentence of the text being translated
+pub struct SegmentSuggestions {
+    pub segment: String,
+    pub matches: Vec<TranslationMatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// Part of a text. Sentences are translatable; the markdown markers and spacing around them are not.
+/// Joining the pieces of a text gives back the text.
+pub struct Piece {
+    pub text: String,
+    pub translatable: bool,
+    // line of the text the piece is on
+    pub block: usize,
+}
+
+/// A text split into sentences for translation. Sentences with an exact match in the
+/// translation memory are filled in; the rest are left for machine translation.
+pub struct TranslationDraft {
+    pieces: Vec<(Piece, Option<String>)>,
+}
+
+impl TranslationPair {
+    /// Align the current English and French versions of a text into sentence pairs and store them.
+    /// Only texts where both versions are human written and neither is out of date are learned from.
+    pub fn learn_text(text_id: Uuid) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let (en, fr) = match (TextVersion::get_head(text_id, "en"), TextVersion::get_head(text_id, "fr")) {
+            (Ok(en), Ok(fr)) => (en, fr),
+            _ => return Ok(0),
+        };
+
+        if en.machine_translation || fr.machine_translation || en.is_out_of_date(&fr) || fr.is_out_of_date(&en) {
+            return Ok(0)
+        };
+
+        let (en_content, fr_content) = (en.decrypt()?, fr.decrypt()?);
+
+        if en_content == PLACEHOLDER || fr_content == PLACEHOLDER {
+            return Ok(0)
+        };
+
+        let classification = Text::get_text_by_id(text_id, "en")?.document_classification()?;
+
+        let pairs = align(&segment(&en_content), &segment(&fr_content))
+            .into_iter()
+            .map(|(en_segment, fr_segment)| InsertableTranslationPair::new(
+                text_id, en.version_number, fr.version_number, &en_segment, &fr_segment, &classification))
+            .collect::<Result<Vec<InsertableTranslationPair>, CustomError>>()?;
+
+        // sentences already in the memory at this classification are kept as they are
+        let inserted = diesel::insert_into(translation_pairs::table)
+            .values(&pairs)
+            .on_conflict_do_nothing()
+            .execute(&conn)?;
+
+        Ok(inserted)
+    }
+
+    /// Learn from every text. Used to build the memory from texts translated before it existed.
+    /// Pairs already in the memory are kept, so it can be run again.
+    pub fn rebuild() -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let text_ids: Vec<Uuid> = texts::table
+            .filter(texts::lang.eq("fr"))
+            .select(texts::id)
+            .load(&conn)?;
+
+        let mut learned = 0;
+
+        for id in text_ids {
+            learned += TranslationPair::learn_text(id)?;
+        };
+
+        Ok(learned)
+    }
+
+    /// Exact and fuzzy matches for a sentence in source_lang from pairs the clearance allows reading,
+    /// best first
+    pub fn find_matches(segment: &str, source_lang: &str, clearance: &str, limit: usize) -> Result<Vec<TranslationMatch>, CustomError> {
+        let conn = database::connection()?;
+
+        let hash = hash_term(&normalize(segment));
+
+        let candidate_terms: Vec<String> = stem_terms(&plain_text(segment), source_lang)
+            .into_iter()
+            .filter(|s| s.chars().count() >= MIN_CANDIDATE_STEM)
+            .map(|s| hash_term(&s))
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect();
+
+        let mut query = translation_pairs::table
+            .filter(translation_pairs::security_classification.eq_any(readable_classifications(clearance)))
+            .order(translation_pairs::created_at.desc())
+            .limit(FUZZY_CANDIDATES)
+            .into_boxed();
+
+        query = match (source_lang, candidate_terms.is_empty()) {
+            ("fr", true) => query.filter(translation_pairs::fr_hash.eq(hash)),
+            ("fr", false) => query.filter(translation_pairs::fr_hash.eq(hash)
+                .or(translation_pairs::fr_terms.overlaps_with(candidate_terms))),
+            (_, true) => query.filter(translation_pairs::en_hash.eq(hash)),
+            (_, false) => query.filter(translation_pairs::en_hash.eq(hash)
+                .or(translation_pairs::en_terms.overlaps_with(candidate_terms))),
+        };
+
+        let candidates = query.load::<Self>(&conn)?;
+
+        let words = tokenize(&plain_text(segment));
+
+        let mut matches: Vec<TranslationMatch> = Vec::new();
+
+        for pair in candidates {
+            let (source, target) = pair.decrypt(source_lang)?;
+
+            let score = match normalize(&source) == normalize(segment) {
+                true => 100,
+                // only the same sentence counts as an exact match
+                false => similarity(&words, &tokenize(&plain_text(&source))).min(99),
+            };
+
+            if score < FUZZY_MATCH_THRESHOLD || matches.iter().any(|m| m.source == source && m.target == target) {
+                continue
+            };
+
+            matches.push(TranslationMatch {
+                source,
+                target,
+                score,
+                text_id: pair.text_id,
+            });
+        };
+
+        // most recent first among equal scores
+        matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+        matches.truncate(limit);
+
+        Ok(matches)
+    }
+
+    /// The most recent stored translation of exactly this sentence, if any
+    pub fn exact_match(segment: &str, source_lang: &str, clearance: &str) -> Result<Option<String>, CustomError> {
+        let conn = database::connection()?;
+
+        let hash = hash_term(&normalize(segment));
+
+        let query = translation_pairs::table
+            .filter(translation_pairs::security_classification.eq_any(readable_classifications(clearance)))
+            .order(translation_pairs::created_at.desc())
+            .into_boxed();
+
+        let query = match source_lang {
+            "fr" => query.filter(translation_pairs::fr_hash.eq(hash)),
+            _ => query.filter(translation_pairs::en_hash.eq(hash)),
+        };
+
+        match query.first::<Self>(&conn).optional()? {
+            Some(pair) => Ok(Some(pair.decrypt(source_lang)?.1)),
+            None => Ok(None),
+        }
+    }
+
+    /// Matches for each sentence of the other language's version of a text being edited in lang.
+    /// Sentences without matches are left out.
+    pub fn suggest_for_text(text_id: Uuid, lang: &str, clearance: &str, limit: usize) -> Result<Vec<SegmentSuggestions>, CustomError> {
+        let source_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let source = match TextVersion::get_head(text_id, source_lang) {
+            Ok(head) => head.decrypt()?,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        if source == PLACEHOLDER {
+            return Ok(Vec::new())
+        };
+
+        let mut suggestions = Vec::new();
+
+        for piece in segment(&source).into_iter().filter(|p| p.translatable) {
+            let matches = TranslationPair::find_matches(&piece.text, source_lang, clearance, limit)?;
+
+            if !matches.is_empty() {
+                suggestions.push(SegmentSuggestions {
+                    segment: piece.text,
+                    matches,
+                });
+            };
+        };
+
+        Ok(suggestions)
+    }
+
+    /// Move the pairs learned from a document's sections to the key and classification the document now has
+    pub fn reclassify_document(document_id: Uuid, classification: &str) -> Result<usize, CustomError> {
+        let conn = database::connection()?;
+
+        let text_ids: Vec<Uuid> = texts::table
+            .inner_join(sections::table)
+            .filter(sections::document_id.eq(document_id))
+            .select(texts::id)
+            .distinct()
+            .load(&conn)?;
+
+        conn.transaction::<usize, CustomError, _>(|| {
+            let pairs = diesel::delete(translation_pairs::table
+                .filter(translation_pairs::text_id.eq_any(text_ids)))
+                .get_results::<Self>(&conn)?;
+
+            let moved = pairs
+                .iter()
+                .map(|p| {
+                    let (en_segment, fr_segment) = p.decrypt("en")?;
+
+                    InsertableTranslationPair::new(p.text_id, p.en_version, p.fr_version, &en_segment, &fr_segment, classification)
+                })
+                .collect::<Result<Vec<InsertableTranslationPair>, CustomError>>()?;
+
+            let inserted = diesel::insert_into(translation_pairs::table)
+                .values(&moved)
+                .on_conflict_do_nothing()
+                .execute(&conn)?;
+
+            Ok(inserted)
+        })
+    }
+
+    /// (source, target) segments when translating from source_lang
+    fn decrypt(&self, source_lang: &str) -> Result<(String, String), CustomError> {
+        let en = KEYRING.decrypt(&self.en_segment)?;
+        let fr = KEYRING.decrypt(&self.fr_segment)?;
+
+        match source_lang {
+            "fr" => Ok((fr, en)),
+            _ => Ok((en, fr)),
+        }
+    }
+}
+
+impl InsertableTranslationPair {
+    pub fn new(
+        text_id: Uuid,
+        en_version: i32,
+        fr_version: i32,
+        en_segment: &str,
+        fr_segment: &str,
+        classification: &str,
+    ) -> Result<Self, CustomError> {
+
+        Ok(InsertableTranslationPair {
+            text_id,
+            en_version,
+            fr_version,
+            en_segment: KEYRING.encrypt_for(classification, en_segment)?,
+            fr_segment: KEYRING.encrypt_for(classification, fr_segment)?,
+            en_hash: hash_term(&normalize(en_segment)),
+            fr_hash: hash_term(&normalize(fr_segment)),
+            en_terms: segment_terms(en_segment, "en"),
+            fr_terms: segment_terms(fr_segment, "fr"),
+            security_classification: classification.to_string(),
+        })
+    }
+}
+
+impl TranslationDraft {
+    /// Split content in source_lang and fill in the sentences stored in the memory at or below clearance
+    pub fn from_memory(content: &str, source_lang: &str, clearance: &str) -> Result<Self, CustomError> {
+        let mut pieces = Vec::new();
+
+        for piece in segment(content) {
+            let translated = match piece.translatable {
+                true => TranslationPair::exact_match(&piece.text, source_lang, clearance)?,
+                false => None,
+            };
+
+            pieces.push((piece, translated));
+        };
+
+        Ok(TranslationDraft { pieces })
+    }
+
+    /// Sentences the memory had no translation for, in order
+    pub fn missing(&self) -> Vec<String> {
+        self.pieces
+            .iter()
+            .filter(|(p, t)| p.translatable && t.is_none())
+            .map(|(p, _)| p.text.to_owned())
+            .collect()
+    }
+
+    /// The translated text, taking the missing sentences in order from translated
+    pub fn complete<I: Iterator<Item = String>>(self, translated: &mut I) -> String {
+        self.pieces
+            .into_iter()
+            .map(|(p, t)| match (p.translatable, t) {
+                (true, Some(t)) => t,
+                (true, None) => translated.next().unwrap_or(p.text),
+                (false, _) => p.text,
+            })
+            .collect()
+    }
+}
+
+/// Split markdown content into sentences and the text between them.
+/// List, heading and quote markers stay out of the sentences, and a redaction is never split.
+pub fn segment(content: &str) -> Vec<Piece> {
+
+    lazy_static! {
+        static ref LINE_MARKERS: Regex = Regex::new(r"^\s*(?:(?:[-*+>]|\d+[.)]|#{1,6})\s+)*").unwrap();
+    }
+
+    let mut pieces = Vec::new();
+
+    for (block, line) in content.split_inclusive('\n').enumerate() {
+        let markers = LINE_MARKERS.find(line).map_or(0, |m| m.end());
+
+        push_piece(&mut pieces, &line[..markers], false, block);
+
+        let body = &line[markers..];
+        let end = body.trim_end().len();
+
+        let mut start = 0;
+
+        for boundary in sentence_ends(&body[..end]) {
+            let sentence = &body[start..boundary];
+            let trimmed = sentence.trim_end();
+
+            push_piece(&mut pieces, trimmed, trimmed.chars().any(|c| c.is_alphabetic()), block);
+            push_piece(&mut pieces, &sentence[trimmed.len()..], false, block);
+
+            start = boundary;
+        };
+
+        let rest = &body[start..end];
+
+        push_piece(&mut pieces, rest, rest.chars().any(|c| c.is_alphabetic()), block);
+        push_piece(&mut pieces, &body[end..], false, block);
+    };
+
+    pieces
+}
+
+// Byte offsets where each sentence but the last of a line ends, including the space after it
+fn sentence_ends(line: &str) -> Vec<usize> {
+
+    const ABBREVIATIONS: [&str; 14] = ["e.g", "i.e", "mr", "mrs", "ms", "dr", "m", "mme", "no", "vs", "art", "s", "ss", "p"];
+
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let mut ends = Vec::new();
+    let mut in_redaction = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+
+        if line[offset..].starts_with("~~") {
+            in_redaction = !in_redaction;
+            i += 2;
+            continue
+        };
+
+        if in_redaction || !matches!(c, '.' | '!' | '?') {
+            i += 1;
+            continue
+        };
+
+        // closing quotes and brackets belong to the sentence
+        let mut j = i + 1;
+
+        while j < chars.len() && matches!(chars[j].1, '"' | '\'' | ')' | '»' | '”' | '’' | '*' | '_') {
+            j += 1;
+        };
+
+        let mut k = j;
+
+        while k < chars.len() && chars[k].1.is_whitespace() {
+            k += 1;
+        };
+
+        let word = line[..offset]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+
+        let ends_sentence = c != '.' || !ABBREVIATIONS.contains(&word.as_str());
+
+        if k > j && k < chars.len() && !chars[k].1.is_lowercase() && ends_sentence {
+            ends.push(chars[k].0);
+        };
+
+        i = j;
+    };
+
+    ends
+}
+
+fn push_piece(pieces: &mut Vec<Piece>, text: &str, translatable: bool, block: usize) {
+    if text.is_empty() {
+        return
+    };
+
+    // text between sentences is merged so pieces alternate
+    if let Some(last) = pieces.last_mut() {
+        if !translatable && !last.translatable {
+            last.text.push_str(text);
+            return
+        };
+    };
+
+    pieces.push(Piece {
+        text: text.to_string(),
+        translatable,
+        block,
+    });
+}
+
+// Pair the sentences of two versions of a text. Lines are paired when both versions have the
+// same number of them, then sentences when a pair of lines has the same number of sentences.
+// Lines that don't split the same way are kept whole. Versions that don't line up aren't paired.
+fn align(en: &[Piece], fr: &[Piece]) -> Vec<(String, String)> {
+    let en_blocks = blocks(en);
+    let fr_blocks = blocks(fr);
+
+    let mut pairs: Vec<(String, String)> = Vec::new();
+
+    if en_blocks.len() == fr_blocks.len() {
+        for (e, f) in zip(&en_blocks, &fr_blocks) {
+            match e.len() == f.len() {
+                true => pairs.extend(zip(e, f).map(|(e, f)| (e.to_string(), f.to_string()))),
+                false => pairs.push((e.join(" "), f.join(" "))),
+            };
+        };
+    } else {
+        let e = en_blocks.concat();
+        let f = fr_blocks.concat();
+
+        if e.len() == f.len() {
+            pairs.extend(zip(e, f).map(|(e, f)| (e.to_string(), f.to_string())));
+        };
+    };
+
+    pairs
+        .into_iter()
+        .filter(|(e, f)| {
+            let ratio = e.chars().count() as f64 / f.chars().count().max(1) as f64;
+
+            (1.0 / MAX_LENGTH_RATIO..=MAX_LENGTH_RATIO).contains(&ratio)
+        })
+        .collect()
+}
+
+// Sentences grouped by line, leaving out lines without any
+fn blocks(pieces: &[Piece]) -> Vec<Vec<&str>> {
+    let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+
+    for p in pieces.iter().filter(|p| p.translatable) {
+        match blocks.last_mut() {
+            Some((block, sentences)) if *block == p.block => sentences.push(p.text.as_str()),
+            _ => blocks.push((p.block, vec![p.text.as_str()])),
+        };
+    };
+
+    blocks.into_iter().map(|(_, b)| b).collect()
+}
+
+// Sentences that differ only in spacing are the same sentence
+fn normalize(segment: &str) -> String {
+    segment.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Hashed stems for finding fuzzy match candidates
+fn segment_terms(segment: &str, lang: &str) -> Vec<String> {
+    stem_terms(&plain_text(segment), lang)
+        .into_iter()
+        .map(|s| hash_term(&s))
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+// Percentage of words in common, from the word level edit distance
+fn similarity(a: &[String], b: &[String]) -> u32 {
+    let longest = a.len().max(b.len());
+
+    if longest == 0 {
+        return 0
+    };
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, wa) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, wb) in b.iter().enumerate() {
+            let substitution = previous[j] + if wa == wb { 0 } else { 1 };
+
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        };
+
+        previous = current;
+    };
+
+    (100 * (longest - previous[b.len()]) / longest) as u32
+}
+
+// Classifications a clearance can read
+fn readable_classifications(clearance: &str) -> Vec<String> {
+    SECURITY_CLASSIFICATIONS
+        .iter()
+        .filter(|c| has_clearance(clearance, c))
+        .map(|c| c.to_string())
+        .collect()
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use diesel::prelude::*;
+use diesel::{QueryDsl};
+use pulldown_cmark::{Parser, Options, Event, Tag};
+use regex::{Regex, Captures};
+
+use crate::database;
+use crate::schema::{documents, sections, texts};
+use crate::errors::CustomError;
+use crate::models::{Document, TextVersion};
+
+const PLACEHOLDER: &str = "default_translation_traduction_par_defaut";
+
+/// Checks comparing a text with the other language
+pub const QA_NUMBERS: &str = "numbers";
+pub const QA_DATES: &str = "dates";
+pub const QA_LINKS: &str = "links";
+pub const QA_MARKDOWN: &str = "markdown";
+pub const QA_REDACTIONS: &str = "redactions";
+
+// Month names and abbreviations in both languages, longest first so the full name wins
+const MONTHS: [(&str, u32); 39] = [
+    ("septembre", 9), ("september", 9), ("décembre", 12), ("decembre", 12), ("december", 12),
+    ("novembre", 11), ("november", 11), ("février", 2), ("fevrier", 2), ("february", 2),
+    ("janvier", 1), ("january", 1), ("juillet", 7), ("octobre", 10), ("october", 10),
+    ("august", 8), ("avril", 4), ("april", 4), ("march", 3), ("mars", 3), ("juin", 6),
+    ("june", 6), ("july", 7), ("août", 8), ("aout", 8), ("sept", 9), ("mai", 5), ("may", 5),
+    ("jan", 1), ("feb", 2), ("mar", 3), ("apr", 4), ("jun", 6), ("jul", 7), ("aug", 8),
+    ("sep", 9), ("oct", 10), ("nov", 11), ("dec", 12),
+];
+
+lazy_static! {
+    // same markers as process_text_redactions
+    static ref REDACTION: Regex = Regex::new(r"(?s)~~(?P<text>.*?)~~\[(?P<act>[^\]]*)\]").unwrap();
+
+    static ref BARE_URL: Regex = Regex::new(r"https?://[^\s<>()\[\]]+").unwrap();
+
+    // language segments of localized links, e.g. /en/ and /fr/, page-eng.html and page-fra.html
+    static ref URL_LANG: Regex = Regex::new(r"(?i)(^|[/=_.-])(?:en|eng|fr|fra)($|[/&#_.-])").unwrap();
+
+    static ref ISO_DATE: Regex = Regex::new(r"\b(?P<y>\d{4})-(?P<m>\d{1,2})-(?P<d>\d{1,2})\b").unwrap();
+
+    static ref MONTH_FIRST_DATE: Regex = Regex::new(&format!(
+        r"(?i)\b(?P<m>{})\.?\s+(?P<d>\d{{1,2}})(?:st|nd|rd|th)?,?\s+(?P<y>\d{{4}})\b", month_names())).unwrap();
+
+    static ref DAY_FIRST_DATE: Regex = Regex::new(&format!(
+        r"(?i)\b(?P<d>\d{{1,2}})(?:er|st|nd|rd|th)?\s+(?P<m>{})\.?\s+(?P<y>\d{{4}})\b", month_names())).unwrap();
+
+    // 1,000.5 and 1 000.5 in English, 1 000,5 in French
+    static ref EN_NUMBER: Regex = Regex::new(r"\d{1,3}(?:[, \u{a0}\u{202f}]\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?").unwrap();
+    static ref FR_NUMBER: Regex = Regex::new(r"\d{1,3}(?:[ \u{a0}\u{202f}]\d{3})+(?:[,.]\d+)?|\d+(?:[,.]\d+)?").unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// A difference between a text and its other language: missing is in the other language only
+/// and extra in this one only. Publishing is blocked while a blocking issue remains.
+pub struct QaIssue {
+    pub check: String,
+    pub missing: Vec<String>,
+    pub extra: Vec<String>,
+    pub blocking: bool,
+}
+
+impl QaIssue {
+    /// Issues with the head revisions of texts in lang, by text.
+    /// Texts that haven't been written in both languages yet are skipped.
+    pub fn get_map(text_ids: Vec<Uuid>, lang: &str) -> Result<BTreeMap<Uuid, Vec<Self>>, CustomError> {
+        let other_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let heads = TextVersion::get_heads(text_ids.clone(), lang)?;
+        let other_heads = TextVersion::get_heads(text_ids, other_lang)?;
+
+        let mut map = BTreeMap::new();
+
+        for (text_id, head) in heads {
+            let other = match other_heads.get(&text_id) {
+                Some(o) => o,
+                None => continue,
+            };
+
+            let (content, other_content) = (head.decrypt()?, other.decrypt()?);
+
+            if content == PLACEHOLDER || other_content == PLACEHOLDER {
+                continue
+            };
+
+            let issues = check_translation(&content, lang, &other_content, other_lang);
+
+            if !issues.is_empty() {
+                map.insert(text_id, issues);
+            };
+        };
+
+        Ok(map)
+    }
+
+    /// Texts of a document whose English and French redactions don't match
+    pub fn get_blocking(document_id: Uuid) -> Result<Vec<Uuid>, CustomError> {
+        let conn = database::connection()?;
+
+        let document = Document::get_by_id(document_id)?;
+
+        let mut text_ids: Vec<Uuid> = texts::table
+            .inner_join(sections::table)
+            .filter(sections::document_id.eq(document_id))
+            .select(texts::id)
+            .distinct()
+            .load(&conn)?;
+
+        text_ids.push(document.title_text_id);
+        text_ids.push(document.purpose_text_id);
+
+        let blocking = QaIssue::get_map(text_ids, "en")?
+            .into_iter()
+            .filter(|(_, issues)| issues.iter().any(|i| i.blocking))
+            .map(|(text_id, _)| text_id)
+            .collect();
+
+        Ok(blocking)
+    }
+
+    /// Refuse content for a text in lang if its document is published and the content
+    /// would leave a redaction in one language only. Redactions of a published document
+    /// are changed by withdrawing it first.
+    pub fn check_save(text_id: Uuid, lang: &str, content: &str) -> Result<(), CustomError> {
+        let conn = database::connection()?;
+
+        let section_state: Option<String> = texts::table
+            .inner_join(sections::table.inner_join(documents::table))
+            .filter(texts::id.eq(text_id))
+            .select(documents::state)
+            .first(&conn)
+            .optional()?;
+
+        // titles and purposes aren't in a section
+        let state = match section_state {
+            Some(s) => Some(s),
+            None => documents::table
+                .filter(documents::title_text_id.eq(text_id).or(documents::purpose_text_id.eq(text_id)))
+                .select(documents::state)
+                .first(&conn)
+                .optional()?,
+        };
+
+        if state.as_deref() != Some("published") {
+            return Ok(())
+        };
+
+        let other_lang = match lang {
+            "fr" => "en",
+            _ => "fr",
+        };
+
+        let other_content = match TextVersion::get_heads(vec![text_id], other_lang)?.get(&text_id) {
+            Some(o) => o.decrypt()?,
+            None => return Ok(()),
+        };
+
+        if content == PLACEHOLDER || other_content == PLACEHOLDER {
+            return Ok(())
+        };
+
+        if check_translation(content, lang, &other_content, other_lang).iter().any(|i| i.blocking) {
+            return Err(CustomError::qa_blocked(format!(
+                "Text {} not saved: redactions would differ between English and French in a published document", text_id)))
+        };
+
+        Ok(())
+    }
+}
+
+/// Compare a text in lang with its other language. Numbers, dates and links are compared
+/// as written in each language, so 1,000.5 matches 1 000,5 and August 10, 2022 matches 10 août 2022.
+pub fn check_translation(content: &str, lang: &str, other_content: &str, other_lang: &str) -> Vec<QaIssue> {
+    let here = Extracted::from_markdown(content, lang);
+    let other = Extracted::from_markdown(other_content, other_lang);
+
+    [
+        (QA_NUMBERS, here.numbers, other.numbers),
+        (QA_DATES, here.dates, other.dates),
+        (QA_LINKS, here.links, other.links),
+        (QA_MARKDOWN, here.structure, other.structure),
+        (QA_REDACTIONS, here.redactions, other.redactions),
+    ]
+        .into_iter()
+        .filter_map(|(check, h, o)| compare(check, h, o))
+        .collect()
+}
+
+// What a text has to keep through translation
+struct Extracted {
+    numbers: Vec<String>,
+    dates: Vec<String>,
+    links: Vec<String>,
+    structure: Vec<String>,
+    redactions: Vec<String>,
+}
+
+impl Extracted {
+    fn from_markdown(markdown: &str, lang: &str) -> Self {
+        // redactions by their access to information act code
+        let redactions = REDACTION
+            .captures_iter(markdown)
+            .map(|c| c["act"].trim().to_string())
+            .collect();
+
+        let unredacted = REDACTION.replace_all(markdown, "$text");
+
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_TABLES);
+
+        let mut links = Vec::new();
+        let mut structure = Vec::new();
+        let mut text = String::new();
+
+        // whether each open list is ordered
+        let mut lists: Vec<bool> = Vec::new();
+
+        for event in Parser::new_ext(&unredacted, options) {
+            match event {
+                Event::Start(Tag::Link(_, dest, _)) | Event::Start(Tag::Image(_, dest, _)) => {
+                    links.push(normalize_link(&dest));
+                },
+                Event::Start(Tag::Heading(level, ..)) => structure.push("#".repeat(level as usize)),
+                Event::Start(Tag::List(start)) => lists.push(start.is_some()),
+                Event::End(Tag::List(_)) => {
+                    lists.pop();
+                },
+                Event::Start(Tag::Item) => match lists.last() {
+                    Some(true) => structure.push("1.".to_string()),
+                    _ => structure.push("-".to_string()),
+                },
+                Event::Start(Tag::BlockQuote) => structure.push(">".to_string()),
+                Event::Start(Tag::CodeBlock(_)) => structure.push("```".to_string()),
+                Event::Start(Tag::TableRow) | Event::Start(Tag::TableHead) => structure.push("|".to_string()),
+                Event::Text(t) | Event::Code(t) => {
+                    text.push_str(&t);
+                    text.push(' ');
+                },
+                Event::SoftBreak | Event::HardBreak => text.push(' '),
+                _ => (),
+            };
+        };
+
+        // links written out in the text, removed so their digits aren't counted as numbers
+        for m in BARE_URL.find_iter(&text) {
+            links.push(normalize_link(m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?'])));
+        };
+
+        let text = BARE_URL.replace_all(&text, " ");
+
+        let (dates, text) = extract_dates(&text);
+
+        let number_pattern: &Regex = match lang {
+            "fr" => &FR_NUMBER,
+            _ => &EN_NUMBER,
+        };
+
+        let numbers = number_pattern
+            .find_iter(&text)
+            .map(|m| normalize_number(m.as_str(), lang))
+            .collect();
+
+        Extracted { numbers, dates, links, structure, redactions }
+    }
+}
+
+// Dates as YYYY-MM-DD, and the text without them so their digits aren't counted as numbers
+fn extract_dates(text: &str) -> (Vec<String>, String) {
+    let mut dates = Vec::new();
+    let mut text = text.to_string();
+
+    for pattern in [&*ISO_DATE, &*MONTH_FIRST_DATE, &*DAY_FIRST_DATE] {
+        text = pattern.replace_all(&text, |c: &Captures| {
+            let month = match c["m"].parse::<u32>() {
+                Ok(m) => m,
+                Err(_) => month_number(&c["m"]),
+            };
+
+            dates.push(format!("{}-{:02}-{:02}", &c["y"], month, c["d"].parse::<u32>().unwrap_or_default()));
+
+            " "
+        }).to_string();
+    };
+
+    (dates, text)
+}
+
+fn month_names() -> String {
+    MONTHS.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join("|")
+}
+
+fn month_number(name: &str) -> u32 {
+    let name = name.to_lowercase();
+
+    MONTHS.iter().find(|(n, _)| *n == name).map(|(_, m)| *m).unwrap_or_default()
+}
+
+// The number's value as written in English without grouping, e.g. 1 000,50 -> 1000.5
+fn normalize_number(number: &str, lang: &str) -> String {
+    let digits: String = number
+        .chars()
+        .filter(|c| match c {
+            ' ' | '\u{a0}' | '\u{202f}' => false,
+            // groups thousands in English and separates decimals in French
+            ',' => lang == "fr",
+            _ => true,
+        })
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((w, f)) => (w, f.trim_end_matches('0')),
+        None => (digits.as_str(), ""),
+    };
+
+    let whole = match whole.trim_start_matches('0') {
+        "" => "0",
+        w => w,
+    };
+
+    match fraction {
+        "" => whole.to_string(),
+        f => format!("{}.{}", whole, f),
+    }
+}
+
+// Localized links are the same link in either language
+fn normalize_link(link: &str) -> String {
+    let link = link.trim().trim_end_matches('/');
+
+    URL_LANG.replace_all(link, "${1}{lang}${2}").to_string()
+}
+
+// Items in other and not here are missing, items here and not in other are extra
+fn compare(check: &str, here: Vec<String>, other: Vec<String>) -> Option<QaIssue> {
+    let mut counts: BTreeMap<String, i32> = BTreeMap::new();
+
+    for item in here {
+        *counts.entry(item).or_default() += 1;
+    };
+
+    for item in other {
+        *counts.entry(item).or_default() -= 1;
+    };
+
+    let mut missing = Vec::new();
+    let mut extra = Vec::new();
+
+    for (item, count) in counts {
+        match count {
+            c if c < 0 => missing.extend(vec![item; -c as usize]),
+            c if c > 0 => extra.extend(vec![item; c as usize]),
+            _ => (),
+        };
+    };
+
+    if missing.is_empty() && extra.is_empty() {
+        return None
+    };
+
+    Some(QaIssue {
+        check: check.to_string(),
+        missing,
+        extra,
+        blocking: check == QA_REDACTIONS,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks(issues: &[QaIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.check.as_str()).collect()
+    }
+
+    #[test]
+    fn numbers_match_as_written_in_each_language() {
+        let issues = check_translation(
+            "The budget grew by 1,000.5 dollars, or 12.50 percent, to 2 000 000.",
+            "en",
+            "Le budget a augmenté de 1 000,5 dollars, soit 12,5 pour cent, à 2\u{a0}000\u{a0}000.",
+            "fr");
+
+        assert!(issues.is_empty(), "{:?}", issues);
+
+        let issues = check_translation("Costs were 1,000.5 dollars.", "en", "Les coûts étaient de 1 000,6 dollars.", "fr");
+
+        assert_eq!(issues, vec![QaIssue {
+            check: QA_NUMBERS.to_string(),
+            missing: vec!["1000.6".to_string()],
+            extra: vec!["1000.5".to_string()],
+            blocking: false,
+        }]);
+    }
+
+    #[test]
+    fn dates_and_links_are_not_counted_as_numbers() {
+        let issues = check_translation(
+            "See [the report](https://example.gc.ca/en/report-2022.html) of August 10, 2022.",
+            "en",
+            "Voir [le rapport](https://example.gc.ca/fr/report-2022.html) du 10 août 2022.",
+            "fr");
+
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn one_sided_redactions_block() {
+        let issues = check_translation(
+            "The ~~Ottawa~~[s.20(1)(b)] office closed.",
+            "en",
+            "Le bureau d'Ottawa a fermé.",
+            "fr");
+
+        assert_eq!(checks(&issues), vec![QA_REDACTIONS]);
+        assert!(issues[0].blocking);
+        assert_eq!(issues[0].extra, vec!["s.20(1)(b)".to_string()]);
+
+        // the same redaction in both languages is fine
+        let issues = check_translation(
+            "The ~~Ottawa~~[s.20(1)(b)] office closed.",
+            "en",
+            "Le bureau d'~~Ottawa~~[ s.20(1)(b) ] a fermé.",
+            "fr");
+
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn other_differences_dont_block() {
+        // list numbers are formatting, not numbers in the text
+        let issues = check_translation("# Summary\n\n- one\n- two 2", "en", "# Résumé\n\n1. un\n2. deux", "fr");
+
+        assert_eq!(checks(&issues), vec![QA_NUMBERS, QA_MARKDOWN]);
+        assert!(issues.iter().all(|i| !i.blocking));
+    }
+}
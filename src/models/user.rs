@@ -10,6 +10,7 @@ use inflector::Inflector;
 use crate::schema::users;
 use crate::database;
 use crate::errors::CustomError;
+use crate::models::UNCLASSIFIED;
 
 use shrinkwraprs::Shrinkwrap;
 use diesel::prelude::*;
@@ -28,6 +29,7 @@ pub struct User {
     pub created_at: NaiveDateTime,
     pub role: String,
     pub validated: bool,
+    pub clearance: String,
 }
 
 #[derive(Debug, Insertable)]
@@ -49,6 +51,7 @@ pub struct SlimUser {
     pub email: String,
     pub slug: String,
     pub role: String,
+    pub clearance: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -233,6 +236,7 @@ impl User {
             created_at: NaiveDateTime::from_timestamp(1_000_000_000, 0),
             role: "".to_string(),
             validated: false,
+            clearance: UNCLASSIFIED.to_string(),
         }
     }
 }
@@ -244,6 +248,7 @@ impl From<User> for SlimUser {
             email,
             role,
             slug,
+            clearance,
             ..
         } = user;
 
@@ -252,6 +257,7 @@ impl From<User> for SlimUser {
             email,
             role,
             slug,
+            clearance,
         }
     }
 }
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use actix_web_actors::ws;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+// How often the server pings a socket, and how long it waits for an answer before dropping it
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// What everyone with a document open is told about
+pub enum DocumentEvent {
+    SectionUpdated {
+        section_id: Uuid,
+        text_id: Uuid,
+        lang: String,
+        updated_by: String,
+    },
+    StateChanged {
+        from_state: String,
+        state: String,
+    },
+    Presence {
+        people: Vec<Presence>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// Someone with the document open, and the section they are in if they are editing
+pub struct Presence {
+    pub user_name: String,
+    pub user_slug: String,
+    pub editing_section_id: Option<Uuid>,
+}
+
+/// Send an event to every socket open on a document
+pub fn publish(document_id: Uuid, event: DocumentEvent) {
+    DocumentHub::from_registry().do_send(Publish { document_id, event });
+}
+
+/// A JSON event for one socket
+pub struct SocketEvent(pub String);
+
+impl Message for SocketEvent {
+    type Result = ();
+}
+
+pub struct Join {
+    pub document_id: Uuid,
+    pub socket_id: Uuid,
+    pub presence: Presence,
+    pub recipient: Recipient<SocketEvent>,
+}
+
+impl Message for Join {
+    type Result = ();
+}
+
+pub struct Leave {
+    pub document_id: Uuid,
+    pub socket_id: Uuid,
+}
+
+impl Message for Leave {
+    type Result = ();
+}
+
+pub struct SetEditing {
+    pub document_id: Uuid,
+    pub socket_id: Uuid,
+    pub section_id: Option<Uuid>,
+}
+
+impl Message for SetEditing {
+    type Result = ();
+}
+
+pub struct Publish {
+    pub document_id: Uuid,
+    pub event: DocumentEvent,
+}
+
+impl Message for Publish {
+    type Result = ();
+}
+
+struct Member {
+    recipient: Recipient<SocketEvent>,
+    presence: Presence,
+}
+
+#[derive(Default)]
+/// Open document sockets by document. One per system, reached through `publish` or `from_registry`.
+pub struct DocumentHub {
+    documents: HashMap<Uuid, HashMap<Uuid, Member>>,
+}
+
+impl DocumentHub {
+    fn broadcast(&self, document_id: Uuid, event: &DocumentEvent) {
+        let members = match self.documents.get(&document_id) {
+            Some(m) => m,
+            None => return,
+        };
+
+        let json = serde_json::to_string(event).expect("Unable to serialize document event");
+
+        for member in members.values() {
+            let _ = member.recipient.do_send(SocketEvent(json.clone()));
+        };
+    }
+
+    fn broadcast_presence(&self, document_id: Uuid) {
+        let people = match self.documents.get(&document_id) {
+            Some(members) => members.values().map(|m| m.presence.clone()).collect(),
+            None => return,
+        };
+
+        self.broadcast(document_id, &DocumentEvent::Presence { people });
+    }
+}
+
+impl Actor for DocumentHub {
+    type Context = Context<Self>;
+}
+
+impl Supervised for DocumentHub {}
+
+impl SystemService for DocumentHub {}
+
+impl Handler<Join> for DocumentHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: Join, _ctx: &mut Context<Self>) {
+        self.documents
+            .entry(msg.document_id)
+            .or_insert_with(HashMap::new)
+            .insert(msg.socket_id, Member {
+                recipient: msg.recipient,
+                presence: msg.presence,
+            });
+
+        self.broadcast_presence(msg.document_id);
+    }
+}
+
+impl Handler<Leave> for DocumentHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: Leave, _ctx: &mut Context<Self>) {
+        if let Some(members) = self.documents.get_mut(&msg.document_id) {
+            members.remove(&msg.socket_id);
+
+            if members.is_empty() {
+                self.documents.remove(&msg.document_id);
+            };
+        };
+
+        self.broadcast_presence(msg.document_id);
+    }
+}
+
+impl Handler<SetEditing> for DocumentHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetEditing, _ctx: &mut Context<Self>) {
+        let member = self.documents
+            .get_mut(&msg.document_id)
+            .and_then(|members| members.get_mut(&msg.socket_id));
+
+        if let Some(member) = member {
+            if member.presence.editing_section_id == msg.section_id {
+                return
+            };
+
+            member.presence.editing_section_id = msg.section_id;
+        };
+
+        self.broadcast_presence(msg.document_id);
+    }
+}
+
+impl Handler<Publish> for DocumentHub {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _ctx: &mut Context<Self>) {
+        self.broadcast(msg.document_id, &msg.event);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// Sent by the page when focus moves into or out of a section editor
+struct ClientMessage {
+    editing: Option<Uuid>,
+}
+
+/// One browser with a document open
+pub struct DocumentSocket {
+    socket_id: Uuid,
+    document_id: Uuid,
+    presence: Presence,
+    heartbeat: Instant,
+}
+
+impl DocumentSocket {
+    pub fn new(document_id: Uuid, user_name: String, user_slug: String) -> Self {
+        DocumentSocket {
+            socket_id: Uuid::new_v4(),
+            document_id,
+            presence: Presence {
+                user_name,
+                user_slug,
+                editing_section_id: None,
+            },
+            heartbeat: Instant::now(),
+        }
+    }
+}
+
+impl Actor for DocumentSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |socket, ctx| {
+            if Instant::now().duration_since(socket.heartbeat) > CLIENT_TIMEOUT {
+                ctx.stop();
+                return
+            };
+
+            ctx.ping(b"");
+        });
+
+        DocumentHub::from_registry().do_send(Join {
+            document_id: self.document_id,
+            socket_id: self.socket_id,
+            presence: self.presence.clone(),
+            recipient: ctx.address().recipient(),
+        });
+    }
+
+    fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+        DocumentHub::from_registry().do_send(Leave {
+            document_id: self.document_id,
+            socket_id: self.socket_id,
+        });
+
+        Running::Stop
+    }
+}
+
+impl Handler<SocketEvent> for DocumentSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: SocketEvent, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for DocumentSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let msg = match msg {
+            Ok(m) => m,
+            Err(_) => {
+                ctx.stop();
+                return
+            },
+        };
+
+        match msg {
+            ws::Message::Ping(bytes) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&bytes);
+            },
+            ws::Message::Pong(_) => {
+                self.heartbeat = Instant::now();
+            },
+            ws::Message::Text(text) => {
+                self.heartbeat = Instant::now();
+
+                // ignore anything that isn't an editing update
+                if let Ok(client_message) = serde_json::from_str::<ClientMessage>(&text) {
+                    DocumentHub::from_registry().do_send(SetEditing {
+                        document_id: self.document_id,
+                        socket_id: self.socket_id,
+                        section_id: client_message.editing,
+                    });
+                };
+            },
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            },
+            _ => (),
+        };
+    }
+}
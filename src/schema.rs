@@ -1,3 +1,14 @@
+table! {
+    authorizations (id) {
+        id -> Uuid,
+        document_id -> Uuid,
+        user_id -> Uuid,
+        order_number -> Int4,
+        created_at -> Timestamp,
+        removed -> Bool,
+    }
+}
+
 table! {
     categories (id) {
         id -> Uuid,
@@ -8,6 +19,29 @@ table! {
     }
 }
 
+table! {
+    decisions (id) {
+        id -> Uuid,
+        document_id -> Uuid,
+        authorization_id -> Uuid,
+        user_id -> Uuid,
+        decision -> Varchar,
+        comment -> Text,
+        date_time -> Timestamp,
+    }
+}
+
+table! {
+    document_transitions (id) {
+        id -> Uuid,
+        document_id -> Uuid,
+        from_state -> Varchar,
+        to_state -> Varchar,
+        user_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     documents (id) {
         id -> Uuid,
@@ -17,8 +51,8 @@ table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         security_classification -> Varchar,
-        published -> Bool,
         created_by_id -> Uuid,
+        state -> Varchar,
     }
 }
 
@@ -31,6 +65,39 @@ table! {
     }
 }
 
+table! {
+    jobs (id) {
+        id -> Uuid,
+        kind -> Varchar,
+        text_id -> Nullable<Uuid>,
+        document_id -> Nullable<Uuid>,
+        lang -> Varchar,
+        base_version -> Nullable<Int4>,
+        status -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        run_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    keyword_suggestions (id) {
+        id -> Uuid,
+        document_id -> Uuid,
+        keyword_id -> Nullable<Uuid>,
+        term -> Varchar,
+        lang -> Varchar,
+        en_string -> Varchar,
+        fr_string -> Varchar,
+        score -> Float8,
+        status -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     keywords (id) {
         id -> Uuid,
@@ -67,6 +134,26 @@ table! {
     }
 }
 
+table! {
+    search_terms (text_id, lang, term) {
+        text_id -> Uuid,
+        lang -> Varchar,
+        term -> Varchar,
+        internal_count -> Int4,
+        open_count -> Int4,
+        keyword -> Bool,
+    }
+}
+
+table! {
+    section_locks (section_id) {
+        section_id -> Uuid,
+        user_id -> Uuid,
+        locked_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
 table! {
     sections (id) {
         id -> Uuid,
@@ -111,17 +198,69 @@ table! {
     }
 }
 
+table! {
+    term_warnings (id) {
+        id -> Uuid,
+        text_id -> Uuid,
+        lang -> Varchar,
+        term_id -> Uuid,
+        found -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    terms (id) {
+        id -> Uuid,
+        en_term -> Varchar,
+        fr_term -> Varchar,
+        en_deprecated -> Array<Varchar>,
+        fr_deprecated -> Array<Varchar>,
+        note -> Nullable<Text>,
+        created_by_id -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    text_versions (id) {
+        id -> Uuid,
+        text_id -> Uuid,
+        lang -> Varchar,
+        version_number -> Int4,
+        content -> Text,
+        translated -> Bool,
+        machine_translation -> Bool,
+        created_at -> Timestamp,
+        created_by_id -> Uuid,
+        source_version -> Nullable<Int4>,
+    }
+}
+
 table! {
     texts (id, lang) {
         id -> Uuid,
         section_id -> Nullable<Uuid>,
         lang -> Varchar,
-        content -> Array<Text>,
         keywords -> Nullable<Jsonb>,
-        translated -> Array<Bool>,
-        machine_translation -> Array<Bool>,
-        created_at -> Array<Timestamp>,
-        created_by_id -> Array<Uuid>,
+    }
+}
+
+table! {
+    translation_pairs (id) {
+        id -> Uuid,
+        text_id -> Uuid,
+        en_version -> Int4,
+        fr_version -> Int4,
+        en_segment -> Text,
+        fr_segment -> Text,
+        en_hash -> Varchar,
+        fr_hash -> Varchar,
+        en_terms -> Array<Varchar>,
+        fr_terms -> Array<Varchar>,
+        security_classification -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -136,27 +275,51 @@ table! {
         created_at -> Timestamp,
         role -> Varchar,
         validated -> Bool,
+        clearance -> Varchar,
     }
 }
 
+joinable!(authorizations -> documents (document_id));
+joinable!(authorizations -> users (user_id));
+joinable!(decisions -> authorizations (authorization_id));
+joinable!(decisions -> documents (document_id));
+joinable!(document_transitions -> documents (document_id));
 joinable!(documents -> templates (template_id));
+joinable!(jobs -> documents (document_id));
+joinable!(keyword_suggestions -> documents (document_id));
+joinable!(keyword_suggestions -> keywords (keyword_id));
 joinable!(metadata -> documents (document_id));
+joinable!(section_locks -> sections (section_id));
+joinable!(section_locks -> users (user_id));
 joinable!(sections -> documents (document_id));
 joinable!(sections -> template_sections (template_section_id));
 joinable!(template_sections -> templates (template_id));
+joinable!(term_warnings -> terms (term_id));
+joinable!(terms -> users (created_by_id));
 joinable!(texts -> sections (section_id));
 
 allow_tables_to_appear_in_same_query!(
+    authorizations,
     categories,
+    decisions,
+    document_transitions,
     documents,
     email_verification_code,
+    jobs,
+    keyword_suggestions,
     keywords,
     metadata,
     password_reset_token,
+    search_terms,
+    section_locks,
     sections,
     subjects,
     template_sections,
     templates,
+    term_warnings,
+    terms,
+    text_versions,
     texts,
+    translation_pairs,
     users,
 );
@@ -0,0 +1,255 @@
+// Reading termbase files: CSV exports from spreadsheets and TBX exchange files from terminology tools.
+
+use quick_xml::Reader;
+use quick_xml::events::{Event, BytesStart};
+use uuid::Uuid;
+
+use crate::errors::CustomError;
+use crate::models::{InsertableTerm, parse_variants};
+
+// TBX administrative statuses of renderings that shouldn't be used
+const DEPRECATED_STATUSES: [&str; 2] = ["deprecatedTerm-admn-sts", "supersededTerm-admn-sts"];
+
+/// Read terms from a .tbx or .csv file, chosen by file name and falling back on the content
+pub fn parse_terms(file_name: &str, buf: &[u8], created_by_id: Uuid) -> Result<Vec<InsertableTerm>, CustomError> {
+    let is_tbx = file_name.to_lowercase().ends_with(".tbx")
+        || String::from_utf8_lossy(&buf[..buf.len().min(512)]).trim_start().starts_with('<');
+
+    match is_tbx {
+        true => parse_tbx(buf, created_by_id),
+        false => parse_csv(buf, created_by_id),
+    }
+}
+
+/// Read a CSV file of en,fr[,note] rows. With a header row, columns are found by name and
+/// en_deprecated and fr_deprecated columns list renderings to avoid, separated by semicolons.
+pub fn parse_csv(buf: &[u8], created_by_id: Uuid) -> Result<Vec<InsertableTerm>, CustomError> {
+    let content = String::from_utf8(buf.to_vec())
+        .map_err(|_| CustomError::new(400, "Termbase CSV file is not UTF-8".to_string()))?;
+
+    let mut rows = csv_rows(content.trim_start_matches('\u{feff}'));
+
+    let header: Vec<String> = rows.first()
+        .map(|r| r.iter().map(|c| c.trim().to_lowercase()).collect())
+        .unwrap_or_default();
+
+    let has_header = header.iter().any(|c| c == "en") && header.iter().any(|c| c == "fr");
+
+    let column = |name: &str, default: Option<usize>| match has_header {
+        true => header.iter().position(|c| c == name),
+        false => default,
+    };
+
+    let (en, fr, note) = (column("en", Some(0)), column("fr", Some(1)), column("note", Some(2)));
+    let (en_deprecated, fr_deprecated) = (column("en_deprecated", None), column("fr_deprecated", None));
+
+    if has_header {
+        rows.remove(0);
+    };
+
+    let cell = |row: &Vec<String>, i: Option<usize>| i
+        .and_then(|i| row.get(i))
+        .map(|c| c.trim().to_string())
+        .unwrap_or_default();
+
+    let mut entries = Vec::new();
+
+    for (i, row) in rows.iter().enumerate() {
+        if row.iter().all(|c| c.trim().is_empty()) {
+            continue
+        };
+
+        let entry = InsertableTerm::new(
+            &cell(row, en),
+            &cell(row, fr),
+            parse_variants(&cell(row, en_deprecated)),
+            parse_variants(&cell(row, fr_deprecated)),
+            &cell(row, note),
+            created_by_id,
+        );
+
+        if entry.en_term.is_empty() || entry.fr_term.is_empty() {
+            return Err(CustomError::new(400, format!(
+                "Termbase CSV row {} needs an English and a French term", i + 1 + has_header as usize)))
+        };
+
+        entries.push(entry);
+    };
+
+    Ok(entries)
+}
+
+/// Read a TBX file (TBX-Basic or TBX v3). Each concept with an English and a French term becomes
+/// a term, using the first rendering in each language that isn't deprecated. Deprecated and
+/// superseded renderings are kept as renderings to avoid.
+pub fn parse_tbx(buf: &[u8], created_by_id: Uuid) -> Result<Vec<InsertableTerm>, CustomError> {
+    let mut reader = Reader::from_reader(buf);
+    reader.trim_text(true);
+
+    let mut entries = Vec::new();
+
+    // (lang, term, status) of the concept being read
+    let mut renderings: Vec<(String, String, String)> = Vec::new();
+    let mut note = String::new();
+
+    let mut lang = String::new();
+    let mut term: Option<String> = None;
+    let mut status = String::new();
+
+    // element whose text is being collected: a term, an administrative status or a definition
+    let mut capturing: Option<&str> = None;
+    let mut text = String::new();
+
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader.read_event(&mut buf)
+            .map_err(|e| CustomError::new(400, format!("Unable to read TBX file at {}: {}", reader.buffer_position(), e)))?;
+
+        match event {
+            Event::Start(ref e) => {
+                match e.local_name() {
+                    b"termEntry" | b"conceptEntry" => {
+                        renderings.clear();
+                        note.clear();
+                    },
+                    b"langSet" | b"langSec" => {
+                        lang = attribute(e, b"lang").unwrap_or_default().to_lowercase();
+                    },
+                    b"tig" | b"ntig" | b"termSec" => {
+                        term = None;
+                        status.clear();
+                    },
+                    b"term" => {
+                        capturing = Some("term");
+                        text.clear();
+                    },
+                    b"termNote" if attribute(e, b"type").as_deref() == Some("administrativeStatus") => {
+                        capturing = Some("status");
+                        text.clear();
+                    },
+                    b"descrip" if note.is_empty() && attribute(e, b"type").as_deref() == Some("definition") => {
+                        capturing = Some("note");
+                        text.clear();
+                    },
+                    b"note" if note.is_empty() => {
+                        capturing = Some("note");
+                        text.clear();
+                    },
+                    _ => (),
+                };
+            },
+            Event::Text(ref e) | Event::CData(ref e) if capturing.is_some() => {
+                let t = match event {
+                    Event::CData(_) => String::from_utf8_lossy(e).to_string(),
+                    _ => e.unescape_and_decode(&reader)
+                        .map_err(|err| CustomError::new(400, format!("Unable to read TBX text: {}", err)))?,
+                };
+
+                text.push_str(&t);
+            },
+            Event::End(ref e) => {
+                match e.local_name() {
+                    b"term" => {
+                        term = Some(text.trim().to_string());
+                        capturing = None;
+                    },
+                    b"termNote" if capturing == Some("status") => {
+                        status = text.trim().to_string();
+                        capturing = None;
+                    },
+                    b"descrip" | b"note" if capturing == Some("note") => {
+                        note = text.trim().to_string();
+                        capturing = None;
+                    },
+                    b"tig" | b"ntig" | b"termSec" => {
+                        if let Some(t) = term.take() {
+                            // en-CA and fr-CA count as en and fr
+                            let short_lang = lang.split('-').next().unwrap_or_default().to_string();
+
+                            renderings.push((short_lang, t, status.clone()));
+                        };
+                    },
+                    b"termEntry" | b"conceptEntry" => {
+                        if let Some(entry) = concept_term(&renderings, &note, created_by_id) {
+                            entries.push(entry);
+                        };
+                    },
+                    _ => (),
+                };
+            },
+            Event::Eof => break,
+            _ => (),
+        };
+
+        buf.clear();
+    };
+
+    Ok(entries)
+}
+
+// The term for a concept, if it has an approved rendering in both languages
+fn concept_term(renderings: &[(String, String, String)], note: &str, created_by_id: Uuid) -> Option<InsertableTerm> {
+    let approved = |lang: &str| renderings
+        .iter()
+        .find(|(l, t, s)| l == lang && !t.is_empty() && !DEPRECATED_STATUSES.contains(&s.as_str()))
+        .map(|(_, t, _)| t.to_owned());
+
+    let deprecated = |lang: &str| renderings
+        .iter()
+        .filter(|(l, _, s)| l == lang && DEPRECATED_STATUSES.contains(&s.as_str()))
+        .map(|(_, t, _)| t.to_owned())
+        .collect::<Vec<String>>();
+
+    Some(InsertableTerm::new(
+        &approved("en")?,
+        &approved("fr")?,
+        deprecated("en"),
+        deprecated("fr"),
+        note,
+        created_by_id,
+    ))
+}
+
+// Rows of a CSV file. Quoted cells can hold commas, quotes ("") and new lines.
+fn csv_rows(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            },
+            ('"', true) => quoted = false,
+            ('"', false) if cell.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut cell)),
+            ('\r', false) => (),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            },
+            _ => cell.push(c),
+        };
+    };
+
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    };
+
+    rows
+}
+
+// Attribute by local name, so xml:lang is found as lang
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .filter_map(|a| a.ok())
+        .find(|a| a.key == name || a.key.rsplit(|b| *b == b':').next() == Some(name))
+        .and_then(|a| a.unescaped_value().ok().map(|v| String::from_utf8_lossy(&v).to_string()))
+}
@@ -0,0 +1,537 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use deepl_api::{DeepL, TranslatableTextList, TranslationOptions};
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::Deserialize;
+
+use crate::errors::CustomError;
+
+// DeepL takes at most 50 texts and 128 KiB per request. Room is left for the other fields.
+const DEEPL_MAX_TEXTS: usize = 50;
+const DEEPL_MAX_TEXT_BYTES: usize = 120 * 1024;
+
+/// A machine translation service. Texts are translated in order between "en" and "fr".
+pub trait Translator: Send + Sync {
+    /// Name of the provider, shown in logs
+    fn name(&self) -> &str;
+
+    fn translate<'a>(&'a self, texts: Vec<String>, source_lang: &'a str, target_lang: &'a str)
+        -> BoxFuture<'a, Result<Vec<String>, CustomError>>;
+
+    /// Translate using (source, target) term pairs wherever the source term appears.
+    /// Providers without glossary support translate as usual.
+    fn translate_with_glossary<'a>(&'a self, texts: Vec<String>, source_lang: &'a str, target_lang: &'a str,
+        _glossary: Vec<(String, String)>) -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        self.translate(texts, source_lang, target_lang)
+    }
+
+    /// Whether translations are worth saving over the other language
+    fn enabled(&self) -> bool {
+        true
+    }
+}
+
+/// Pick the provider from the environment:
+/// TRANSLATION_PROVIDER = deepl (needs DEEPL_API_KEY), dictionary (needs TRANSLATION_DICTIONARY) or echo.
+/// Without TRANSLATION_PROVIDER, DeepL is used when DEEPL_API_KEY is set and machine translation
+/// is disabled otherwise, so the app runs without a key. Echo copies texts untranslated, so it is only used when asked for.
+pub fn from_env() -> Result<Box<dyn Translator>, CustomError> {
+    from_settings(
+        env::var("TRANSLATION_PROVIDER").ok(),
+        env::var("DEEPL_API_KEY").ok(),
+        env::var("TRANSLATION_DICTIONARY").ok(),
+    )
+}
+
+fn from_settings(provider: Option<String>, key: Option<String>, dictionary: Option<String>) -> Result<Box<dyn Translator>, CustomError> {
+    let key = key.filter(|k| !k.is_empty());
+
+    let provider = match provider {
+        Some(p) if !p.is_empty() => p.to_lowercase(),
+        _ => match key {
+            Some(_) => "deepl".to_string(),
+            None => {
+                eprintln!("No DEEPL_API_KEY or TRANSLATION_PROVIDER found. Machine translation is disabled.");
+                "disabled".to_string()
+            },
+        },
+    };
+
+    match provider.as_str() {
+        "deepl" => match key {
+            Some(k) => Ok(Box::new(DeepLTranslator::new(k))),
+            None => Err(CustomError::new(500, "TRANSLATION_PROVIDER is deepl but DEEPL_API_KEY is not set".to_string())),
+        },
+        "dictionary" => {
+            let path = dictionary.filter(|d| !d.is_empty())
+                .ok_or_else(|| CustomError::new(500, "TRANSLATION_PROVIDER is dictionary but TRANSLATION_DICTIONARY is not set".to_string()))?;
+
+            Ok(Box::new(DictionaryTranslator::from_file(&path)?))
+        },
+        "echo" => Ok(Box::new(EchoTranslator)),
+        "disabled" => Ok(Box::new(DisabledTranslator)),
+        p => Err(CustomError::new(500, format!("Unknown TRANSLATION_PROVIDER {}", p))),
+    }
+}
+
+/// Translation through the DeepL API
+pub struct DeepLTranslator {
+    deepl: DeepL,
+    key: String,
+    // (source lang, target lang) -> (fingerprint of the entries, DeepL glossary id)
+    glossaries: Mutex<BTreeMap<(String, String), (u64, String)>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeepLGlossary {
+    glossary_id: String,
+}
+
+impl DeepLTranslator {
+    pub fn new(key: String) -> Self {
+        DeepLTranslator {
+            deepl: DeepL::new(key.clone()),
+            key,
+            glossaries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Free API keys end in :fx and use a different host
+    fn api_url(&self, path: &str) -> String {
+        match self.key.ends_with(":fx") {
+            true => format!("https://api-free.deepl.com/v2/{}", path),
+            false => format!("https://api.deepl.com/v2/{}", path),
+        }
+    }
+
+    /// Id of a DeepL glossary with exactly these entries. DeepL glossaries can't be edited,
+    /// so a new one is created when the entries change and the one it replaces is deleted.
+    async fn glossary_id(&self, source_lang: &str, target_lang: &str, entries: &[(String, String)]) -> Result<String, CustomError> {
+        let mut hasher = DefaultHasher::new();
+        entries.hash(&mut hasher);
+        let fingerprint = hasher.finish();
+
+        let direction = (source_lang.to_string(), target_lang.to_string());
+
+        let previous = self.glossaries.lock().expect("Glossary cache poisoned").get(&direction).cloned();
+
+        if let Some((f, id)) = &previous {
+            if *f == fingerprint {
+                return Ok(id.to_owned())
+            };
+        };
+
+        // tabs and new lines would break the tab separated entries
+        let tsv: Vec<String> = entries
+            .iter()
+            .map(|(s, t)| format!("{}\t{}", s.replace(['\t', '\n', '\r'], " "), t.replace(['\t', '\n', '\r'], " ")))
+            .collect();
+
+        let client = reqwest::Client::new();
+
+        let glossary = client.post(self.api_url("glossaries"))
+            .header("Authorization", format!("DeepL-Auth-Key {}", self.key))
+            .form(&[
+                ("name", format!("data-docs-{}-{}", source_lang, target_lang)),
+                ("source_lang", source_lang.to_string()),
+                ("target_lang", target_lang.to_string()),
+                ("entries", tsv.join("\n")),
+                ("entries_format", "tsv".to_string()),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| CustomError::new(500, format!("Unable to create DeepL glossary: {}", e)))?
+            .json::<DeepLGlossary>()
+            .await
+            .map_err(|e| CustomError::new(500, format!("Unable to read DeepL glossary: {}", e)))?;
+
+        self.glossaries.lock().expect("Glossary cache poisoned")
+            .insert(direction, (fingerprint, glossary.glossary_id.clone()));
+
+        if let Some((_, old_id)) = previous {
+            let deleted = client.delete(self.api_url(&format!("glossaries/{}", old_id)))
+                .header("Authorization", format!("DeepL-Auth-Key {}", self.key))
+                .send()
+                .await;
+
+            if let Err(e) = deleted {
+                eprintln!("Unable to delete DeepL glossary {}: {}", old_id, e);
+            };
+        };
+
+        Ok(glossary.glossary_id)
+    }
+
+    /// Translate in as many requests as DeepL's limits need, keeping the order of the texts
+    async fn translate_batches(&self, texts: Vec<String>, source_lang: &str, target_lang: &str,
+        glossary_id: Option<String>) -> Result<Vec<String>, CustomError> {
+
+        let mut translated = Vec::with_capacity(texts.len());
+
+        for batch in deepl_batches(texts)? {
+            let options = glossary_id.as_ref().map(|id| TranslationOptions {
+                split_sentences: None,
+                preserve_formatting: None,
+                formality: None,
+                glossary_id: Some(id.to_owned()),
+            });
+
+            let translatable_text = TranslatableTextList {
+                source_language: Some(source_lang.to_uppercase()),
+                target_language: target_lang.to_uppercase(),
+                texts: batch,
+            };
+
+            let batch_translated = self.deepl.translate(options, translatable_text)
+                .await
+                .map_err(|e| CustomError::new(500, format!("DeepL translation failed: {}", e)))?;
+
+            translated.extend(batch_translated.into_iter().map(|t| t.text));
+        };
+
+        Ok(translated)
+    }
+}
+
+// Texts split into requests within DeepL's limits, in order
+fn deepl_batches(texts: Vec<String>) -> Result<Vec<Vec<String>>, CustomError> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+
+    for text in texts {
+        // each text is sent as a form encoded text field
+        let bytes = "&text=".len() + form_encoded_len(&text);
+
+        if bytes > DEEPL_MAX_TEXT_BYTES {
+            return Err(CustomError::new(500, format!(
+                "A text of {} characters is too long to send to DeepL", text.chars().count())))
+        };
+
+        if batch.len() == DEEPL_MAX_TEXTS || batch_bytes + bytes > DEEPL_MAX_TEXT_BYTES {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        };
+
+        batch_bytes += bytes;
+        batch.push(text);
+    };
+
+    if !batch.is_empty() {
+        batches.push(batch);
+    };
+
+    Ok(batches)
+}
+
+// Spaces become + and bytes other than letters, digits and -_.* become %XX
+fn form_encoded_len(text: &str) -> usize {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' | b' ' => 1,
+            _ => 3,
+        })
+        .sum()
+}
+
+impl Translator for DeepLTranslator {
+    fn name(&self) -> &str {
+        "deepl"
+    }
+
+    fn translate<'a>(&'a self, texts: Vec<String>, source_lang: &'a str, target_lang: &'a str)
+        -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        self.translate_batches(texts, source_lang, target_lang, None).boxed()
+    }
+
+    fn translate_with_glossary<'a>(&'a self, texts: Vec<String>, source_lang: &'a str, target_lang: &'a str,
+        glossary: Vec<(String, String)>) -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        async move {
+            if texts.is_empty() || glossary.is_empty() {
+                return self.translate(texts, source_lang, target_lang).await
+            };
+
+            let glossary_id = self.glossary_id(source_lang, target_lang, &glossary).await?;
+
+            self.translate_batches(texts, source_lang, target_lang, Some(glossary_id)).await
+        }.boxed()
+    }
+}
+
+/// Returns texts unchanged. Used with TRANSLATION_PROVIDER=echo when no translation service is available.
+pub struct EchoTranslator;
+
+impl Translator for EchoTranslator {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    fn translate<'a>(&'a self, texts: Vec<String>, _source_lang: &'a str, _target_lang: &'a str)
+        -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        future::ready(Ok(texts)).boxed()
+    }
+}
+
+/// Used when no provider is configured. Strings are returned unchanged and machine translations
+/// of texts aren't saved, so the other language keeps its own content.
+pub struct DisabledTranslator;
+
+impl Translator for DisabledTranslator {
+    fn name(&self) -> &str {
+        "disabled"
+    }
+
+    fn translate<'a>(&'a self, texts: Vec<String>, _source_lang: &'a str, _target_lang: &'a str)
+        -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        future::ready(Ok(texts)).boxed()
+    }
+
+    fn enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Offline translation from a fixed word list, for tests and local development.
+/// A text found whole in the dictionary is replaced whole, otherwise each word is
+/// looked up on its own and words that aren't found are kept.
+pub struct DictionaryTranslator {
+    // (source lang, lowercase source term) -> target term
+    entries: BTreeMap<(String, String), String>,
+}
+
+impl DictionaryTranslator {
+    /// Pairs of (en, fr) terms. Each pair translates both ways.
+    pub fn new(pairs: Vec<(String, String)>) -> Self {
+        let mut entries = BTreeMap::new();
+
+        for (en, fr) in pairs {
+            entries.insert(("en".to_string(), en.to_lowercase()), fr.clone());
+            entries.insert(("fr".to_string(), fr.to_lowercase()), en);
+        };
+
+        DictionaryTranslator { entries }
+    }
+
+    /// Read a file of tab separated `en<TAB>fr` lines. Blank lines and lines starting with # are skipped.
+    pub fn from_file(path: &str) -> Result<Self, CustomError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| CustomError::new(500, format!("Unable to read translation dictionary {}: {}", path, e)))?;
+
+        let mut pairs = Vec::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            };
+
+            match line.split_once('\t') {
+                Some((en, fr)) => pairs.push((en.trim().to_string(), fr.trim().to_string())),
+                None => return Err(CustomError::new(500, format!(
+                    "Translation dictionary {} line {} is not en<TAB>fr", path, i + 1))),
+            };
+        };
+
+        Ok(DictionaryTranslator::new(pairs))
+    }
+
+    fn lookup(&self, lang: &str, term: &str) -> Option<&String> {
+        if term.is_empty() {
+            return None
+        };
+
+        self.entries.get(&(lang.to_string(), term.to_lowercase()))
+    }
+
+    fn translate_text(&self, text: &str, source_lang: &str) -> String {
+        if let Some(t) = self.lookup(source_lang, text.trim()) {
+            return t.to_owned()
+        };
+
+        let mut translated = String::new();
+        let mut word = String::new();
+
+        for c in text.chars() {
+            if c.is_alphanumeric() || c == '-' || c == '\'' {
+                word.push(c);
+                continue
+            };
+
+            translated.push_str(self.lookup(source_lang, &word).unwrap_or(&word));
+            translated.push(c);
+            word.clear();
+        };
+
+        translated.push_str(self.lookup(source_lang, &word).unwrap_or(&word));
+
+        translated
+    }
+}
+
+impl Translator for DictionaryTranslator {
+    fn name(&self) -> &str {
+        "dictionary"
+    }
+
+    fn translate<'a>(&'a self, texts: Vec<String>, source_lang: &'a str, _target_lang: &'a str)
+        -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        let translated = texts
+            .iter()
+            .map(|t| self.translate_text(t, source_lang))
+            .collect();
+
+        future::ready(Ok(translated)).boxed()
+    }
+
+    fn translate_with_glossary<'a>(&'a self, texts: Vec<String>, source_lang: &'a str, _target_lang: &'a str,
+        glossary: Vec<(String, String)>) -> BoxFuture<'a, Result<Vec<String>, CustomError>> {
+
+        // glossary terms win over the word list
+        let mut with_glossary = DictionaryTranslator { entries: self.entries.clone() };
+
+        for (source, target) in glossary {
+            with_glossary.entries.insert((source_lang.to_string(), source.to_lowercase()), target);
+        };
+
+        let translated = texts
+            .iter()
+            .map(|t| with_glossary.translate_text(t, source_lang))
+            .collect();
+
+        future::ready(Ok(translated)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn dictionary() -> DictionaryTranslator {
+        DictionaryTranslator::new(vec![
+            ("data".to_string(), "données".to_string()),
+            ("open".to_string(), "ouvert".to_string()),
+            ("Open data".to_string(), "Données ouvertes".to_string()),
+        ])
+    }
+
+    fn strings(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn dictionary_replaces_whole_texts() {
+        let translated = block_on(dictionary().translate(strings(&["open data", " Données ouvertes "]), "en", "fr"));
+
+        assert_eq!(translated.unwrap()[0], "Données ouvertes");
+
+        let translated = block_on(dictionary().translate(strings(&["Données ouvertes"]), "fr", "en"));
+
+        assert_eq!(translated.unwrap(), strings(&["Open data"]));
+    }
+
+    #[test]
+    fn dictionary_translates_words_and_keeps_the_rest() {
+        let translated = block_on(dictionary().translate(strings(&["The data is open.", ""]), "en", "fr")).unwrap();
+
+        assert_eq!(translated, strings(&["The données is ouvert.", ""]));
+
+        let translated = block_on(dictionary().translate(strings(&["Les données, ouvert!"]), "fr", "en")).unwrap();
+
+        assert_eq!(translated, strings(&["Les data, open!"]));
+    }
+
+    #[test]
+    fn glossary_terms_win_over_the_dictionary() {
+        let glossary = vec![("data".to_string(), "information".to_string())];
+
+        let translated = block_on(dictionary().translate_with_glossary(
+            strings(&["open data", "data, open"]), "en", "fr", glossary)).unwrap();
+
+        // whole texts in the dictionary still come first
+        assert_eq!(translated, strings(&["Données ouvertes", "information, ouvert"]));
+    }
+
+    #[test]
+    fn dictionary_reads_tab_separated_files() {
+        let path = env::temp_dir().join(format!("translation-dictionary-{}.tsv", std::process::id()));
+
+        fs::write(&path, "# en\tfr\n\nsection\tsection\nreport\t rapport\n").unwrap();
+
+        let translator = DictionaryTranslator::from_file(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(translator.translate_text("Report", "en"), "rapport");
+        assert_eq!(translator.translate_text("rapport", "fr"), "report");
+
+        fs::write(&path, "report rapport\n").unwrap();
+
+        let error = DictionaryTranslator::from_file(path.to_str().unwrap()).err().unwrap();
+
+        assert!(error.error_message.contains("line 1"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn deepl_requests_stay_within_limits() {
+        let texts: Vec<String> = (0..120).map(|i| format!("Section {}", i)).collect();
+
+        let batches = deepl_batches(texts.clone()).unwrap();
+
+        assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<usize>>(), vec![50, 50, 20]);
+        assert_eq!(batches.concat(), texts);
+
+        // accented letters take 6 bytes once encoded
+        let long = vec!["é".repeat(9_000); 5];
+
+        let batches = deepl_batches(long).unwrap();
+
+        assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<usize>>(), vec![2, 2, 1]);
+        assert!(batches.iter().all(|b| b.iter().map(|t| 6 + form_encoded_len(t)).sum::<usize>() <= DEEPL_MAX_TEXT_BYTES));
+
+        assert!(deepl_batches(vec!["a".repeat(DEEPL_MAX_TEXT_BYTES)]).is_err());
+        assert!(deepl_batches(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn runs_without_a_provider() {
+        for translator in [from_settings(None, None, None).unwrap(), from_settings(Some("".to_string()), Some("".to_string()), None).unwrap()] {
+            assert_eq!(translator.name(), "disabled");
+            assert!(!translator.enabled());
+            assert_eq!(block_on(translator.translate(strings(&["data"]), "en", "fr")).unwrap(), strings(&["data"]));
+        };
+    }
+
+    #[test]
+    fn echo_is_only_used_when_asked_for() {
+        let echo = from_settings(Some("Echo".to_string()), None, None).unwrap();
+
+        assert_eq!(echo.name(), "echo");
+        assert!(echo.enabled());
+        assert_eq!(block_on(echo.translate(strings(&["data"]), "en", "fr")).unwrap(), strings(&["data"]));
+    }
+
+    #[test]
+    fn provider_defaults_to_deepl_with_a_key() {
+        let translator = from_settings(None, Some("key:fx".to_string()), None).unwrap();
+
+        assert_eq!(translator.name(), "deepl");
+
+        assert!(from_settings(Some("deepl".to_string()), None, None).is_err());
+        assert!(from_settings(Some("dictionary".to_string()), Some("key".to_string()), None).is_err());
+        assert!(from_settings(Some("google".to_string()), Some("key".to_string()), None).is_err());
+    }
+}
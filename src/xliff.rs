@@ -0,0 +1,624 @@
+// XLIFF 2.0 export and import of document texts for human translation in CAT tools.
+// Each Text is a unit. Redaction markup is sent as protected paired codes so the
+// translator can translate the redacted words but can't move or drop the markers.
+// On import the markup is rebuilt from the source revision, never from the file.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use quick_xml::Reader;
+use quick_xml::events::{Event, BytesStart};
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::run_rake;
+use crate::errors::CustomError;
+use crate::models::{Document, ReadableDocument, Text, TextVersion, Job, QaIssue};
+
+const XLIFF_NAMESPACE: &str = "urn:oasis:names:tc:xliff:document:2.0";
+
+// Note carrying the revision of the source text a unit was exported from
+const SOURCE_VERSION_NOTE: &str = "source-version";
+
+// Note carrying the revision of the target text when the unit was exported, 0 if it had none
+const TARGET_VERSION_NOTE: &str = "target-version";
+
+lazy_static! {
+    // same markers as process_text_redactions
+    static ref REDACTION: Regex = Regex::new(r"(?s)~~(?P<text>.*?)~~\[(?P<act>[^\]]*)\]").unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A text of a document ready to go out for translation
+pub struct XliffUnit {
+    pub text_id: Uuid,
+    pub name: String,
+    pub source: String,
+    pub source_version: i32,
+    pub target_version: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// Translated text, or an inline code by the id of the source markup it stands for
+pub enum TargetPart {
+    Text(String),
+    Code(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A unit read back from a translated file. Its codes are resolved against the source revision on import.
+pub struct ParsedXliffUnit {
+    pub id: String,
+    pub source_version: Option<i32>,
+    pub target_version: Option<i32>,
+    pub target: Option<Vec<TargetPart>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ParsedXliff {
+    pub src_lang: String,
+    pub trg_lang: String,
+    pub units: Vec<ParsedXliffUnit>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Outcome of importing a translated file into a document
+pub struct XliffImportReport {
+    pub document_id: Uuid,
+    pub trg_lang: String,
+    pub imported: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub skipped: Vec<String>,
+    // not saved because the document is published and redactions would differ between languages
+    pub blocked: Vec<String>,
+    // not saved because the target text was edited after the file was exported
+    pub conflicted: Vec<String>,
+}
+
+/// The document with its title, purpose and section texts in lang, in reading order,
+/// and the revision each text is at in trg_lang
+pub fn document_units(document_id: Uuid, lang: &str, trg_lang: &str, clearance: &str) -> Result<(ReadableDocument, Vec<XliffUnit>), CustomError> {
+
+    let (document, sections) = Document::get_all_readable_by_id(document_id, lang, false, false, clearance)?;
+
+    let mut names = vec![
+        (document.title_text_id, "title".to_string()),
+        (document.purpose_text_id, "purpose".to_string()),
+    ];
+
+    let mut ordered_sections = BTreeMap::new();
+
+    for (_id, s) in sections {
+        ordered_sections.insert(s.order_number, s);
+    };
+
+    for (_order, s) in ordered_sections {
+        names.push((s.text_id, s.header_text));
+    };
+
+    let heads = TextVersion::get_heads(names.iter().map(|(id, _)| *id).collect(), lang)?;
+    let target_heads = TextVersion::get_heads(names.iter().map(|(id, _)| *id).collect(), trg_lang)?;
+
+    let mut units = Vec::new();
+
+    for (text_id, name) in names {
+        let head = match heads.get(&text_id) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        units.push(XliffUnit {
+            text_id,
+            name,
+            source: head.decrypt()?,
+            source_version: head.version_number,
+            target_version: target_heads.get(&text_id).map_or(0, |h| h.version_number),
+        });
+    };
+
+    Ok((document, units))
+}
+
+/// Write units as an XLIFF 2.0 file from src_lang into trg_lang
+pub fn write_xliff(document_id: Uuid, src_lang: &str, trg_lang: &str, units: &[XliffUnit]) -> String {
+
+    let mut xml = String::new();
+
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<xliff xmlns=\"{}\" version=\"2.0\" srcLang=\"{}\" trgLang=\"{}\">\n",
+        XLIFF_NAMESPACE, escape_xml(src_lang), escape_xml(trg_lang)));
+    xml.push_str(&format!("  <file id=\"f{}\" original=\"document/{}\">\n", document_id, document_id));
+
+    for unit in units {
+        let (source, codes) = inline_codes(&unit.source);
+
+        xml.push_str(&format!("    <unit id=\"{}\" name=\"{}\">\n", unit.text_id, escape_xml(&unit.name)));
+        xml.push_str(&format!(
+            "      <notes>\n        <note category=\"{}\">{}</note>\n        <note category=\"{}\">{}</note>\n      </notes>\n",
+            SOURCE_VERSION_NOTE, unit.source_version, TARGET_VERSION_NOTE, unit.target_version));
+
+        if !codes.is_empty() {
+            xml.push_str("      <originalData>\n");
+
+            for (id, data) in codes {
+                xml.push_str(&format!("        <data id=\"{}\">{}</data>\n", id, escape_xml(&data)));
+            };
+
+            xml.push_str("      </originalData>\n");
+        };
+
+        xml.push_str("      <segment>\n");
+        xml.push_str(&format!("        <source>{}</source>\n", source));
+        xml.push_str("      </segment>\n");
+        xml.push_str("    </unit>\n");
+    };
+
+    xml.push_str("  </file>\n");
+    xml.push_str("</xliff>\n");
+
+    xml
+}
+
+/// Read the languages and translated units of an XLIFF 2.0 file
+pub fn parse_xliff(buf: &[u8]) -> Result<ParsedXliff, CustomError> {
+
+    let mut reader = Reader::from_reader(buf);
+    reader.trim_text(false);
+
+    let mut parsed = ParsedXliff {
+        src_lang: String::new(),
+        trg_lang: String::new(),
+        units: Vec::new(),
+    };
+
+    let mut unit: Option<ParsedXliffUnit> = None;
+
+    // element whose text is being collected: a note or a target
+    let mut capturing: Option<String> = None;
+    let mut text = String::new();
+    let mut parts: Vec<TargetPart> = Vec::new();
+    let mut note_category = String::new();
+
+    // ends of the paired codes open in the target.
+    // The originalData in the file is ignored so markup can't be changed through it.
+    let mut open_codes: Vec<String> = Vec::new();
+
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader.read_event(&mut buf)
+            .map_err(|e| CustomError::new(400, format!("Unable to read XLIFF file at {}: {}", reader.buffer_position(), e)))?;
+
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let empty = matches!(event, Event::Empty(_));
+
+                match (e.local_name(), capturing.as_deref()) {
+                    (b"xliff", _) => {
+                        parsed.src_lang = attribute(e, b"srcLang").unwrap_or_default();
+                        parsed.trg_lang = attribute(e, b"trgLang").unwrap_or_default();
+                    },
+                    (b"unit", _) => {
+                        unit = Some(ParsedXliffUnit {
+                            id: attribute(e, b"id").unwrap_or_default(),
+                            source_version: None,
+                            target_version: None,
+                            target: None,
+                        });
+                    },
+                    (b"note", None) if !empty => {
+                        note_category = attribute(e, b"category").unwrap_or_default();
+                        capturing = Some("note".to_string());
+                        text.clear();
+                    },
+                    (b"target", None) if !empty => {
+                        capturing = Some("target".to_string());
+                        parts.clear();
+                        open_codes.clear();
+                    },
+                    (b"pc", Some("target")) => {
+                        parts.push(TargetPart::Code(attribute(e, b"dataRefStart").unwrap_or_default()));
+
+                        let end = attribute(e, b"dataRefEnd").unwrap_or_default();
+
+                        match empty {
+                            true => parts.push(TargetPart::Code(end)),
+                            false => open_codes.push(end),
+                        };
+                    },
+                    (b"ph", Some("target")) | (b"sc", Some("target")) | (b"ec", Some("target")) => {
+                        parts.push(TargetPart::Code(attribute(e, b"dataRef").unwrap_or_default()));
+                    },
+                    _ => (),
+                };
+            },
+            Event::Text(ref e) | Event::CData(ref e) => {
+                if capturing.is_some() {
+                    let t = match event {
+                        Event::CData(_) => String::from_utf8_lossy(e).to_string(),
+                        _ => e.unescape_and_decode(&reader)
+                            .map_err(|err| CustomError::new(400, format!("Unable to read XLIFF text: {}", err)))?,
+                    };
+
+                    match capturing.as_deref() {
+                        Some("target") => parts.push(TargetPart::Text(t)),
+                        _ => text.push_str(&t),
+                    };
+                };
+            },
+            Event::End(ref e) => {
+                match (e.local_name(), capturing.as_deref()) {
+                    (b"note", Some("note")) => {
+                        if let Some(u) = unit.as_mut() {
+                            match note_category.as_str() {
+                                SOURCE_VERSION_NOTE => u.source_version = text.trim().parse::<i32>().ok(),
+                                TARGET_VERSION_NOTE => u.target_version = text.trim().parse::<i32>().ok(),
+                                _ => (),
+                            };
+                        };
+                        capturing = None;
+                    },
+                    (b"pc", Some("target")) => {
+                        parts.push(TargetPart::Code(open_codes.pop().unwrap_or_default()));
+                    },
+                    (b"target", Some("target")) => {
+                        // a unit split into several segments is joined back together
+                        if let Some(u) = unit.as_mut() {
+                            u.target.get_or_insert_with(Vec::new).append(&mut parts);
+                        };
+                        capturing = None;
+                    },
+                    (b"unit", _) => {
+                        if let Some(u) = unit.take() {
+                            parsed.units.push(u);
+                        };
+                    },
+                    _ => (),
+                };
+            },
+            Event::Eof => break,
+            _ => (),
+        };
+
+        buf.clear();
+    };
+
+    if parsed.src_lang.is_empty() || parsed.trg_lang.is_empty() {
+        return Err(CustomError::new(400, "Not an XLIFF 2.0 file: srcLang and trgLang are required".to_string()))
+    };
+
+    Ok(parsed)
+}
+
+/// Save the translated units of a file as new revisions of the document's texts in the target language.
+/// Each revision is recorded as a human translation of the source revision named in the file.
+/// Units for other documents, untranslated units and unchanged translations are skipped.
+/// Nothing is saved if a unit's codes don't match the redactions of its source revision.
+/// Units that would leave a redaction in one language of a published document are reported as blocked.
+/// Units whose target text has been edited since the export are reported as conflicted and keep the edit.
+pub fn import_document_xliff(
+    document_id: Uuid,
+    parsed: &ParsedXliff,
+    user_id: Uuid,
+    clearance: &str,
+) -> Result<XliffImportReport, CustomError> {
+
+    let src_lang = short_lang(&parsed.src_lang);
+    let trg_lang = short_lang(&parsed.trg_lang);
+
+    if !["en", "fr"].contains(&src_lang.as_str()) || !["en", "fr"].contains(&trg_lang.as_str()) || src_lang == trg_lang {
+        return Err(CustomError::new(400, format!(
+            "Unable to import a translation from {} into {}", parsed.src_lang, parsed.trg_lang)))
+    };
+
+    // fails without reading anything if the document is above clearance
+    let names: BTreeMap<Uuid, String> = document_units(document_id, &src_lang, &trg_lang, clearance)?
+        .1
+        .into_iter()
+        .map(|u| (u.text_id, u.name))
+        .collect();
+
+    let mut report = XliffImportReport {
+        document_id,
+        trg_lang: trg_lang.clone(),
+        imported: Vec::new(),
+        unchanged: Vec::new(),
+        skipped: Vec::new(),
+        blocked: Vec::new(),
+        conflicted: Vec::new(),
+    };
+
+    // every unit is checked before any is saved
+    let mut translations = Vec::new();
+
+    for unit in &parsed.units {
+        let (text_id, name) = match Uuid::parse_str(&unit.id).ok().and_then(|id| names.get(&id).map(|n| (id, n))) {
+            Some(u) => u,
+            None => {
+                report.skipped.push(unit.id.clone());
+                continue
+            },
+        };
+
+        let parts = match &unit.target {
+            Some(p) if !is_untranslated(p) => p,
+            _ => {
+                report.skipped.push(name.clone());
+                continue
+            },
+        };
+
+        let source = match unit.source_version {
+            Some(v) => TextVersion::get_all(text_id, &src_lang)?
+                .into_iter()
+                .find(|s| s.version_number == v)
+                .ok_or_else(|| CustomError::new(400, format!("{}: revision {} of the source text not found", name, v)))?,
+            None => TextVersion::get_head(text_id, &src_lang)?,
+        };
+
+        let content = resolve_target(parts, &source.decrypt()?)
+            .map_err(|e| CustomError::new(400, format!("{}: {}", name, e)))?
+            .trim()
+            .to_string();
+
+        let current = TextVersion::get_head(text_id, &trg_lang).ok();
+
+        if let Some(c) = &current {
+            if c.decrypt()? == content {
+                report.unchanged.push(name.clone());
+                continue
+            };
+        };
+
+        // files exported before target versions were recorded aren't checked
+        if let Some(base) = unit.target_version {
+            if current.map_or(0, |c| c.version_number) != base {
+                report.conflicted.push(name.clone());
+                continue
+            };
+        };
+
+        match QaIssue::check_save(text_id, &trg_lang, &content) {
+            Ok(()) => (),
+            Err(err) if err.is_qa_blocked() => {
+                report.blocked.push(name.clone());
+                continue
+            },
+            Err(err) => return Err(err),
+        };
+
+        translations.push((text_id, name, content, source.version_number, unit.target_version));
+    };
+
+    for (text_id, name, content, source_version, target_version) in translations {
+        let mut text = Text::get_text_by_id(text_id, &src_lang)?;
+
+        text.lang = trg_lang.clone();
+
+        if text.section_id != None {
+            text.keywords = Some(run_rake(&content, &trg_lang)?);
+        };
+
+        // an edit saved since the check above is kept too
+        match Text::update_or_create(&text, &content, false, Some(source_version), user_id, target_version) {
+            Ok(_) => report.imported.push(name.clone()),
+            Err(err) if err.is_edit_conflict() => report.conflicted.push(name.clone()),
+            Err(err) => return Err(err),
+        };
+    };
+
+    if !report.imported.is_empty() {
+        Job::enqueue_document_analysis(document_id, &trg_lang)?;
+    };
+
+    Ok(report)
+}
+
+/// File name for a downloaded translation file
+pub fn xliff_file_name(title: &str, src_lang: &str, trg_lang: &str) -> String {
+    format!("{}_{}-{}.xlf", crate::docx::docx_file_name(title), src_lang, trg_lang)
+}
+
+// Escape content and replace redactions with protected paired codes.
+// Returns the segment XML and the original data of each code.
+fn inline_codes(content: &str) -> (String, Vec<(String, String)>) {
+
+    let mut xml = String::new();
+    let mut codes = Vec::new();
+    let mut last = 0;
+
+    for (i, caps) in REDACTION.captures_iter(content).enumerate() {
+        let whole = caps.get(0).unwrap();
+        let n = i + 1;
+
+        xml.push_str(&escape_xml(&content[last..whole.start()]));
+
+        xml.push_str(&format!(
+            "<pc id=\"{n}\" dataRefStart=\"d{n}s\" dataRefEnd=\"d{n}e\" canCopy=\"no\" canDelete=\"no\" canReorder=\"firstNo\">{}</pc>",
+            escape_xml(caps.name("text").map_or("", |m| m.as_str())),
+            n = n,
+        ));
+
+        codes.push((format!("d{}s", n), "~~".to_string()));
+        codes.push((format!("d{}e", n), format!("~~[{}]", caps.name("act").map_or("", |m| m.as_str()))));
+
+        last = whole.end();
+    };
+
+    xml.push_str(&escape_xml(&content[last..]));
+
+    (xml, codes)
+}
+
+// The target with its codes replaced by the redaction markup of the source revision.
+// Every code of the source must be used once, a start before its end, so redactions
+// can't be dropped, repeated or made up.
+fn resolve_target(parts: &[TargetPart], source: &str) -> Result<String, String> {
+    let codes: BTreeMap<String, String> = inline_codes(source).1.into_iter().collect();
+
+    let mut used = BTreeSet::new();
+    let mut content = String::new();
+
+    for part in parts {
+        let id = match part {
+            TargetPart::Text(t) => {
+                content.push_str(t);
+                continue
+            },
+            TargetPart::Code(id) => id,
+        };
+
+        let data = codes.get(id).ok_or_else(|| format!("code {:?} is not in the source text", id))?;
+
+        if !used.insert(id.to_owned()) {
+            return Err(format!("code {} is used more than once", id))
+        };
+
+        // ids are d1s and d1e for the start and end of the first redaction
+        if let Some(n) = id.strip_suffix('e') {
+            if !used.contains(&format!("{}s", n)) {
+                return Err(format!("code {} ends a redaction before it starts", id))
+            };
+        };
+
+        content.push_str(data);
+    };
+
+    let redactions = codes.len() / 2;
+
+    if used.len() != codes.len() || REDACTION.find_iter(&content).count() != redactions {
+        return Err(format!("the source text has {} redactions and the translation doesn't keep them all", redactions))
+    };
+
+    Ok(content)
+}
+
+// A target with no text and no codes
+fn is_untranslated(parts: &[TargetPart]) -> bool {
+    parts.iter().all(|p| matches!(p, TargetPart::Text(t) if t.trim().is_empty()))
+}
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .filter_map(|a| a.ok())
+        .find(|a| a.key == name)
+        .and_then(|a| a.unescaped_value().ok().map(|v| String::from_utf8_lossy(&v).to_string()))
+}
+
+// en-CA -> en, FR -> fr
+fn short_lang(lang: &str) -> String {
+    lang.split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "Spending on ~~new offices~~[s.21(1)(a)] rose & ~~staff~~[s.19] too.";
+
+    // a file exported from SOURCE with the target written by the translator
+    fn translated_file(target: &str) -> Vec<u8> {
+        let unit = XliffUnit {
+            text_id: Uuid::nil(),
+            name: "Summary".to_string(),
+            source: SOURCE.to_string(),
+            source_version: 3,
+            target_version: 2,
+        };
+
+        write_xliff(Uuid::nil(), "en", "fr", &[unit])
+            .replace("</source>", &format!("</source>\n        <target>{}</target>", target))
+            .into_bytes()
+    }
+
+    fn resolve(file: &[u8]) -> Result<String, String> {
+        let parsed = parse_xliff(file).unwrap();
+
+        resolve_target(parsed.units[0].target.as_ref().unwrap(), SOURCE)
+    }
+
+    #[test]
+    fn export_protects_redactions() {
+        let (xml, codes) = inline_codes(SOURCE);
+
+        assert!(xml.contains("<pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\" canCopy=\"no\" canDelete=\"no\" canReorder=\"firstNo\">new offices</pc>"));
+        assert!(xml.contains(" rose &amp; "));
+        assert_eq!(codes.len(), 4);
+        assert_eq!(codes[1], ("d1e".to_string(), "~~[s.21(1)(a)]".to_string()));
+    }
+
+    #[test]
+    fn redactions_round_trip() {
+        let (source, _) = inline_codes(SOURCE);
+
+        let parsed = parse_xliff(&translated_file(&source)).unwrap();
+
+        assert_eq!(parsed.src_lang, "en");
+        assert_eq!(parsed.trg_lang, "fr");
+        assert_eq!(parsed.units[0].source_version, Some(3));
+        assert_eq!(parsed.units[0].target_version, Some(2));
+        assert_eq!(resolve_target(parsed.units[0].target.as_ref().unwrap(), SOURCE).unwrap(), SOURCE);
+
+        let translated = resolve(&translated_file(
+            "Les dépenses pour <pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\">les nouveaux bureaux</pc> et \
+             <sc id=\"2\" dataRef=\"d2s\"/>le personnel<ec startRef=\"2\" dataRef=\"d2e\"/> ont augmenté."));
+
+        assert_eq!(translated.unwrap(),
+            "Les dépenses pour ~~les nouveaux bureaux~~[s.21(1)(a)] et ~~le personnel~~[s.19] ont augmenté.");
+    }
+
+    #[test]
+    fn markup_comes_from_the_source_not_the_file() {
+        let (source, _) = inline_codes(SOURCE);
+
+        let forged = String::from_utf8(translated_file(&source)).unwrap()
+            .replace("~~[s.21(1)(a)]", "~~[none]");
+
+        assert_eq!(resolve(forged.as_bytes()).unwrap(), SOURCE);
+    }
+
+    #[test]
+    fn dropped_or_extra_redactions_are_rejected() {
+        // second redaction left out
+        assert!(resolve(&translated_file(
+            "Les <pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\">bureaux</pc> et le personnel.")).is_err());
+
+        // first redaction repeated
+        assert!(resolve(&translated_file(
+            "<pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\">a</pc> <pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\">b</pc> \
+             <pc id=\"2\" dataRefStart=\"d2s\" dataRefEnd=\"d2e\">c</pc>")).is_err());
+
+        // a code the source doesn't have
+        assert!(resolve(&translated_file(
+            "<pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\">a</pc> <pc id=\"2\" dataRefStart=\"d2s\" dataRefEnd=\"d2e\">b</pc> \
+             <ph id=\"3\" dataRef=\"d3s\"/>")).is_err());
+
+        // end before start
+        assert!(resolve(&translated_file(
+            "<ec dataRef=\"d1e\"/>a<sc dataRef=\"d1s\"/> <pc id=\"2\" dataRefStart=\"d2s\" dataRefEnd=\"d2e\">b</pc>")).is_err());
+
+        // a redaction typed into the text
+        assert!(resolve(&translated_file(
+            "<pc id=\"1\" dataRefStart=\"d1s\" dataRefEnd=\"d1e\">a</pc> <pc id=\"2\" dataRefStart=\"d2s\" dataRefEnd=\"d2e\">b</pc> \
+             ~~c~~[s.20]")).is_err());
+    }
+
+    #[test]
+    fn empty_targets_are_untranslated() {
+        let parsed = parse_xliff(&translated_file("  ")).unwrap();
+
+        assert!(is_untranslated(parsed.units[0].target.as_ref().unwrap()));
+        assert!(!is_untranslated(&[TargetPart::Code("d1s".to_string())]));
+    }
+}